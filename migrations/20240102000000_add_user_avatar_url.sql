@@ -0,0 +1,2 @@
+-- Add profile picture URL to users
+ALTER TABLE users ADD COLUMN IF NOT EXISTS avatar_url VARCHAR(2048);
@@ -0,0 +1,2 @@
+-- Soft account deletion
+ALTER TABLE users ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMPTZ;
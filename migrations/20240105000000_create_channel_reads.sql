@@ -0,0 +1,9 @@
+-- Per-user read position in each channel (for read receipts)
+CREATE TABLE IF NOT EXISTS channel_reads (
+    channel_id UUID NOT NULL REFERENCES channels(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    last_read_message_id UUID NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+    last_read_message_at TIMESTAMPTZ NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (channel_id, user_id)
+);
@@ -0,0 +1,3 @@
+-- Optional channel description and topic
+ALTER TABLE channels ADD COLUMN IF NOT EXISTS description TEXT;
+ALTER TABLE channels ADD COLUMN IF NOT EXISTS topic VARCHAR(250);
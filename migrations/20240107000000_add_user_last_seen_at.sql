@@ -0,0 +1,2 @@
+-- Last time a user had a live WebSocket connection
+ALTER TABLE users ADD COLUMN IF NOT EXISTS last_seen_at TIMESTAMPTZ;
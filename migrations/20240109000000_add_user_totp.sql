@@ -0,0 +1,3 @@
+-- Opt-in TOTP two-factor authentication (secret stored encrypted)
+ALTER TABLE users ADD COLUMN IF NOT EXISTS totp_secret_encrypted TEXT;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS totp_enabled BOOLEAN NOT NULL DEFAULT false;
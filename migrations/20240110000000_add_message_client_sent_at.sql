@@ -0,0 +1,2 @@
+-- Client-reported send time, kept alongside the authoritative server created_at
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS client_sent_at TIMESTAMPTZ;
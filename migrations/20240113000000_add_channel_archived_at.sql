@@ -0,0 +1,2 @@
+-- Archived channels are hidden from listings and read-only
+ALTER TABLE channels ADD COLUMN IF NOT EXISTS archived_at TIMESTAMPTZ;
@@ -0,0 +1,2 @@
+-- When the invitee last acknowledged a pending invitation, independent of its status
+ALTER TABLE invitations ADD COLUMN IF NOT EXISTS seen_at TIMESTAMPTZ;
@@ -0,0 +1,4 @@
+-- Per-channel message retention; NULL falls back to MESSAGE_RETENTION_DAYS
+ALTER TABLE channels ADD COLUMN IF NOT EXISTS retention_days INTEGER;
+
+CREATE INDEX IF NOT EXISTS idx_messages_channel_created_at ON messages(channel_id, created_at);
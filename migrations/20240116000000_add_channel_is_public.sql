@@ -0,0 +1,2 @@
+-- Public channels are listed to everyone and readable by guests when GUEST_READ_ACCESS is on
+ALTER TABLE channels ADD COLUMN IF NOT EXISTS is_public BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,2 @@
+-- Per-channel member cap; NULL falls back to MAX_CHANNEL_MEMBERS
+ALTER TABLE channels ADD COLUMN IF NOT EXISTS max_members INTEGER;
@@ -0,0 +1,2 @@
+-- Bumped on every edit so concurrent edits can detect each other
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS version INTEGER NOT NULL DEFAULT 1;
@@ -0,0 +1,2 @@
+-- 'user' for chat, 'system' for persisted events like renames and joins
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS kind VARCHAR(20) NOT NULL DEFAULT 'user';
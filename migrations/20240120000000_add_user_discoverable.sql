@@ -0,0 +1,2 @@
+-- users who opt out are left out of GET /api/users/search
+ALTER TABLE users ADD COLUMN IF NOT EXISTS discoverable BOOLEAN NOT NULL DEFAULT TRUE;
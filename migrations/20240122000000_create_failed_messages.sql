@@ -0,0 +1,13 @@
+-- Chat messages that could not be stored after retrying, kept for manual replay.
+-- No foreign keys: a missing channel or user may be the very reason the insert failed.
+CREATE TABLE IF NOT EXISTS failed_messages (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    channel_id UUID NOT NULL,
+    user_id UUID NOT NULL,
+    content TEXT NOT NULL,
+    attachments JSONB NOT NULL DEFAULT '[]'::jsonb,
+    client_sent_at TIMESTAMPTZ,
+    error TEXT NOT NULL,
+    attempts INTEGER NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
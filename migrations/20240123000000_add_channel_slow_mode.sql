@@ -0,0 +1,2 @@
+-- Minimum seconds between two messages from the same member; NULL means no slow mode
+ALTER TABLE channels ADD COLUMN IF NOT EXISTS slow_mode_seconds INTEGER;
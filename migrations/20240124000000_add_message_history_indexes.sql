@@ -0,0 +1,15 @@
+-- Membership checks already use the channel_members primary key (channel_id, user_id),
+-- and retention sweeps use idx_messages_channel_created_at.
+
+-- History pages filter out deleted messages and sort on (created_at, id) for the keyset
+-- cursor, so they can walk this index without a sort step
+CREATE INDEX IF NOT EXISTS idx_messages_channel_history
+    ON messages(channel_id, created_at, id)
+    WHERE deleted_at IS NULL;
+
+-- A member's latest message in a channel, read on every send when slow mode is on
+CREATE INDEX IF NOT EXISTS idx_messages_channel_user_created_at
+    ON messages(channel_id, user_id, created_at);
+
+-- Covered by both indexes above
+DROP INDEX IF EXISTS idx_messages_channel_id;
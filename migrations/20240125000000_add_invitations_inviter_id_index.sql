@@ -0,0 +1,2 @@
+-- Sent invitations are listed and summarised by inviter
+CREATE INDEX IF NOT EXISTS idx_invitations_inviter_id ON invitations(inviter_id);
@@ -0,0 +1,4 @@
+-- Keep these in step with the limits in src/utils/validation.rs
+COMMENT ON COLUMN users.username IS 'At most 50 characters, MAX_USERNAME_LENGTH';
+COMMENT ON COLUMN users.email IS 'At most 255 characters, MAX_EMAIL_LENGTH';
+COMMENT ON COLUMN users.password_hash IS 'bcrypt or argon2 PHC string; passwords are capped at 72 bytes, MAX_PASSWORD_LENGTH';
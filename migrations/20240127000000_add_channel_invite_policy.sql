@@ -0,0 +1,2 @@
+-- Who may invite: 'admins_only' or 'members' (admins and members, never viewers)
+ALTER TABLE channels ADD COLUMN IF NOT EXISTS invite_policy VARCHAR(20) NOT NULL DEFAULT 'admins_only';
@@ -0,0 +1,10 @@
+-- Messages pinned to the top of a channel, listed newest pin first
+CREATE TABLE IF NOT EXISTS pinned_messages (
+    message_id UUID PRIMARY KEY REFERENCES messages(id) ON DELETE CASCADE,
+    channel_id UUID NOT NULL REFERENCES channels(id) ON DELETE CASCADE,
+    pinned_by UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    pinned_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_pinned_messages_channel_pinned_at
+    ON pinned_messages(channel_id, pinned_at DESC);
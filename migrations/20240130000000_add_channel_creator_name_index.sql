@@ -0,0 +1,4 @@
+-- Backs the per-creator name check behind UNIQUE_CHANNEL_NAMES. Not a unique index so the
+-- check can be switched off and existing duplicates don't block the migration.
+CREATE INDEX IF NOT EXISTS idx_channels_created_by_lower_name
+    ON channels(created_by, lower(name));
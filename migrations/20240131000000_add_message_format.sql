@@ -0,0 +1,2 @@
+-- Rendering hint from the sender: 'plain' or 'markdown'
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS format VARCHAR(20) NOT NULL DEFAULT 'plain';
@@ -0,0 +1,2 @@
+-- Keeps the rendering hint so a replayed message comes back as it was sent
+ALTER TABLE failed_messages ADD COLUMN IF NOT EXISTS format VARCHAR(20) NOT NULL DEFAULT 'plain';
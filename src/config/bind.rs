@@ -0,0 +1,47 @@
+use std::{env, path::PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BindTarget {
+    Tcp(String),
+    Uds(PathBuf),
+}
+
+/// Picks a Unix domain socket when `uds` is set and non-empty, otherwise `host:port`.
+pub fn select_bind_target(uds: Option<&str>, host: &str, port: &str) -> BindTarget {
+    match uds.map(str::trim).filter(|path| !path.is_empty()) {
+        Some(path) => BindTarget::Uds(PathBuf::from(path)),
+        None => BindTarget::Tcp(format!("{}:{}", host, port)),
+    }
+}
+
+/// Reads `BIND_UDS`, falling back to `HOST` and `PORT`.
+pub fn bind_target() -> BindTarget {
+    let uds = env::var("BIND_UDS").ok();
+    let host = env::var("HOST").unwrap_or_else(|_| "localhost".to_string());
+    let port = env::var("PORT").unwrap_or_else(|_| "8080".to_string());
+
+    select_bind_target(uds.as_deref(), &host, &port)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uds_path_wins_over_tcp() {
+        assert_eq!(
+            select_bind_target(Some("/run/chat.sock"), "localhost", "8080"),
+            BindTarget::Uds(PathBuf::from("/run/chat.sock"))
+        );
+    }
+
+    #[test]
+    fn missing_or_blank_uds_falls_back_to_tcp() {
+        for uds in [None, Some(""), Some("  ")] {
+            assert_eq!(
+                select_bind_target(uds, "0.0.0.0", "9000"),
+                BindTarget::Tcp("0.0.0.0:9000".to_string())
+            );
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use actix_cors::Cors;
+use actix_web::http::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE};
+use std::env;
+
+/// Splits a comma-separated origin list, dropping blanks and trailing slashes.
+pub fn parse_allowed_origins(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|origin| origin.trim().trim_end_matches('/'))
+        .filter(|origin| !origin.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Reads `CORS_ALLOWED_ORIGINS`; an empty list means any origin is allowed.
+pub fn allowed_origins() -> Vec<String> {
+    env::var("CORS_ALLOWED_ORIGINS")
+        .map(|value| parse_allowed_origins(&value))
+        .unwrap_or_default()
+}
+
+pub fn build_cors(allowed_origins: &[String]) -> Cors {
+    let cors = Cors::default()
+        .allow_any_method()
+        .allowed_headers(vec![AUTHORIZATION, ACCEPT])
+        .allowed_header(CONTENT_TYPE)
+        .max_age(3600);
+
+    if allowed_origins.is_empty() {
+        return cors.allow_any_origin().send_wildcard();
+    }
+
+    allowed_origins
+        .iter()
+        .fold(cors, |cors, origin| cors.allowed_origin(origin))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn origins_are_trimmed_and_blanks_dropped() {
+        assert_eq!(
+            parse_allowed_origins(" https://app.example.com/ ,,http://localhost:3000"),
+            ["https://app.example.com", "http://localhost:3000"]
+        );
+        assert!(parse_allowed_origins("").is_empty());
+        assert!(parse_allowed_origins(" , ").is_empty());
+    }
+}
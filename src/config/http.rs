@@ -0,0 +1,111 @@
+use actix_web::http::KeepAlive;
+use std::{env, thread, time::Duration};
+
+const MAX_WORKERS: usize = 256;
+const DEFAULT_KEEP_ALIVE_SECS: u64 = 5;
+const MAX_KEEP_ALIVE_SECS: u64 = 3600;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HttpSettings {
+    pub workers: usize,
+    /// `None` turns keep-alive off.
+    pub keep_alive: Option<Duration>,
+}
+
+impl HttpSettings {
+    pub fn keep_alive(&self) -> KeepAlive {
+        match self.keep_alive {
+            Some(timeout) => KeepAlive::Timeout(timeout),
+            None => KeepAlive::Disabled,
+        }
+    }
+}
+
+/// Worker threads, `1`–`256`; unset falls back to `default`.
+pub fn parse_workers(value: Option<&str>, default: usize) -> Result<usize, String> {
+    let Some(value) = value.map(str::trim).filter(|value| !value.is_empty()) else {
+        return Ok(default);
+    };
+
+    value
+        .parse::<usize>()
+        .ok()
+        .filter(|workers| (1..=MAX_WORKERS).contains(workers))
+        .ok_or_else(|| format!("HTTP_WORKERS must be between 1 and {}", MAX_WORKERS))
+}
+
+/// Keep-alive in seconds, up to an hour; `0` disables it and unset means 5 seconds.
+pub fn parse_keep_alive(value: Option<&str>) -> Result<Option<Duration>, String> {
+    let Some(value) = value.map(str::trim).filter(|value| !value.is_empty()) else {
+        return Ok(Some(Duration::from_secs(DEFAULT_KEEP_ALIVE_SECS)));
+    };
+
+    let secs = value
+        .parse::<u64>()
+        .ok()
+        .filter(|secs| *secs <= MAX_KEEP_ALIVE_SECS)
+        .ok_or_else(|| {
+            format!(
+                "HTTP_KEEP_ALIVE_SECS must be between 0 and {}",
+                MAX_KEEP_ALIVE_SECS
+            )
+        })?;
+
+    Ok((secs > 0).then(|| Duration::from_secs(secs)))
+}
+
+/// Reads `HTTP_WORKERS` and `HTTP_KEEP_ALIVE_SECS`, warning about and ignoring invalid values.
+///
+/// Workers default to the available parallelism, like actix itself.
+pub fn http_settings() -> HttpSettings {
+    let default_workers = thread::available_parallelism().map_or(1, |n| n.get());
+
+    let workers = parse_workers(env::var("HTTP_WORKERS").ok().as_deref(), default_workers)
+        .unwrap_or_else(|e| {
+            log::warn!("{}, using {}", e, default_workers);
+            default_workers
+        });
+
+    let keep_alive = parse_keep_alive(env::var("HTTP_KEEP_ALIVE_SECS").ok().as_deref())
+        .unwrap_or_else(|e| {
+            log::warn!("{}, using {}s", e, DEFAULT_KEEP_ALIVE_SECS);
+            Some(Duration::from_secs(DEFAULT_KEEP_ALIVE_SECS))
+        });
+
+    HttpSettings {
+        workers,
+        keep_alive,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn workers_default_when_unset_and_stay_in_range() {
+        assert_eq!(parse_workers(None, 4), Ok(4));
+        assert_eq!(parse_workers(Some(" "), 4), Ok(4));
+        assert_eq!(parse_workers(Some("1"), 4), Ok(1));
+        assert_eq!(parse_workers(Some(" 256 "), 4), Ok(MAX_WORKERS));
+        for value in ["0", "257", "-1", "many"] {
+            assert!(parse_workers(Some(value), 4).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn keep_alive_zero_disables_it() {
+        assert_eq!(
+            parse_keep_alive(None),
+            Ok(Some(Duration::from_secs(DEFAULT_KEEP_ALIVE_SECS)))
+        );
+        assert_eq!(parse_keep_alive(Some("0")), Ok(None));
+        assert_eq!(
+            parse_keep_alive(Some("3600")),
+            Ok(Some(Duration::from_secs(3600)))
+        );
+        for value in ["3601", "-5", "5s"] {
+            assert!(parse_keep_alive(Some(value)).is_err(), "{}", value);
+        }
+    }
+}
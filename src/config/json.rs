@@ -0,0 +1,75 @@
+use crate::models::error::ErrorResponse;
+use actix_web::{error::JsonPayloadError, web, HttpResponse};
+use std::env;
+
+const DEFAULT_JSON_BODY_LIMIT: usize = 64 * 1024;
+
+/// Reads `JSON_BODY_LIMIT` in bytes, falling back to 64 KiB when unset or invalid.
+pub fn json_body_limit() -> usize {
+    env::var("JSON_BODY_LIMIT")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .filter(|limit| *limit > 0)
+        .unwrap_or(DEFAULT_JSON_BODY_LIMIT)
+}
+
+/// Maps a rejected JSON body to a status code and an `ErrorResponse` body.
+pub fn json_error_response(err: &JsonPayloadError) -> HttpResponse {
+    match err {
+        JsonPayloadError::OverflowKnownLength { limit, .. }
+        | JsonPayloadError::Overflow { limit } => HttpResponse::PayloadTooLarge().json(
+            ErrorResponse::new(format!("Request body exceeds the {} byte limit", limit)),
+        ),
+        JsonPayloadError::ContentType => HttpResponse::UnsupportedMediaType()
+            .json(ErrorResponse::new("Content-Type must be application/json")),
+        JsonPayloadError::Deserialize(e) => {
+            HttpResponse::BadRequest().json(ErrorResponse::new(format!("Invalid JSON: {}", e)))
+        }
+        _ => HttpResponse::BadRequest().json(ErrorResponse::new("Invalid request body")),
+    }
+}
+
+pub fn json_config(limit: usize) -> web::JsonConfig {
+    web::JsonConfig::default()
+        .limit(limit)
+        .error_handler(|err, _req| {
+            let response = json_error_response(&err);
+            actix_web::error::InternalError::from_response(err, response).into()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{app, create_user, start_server};
+    use actix_web::{http::StatusCode, test};
+    use serde_json::{json, Value};
+    use sqlx::PgPool;
+
+    #[sqlx::test]
+    async fn oversized_or_malformed_bodies_get_a_structured_error(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+
+        let req = test::TestRequest::post()
+            .uri("/api/channels")
+            .insert_header(alice.bearer())
+            .set_json(json!({ "name": "big", "description": "x".repeat(128 * 1024) }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["error"], "Request body exceeds the 65536 byte limit");
+
+        let req = test::TestRequest::post()
+            .uri("/api/channels")
+            .insert_header(alice.bearer())
+            .insert_header(("Content-Type", "application/json"))
+            .set_payload("{\"name\":")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body: Value = test::read_body_json(resp).await;
+        assert!(body["error"].as_str().unwrap().starts_with("Invalid JSON"));
+    }
+}
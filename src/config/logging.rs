@@ -0,0 +1,66 @@
+use chrono::Utc;
+use env_logger::{Builder, Env};
+use std::{env, io::Write};
+
+/// Access log format; the default one plus the caller's `X-Request-Id`, `-` when absent.
+///
+/// `client_ip` is filled in by `config::proxy::client_ip`, so it honours `TRUSTED_PROXIES`.
+pub const ACCESS_LOG_FORMAT: &str =
+    r#"%{client_ip}xi "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T request_id=%{X-Request-Id}i"#;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+/// `json` (any case) selects JSON lines; anything else, including unset, keeps plain text.
+pub fn parse_log_format(value: Option<&str>) -> LogFormat {
+    match value.map(str::trim) {
+        Some(value) if value.eq_ignore_ascii_case("json") => LogFormat::Json,
+        _ => LogFormat::Text,
+    }
+}
+
+/// Reads `LOG_FORMAT`.
+pub fn log_format() -> LogFormat {
+    parse_log_format(env::var("LOG_FORMAT").ok().as_deref())
+}
+
+/// Installs the global logger, filtered by `RUST_LOG` (default `info`).
+pub fn init_logger() {
+    let mut builder = Builder::from_env(Env::default().default_filter_or("info"));
+
+    if log_format() == LogFormat::Json {
+        builder.format(|buf, record| {
+            let line = serde_json::json!({
+                "timestamp": Utc::now().to_rfc3339(),
+                "level": record.level().as_str(),
+                "target": record.target(),
+                "message": record.args().to_string(),
+            });
+            writeln!(buf, "{}", line)
+        });
+    }
+
+    builder.init();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_is_selected_in_any_case() {
+        for value in ["json", "JSON", " Json "] {
+            assert_eq!(parse_log_format(Some(value)), LogFormat::Json);
+        }
+    }
+
+    #[test]
+    fn anything_else_keeps_text() {
+        for value in [None, Some(""), Some("text"), Some("jsonl")] {
+            assert_eq!(parse_log_format(value), LogFormat::Text);
+        }
+    }
+}
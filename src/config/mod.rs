@@ -0,0 +1,56 @@
+pub mod bind;
+pub mod cors;
+pub mod http;
+pub mod json;
+pub mod logging;
+pub mod proxy;
+
+use std::{env, time::Duration};
+
+const DEFAULT_SHUTDOWN_DRAIN_SECS: u64 = 10;
+
+/// True for `APP_ENV` `development`/`dev`/`test`, and when it's unset so local setups keep working.
+pub fn is_development() -> bool {
+    matches!(
+        env::var("APP_ENV").as_deref(),
+        Err(_) | Ok("development") | Ok("dev") | Ok("test")
+    )
+}
+
+/// When `REQUIRE_EMAIL_VERIFICATION` is `true`, unverified users can't create channels.
+pub fn require_email_verification() -> bool {
+    env::var("REQUIRE_EMAIL_VERIFICATION")
+        .map(|value| value == "true")
+        .unwrap_or(false)
+}
+
+/// When `PERSIST_SYSTEM_MESSAGES` is `true`, renames and joins are stored as `system` messages.
+pub fn persist_system_messages() -> bool {
+    env::var("PERSIST_SYSTEM_MESSAGES")
+        .map(|value| value == "true")
+        .unwrap_or(false)
+}
+
+/// When `GUEST_READ_ACCESS` is `true`, public channels and their messages can be read without a token.
+pub fn guest_read_access() -> bool {
+    env::var("GUEST_READ_ACCESS")
+        .map(|value| value == "true")
+        .unwrap_or(false)
+}
+
+/// When `UNIQUE_CHANNEL_NAMES` is `true`, a user can't create two channels with the same name,
+/// compared case-insensitively. Different creators may still share a name.
+pub fn unique_channel_names() -> bool {
+    env::var("UNIQUE_CHANNEL_NAMES")
+        .map(|value| value == "true")
+        .unwrap_or(false)
+}
+
+/// How long shutdown waits for in-flight message inserts, from `SHUTDOWN_DRAIN_TIMEOUT_SECS`.
+pub fn shutdown_drain_timeout() -> Duration {
+    let secs = env::var("SHUTDOWN_DRAIN_TIMEOUT_SECS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_SHUTDOWN_DRAIN_SECS);
+    Duration::from_secs(secs)
+}
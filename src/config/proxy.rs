@@ -0,0 +1,268 @@
+use actix_web::{
+    http::header::{HeaderName, FORWARDED, X_FORWARDED_FOR},
+    web, HttpRequest,
+};
+use std::{env, net::IpAddr, net::SocketAddr};
+
+/// An address range in CIDR notation; a bare address is a single-host range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn parse(value: &str) -> Option<Self> {
+        let (addr, prefix) = match value.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (value, None),
+        };
+
+        let network: IpAddr = addr.trim().parse().ok()?;
+        let max = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.trim().parse::<u8>().ok().filter(|p| *p <= max)?,
+            None => max,
+        };
+
+        Some(Self { network, prefix })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        // an IPv4 peer on a dual-stack socket shows up as ::ffff:a.b.c.d
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+            ip => ip,
+        };
+
+        match (self.network, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Proxies whose `Forwarded`/`X-Forwarded-For` headers are believed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrustedProxies {
+    ranges: Vec<Cidr>,
+}
+
+impl TrustedProxies {
+    pub fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.ranges.iter().any(|range| range.contains(ip))
+    }
+
+    /// The client address for a request that arrived from `peer`.
+    ///
+    /// Forwarding headers are only read when `peer` is trusted. Hops are walked from the
+    /// nearest one back, and the first untrusted address is the client, so a spoofed
+    /// entry prepended by the client itself is never picked.
+    pub fn resolve(&self, peer: IpAddr, forwarded_for: &[Option<IpAddr>]) -> IpAddr {
+        if !self.is_trusted(peer) {
+            return peer;
+        }
+
+        let mut client = peer;
+        for hop in forwarded_for.iter().rev() {
+            let Some(hop) = *hop else {
+                break;
+            };
+            client = hop;
+            if !self.is_trusted(hop) {
+                break;
+            }
+        }
+        client
+    }
+
+    /// The real client IP of `req`, or `None` when there is no peer address (Unix sockets).
+    pub fn client_ip(&self, req: &HttpRequest) -> Option<IpAddr> {
+        let peer = req.peer_addr()?.ip();
+        if !self.is_trusted(peer) {
+            return Some(peer);
+        }
+
+        // `Forwarded` wins when a proxy sends both
+        let hops = if req.headers().contains_key(FORWARDED) {
+            forwarded_hops(req, FORWARDED, parse_forwarded_element)
+        } else {
+            forwarded_hops(req, X_FORWARDED_FOR, parse_node)
+        };
+
+        Some(self.resolve(peer, &hops))
+    }
+}
+
+/// Every comma-separated entry across all instances of `header`, in order.
+fn forwarded_hops(
+    req: &HttpRequest,
+    header: HeaderName,
+    parse: fn(&str) -> Option<IpAddr>,
+) -> Vec<Option<IpAddr>> {
+    req.headers()
+        .get_all(header)
+        .flat_map(|value| value.to_str().unwrap_or_default().split(','))
+        .map(|entry| parse(entry.trim()))
+        .collect()
+}
+
+/// Reads the `for=` parameter of one `Forwarded` element, e.g. `for=192.0.2.60;proto=https`.
+fn parse_forwarded_element(element: &str) -> Option<IpAddr> {
+    element
+        .split(';')
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| key.eq_ignore_ascii_case("for"))
+        .and_then(|(_, node)| parse_node(node.trim_matches('"')))
+}
+
+/// Parses `1.2.3.4`, `1.2.3.4:80`, `2001:db8::1`, `[2001:db8::1]` or `[2001:db8::1]:80`.
+///
+/// Obfuscated identifiers such as `unknown` or `_hidden` yield `None`.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    node.parse::<IpAddr>()
+        .ok()
+        .or_else(|| node.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+        .or_else(|| {
+            node.strip_prefix('[')
+                .and_then(|node| node.strip_suffix(']'))
+                .and_then(|node| node.parse().ok())
+        })
+}
+
+/// Comma-separated CIDRs or addresses, plus the entries that didn't parse.
+pub fn parse_trusted_proxies(value: Option<&str>) -> (TrustedProxies, Vec<String>) {
+    let mut ranges = Vec::new();
+    let mut invalid = Vec::new();
+
+    for entry in value
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+    {
+        match Cidr::parse(entry) {
+            Some(range) => ranges.push(range),
+            None => invalid.push(entry.to_string()),
+        }
+    }
+
+    (TrustedProxies { ranges }, invalid)
+}
+
+/// Reads `TRUSTED_PROXIES`, warning about and skipping entries that don't parse.
+///
+/// Unset trusts nobody, so the peer address is always the client.
+pub fn trusted_proxies() -> TrustedProxies {
+    let (proxies, invalid) = parse_trusted_proxies(env::var("TRUSTED_PROXIES").ok().as_deref());
+
+    for entry in invalid {
+        log::warn!("Ignoring invalid TRUSTED_PROXIES entry '{}'", entry);
+    }
+
+    proxies
+}
+
+/// The real client IP of `req` under the app's `TrustedProxies`, or its peer address.
+pub fn client_ip(req: &HttpRequest) -> Option<IpAddr> {
+    match req.app_data::<web::Data<TrustedProxies>>() {
+        Some(proxies) => proxies.client_ip(req),
+        None => req.peer_addr().map(|addr| addr.ip()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    fn proxies(value: &str) -> TrustedProxies {
+        let (proxies, invalid) = parse_trusted_proxies(Some(value));
+        assert!(invalid.is_empty(), "{:?}", invalid);
+        proxies
+    }
+
+    #[test]
+    fn cidr_ranges_match_by_prefix() {
+        let range = Cidr::parse("10.0.0.0/8").unwrap();
+        assert!(range.contains(ip("10.20.30.40")));
+        assert!(range.contains(ip("::ffff:10.0.0.1")));
+        assert!(!range.contains(ip("11.0.0.1")));
+
+        let host = Cidr::parse("2001:db8::1").unwrap();
+        assert!(host.contains(ip("2001:db8::1")));
+        assert!(!host.contains(ip("2001:db8::2")));
+        assert!(Cidr::parse("0.0.0.0/0")
+            .unwrap()
+            .contains(ip("203.0.113.9")));
+
+        for invalid in ["10.0.0.0/33", "::/129", "proxy.local", "10.0.0.0/x"] {
+            assert_eq!(Cidr::parse(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn invalid_entries_are_reported_and_skipped() {
+        let (proxies, invalid) = parse_trusted_proxies(Some("10.0.0.1, nonsense, ,192.168.0.0/16"));
+        assert_eq!(invalid, ["nonsense"]);
+        assert!(proxies.is_trusted(ip("192.168.4.4")));
+        assert!(!proxies.is_trusted(ip("172.16.0.1")));
+    }
+
+    #[test]
+    fn without_trusted_proxies_the_peer_is_the_client() {
+        let req = TestRequest::default()
+            .peer_addr("10.0.0.1:4000".parse().unwrap())
+            .insert_header((X_FORWARDED_FOR, "203.0.113.9"))
+            .to_http_request();
+        assert_eq!(
+            TrustedProxies::default().client_ip(&req),
+            Some(ip("10.0.0.1"))
+        );
+        // nor are the headers read from a peer outside the trusted ranges
+        assert_eq!(
+            proxies("192.168.0.0/16").client_ip(&req),
+            Some(ip("10.0.0.1"))
+        );
+    }
+
+    #[test]
+    fn trusted_proxies_are_skipped_back_to_the_first_untrusted_hop() {
+        let proxies = proxies("10.0.0.0/8");
+        let req = TestRequest::default()
+            .peer_addr("10.0.0.1:4000".parse().unwrap())
+            .insert_header((X_FORWARDED_FOR, "198.51.100.7, 203.0.113.9, 10.0.0.2"))
+            .to_http_request();
+        // 198.51.100.7 could have been written by the client itself
+        assert_eq!(proxies.client_ip(&req), Some(ip("203.0.113.9")));
+
+        let req = TestRequest::default()
+            .peer_addr("10.0.0.1:4000".parse().unwrap())
+            .insert_header((X_FORWARDED_FOR, "198.51.100.7"))
+            .insert_header((FORWARDED, r#"for="[2001:db8::5]:443";proto=https"#))
+            .to_http_request();
+        assert_eq!(proxies.client_ip(&req), Some(ip("2001:db8::5")));
+
+        // an obfuscated hop stops the walk at the last address known
+        let req = TestRequest::default()
+            .peer_addr("10.0.0.1:4000".parse().unwrap())
+            .insert_header((FORWARDED, "for=unknown, for=10.0.0.3"))
+            .to_http_request();
+        assert_eq!(proxies.client_ip(&req), Some(ip("10.0.0.3")));
+    }
+}
@@ -0,0 +1,99 @@
+use sqlx::{Postgres, Transaction};
+use std::env;
+use uuid::Uuid;
+
+use crate::models::role::Role;
+
+/// Reads `MAX_CHANNEL_MEMBERS`, the cap for channels without their own `max_members`.
+pub fn default_max_members() -> Option<i32> {
+    env::var("MAX_CHANNEL_MEMBERS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .filter(|members| *members > 0)
+}
+
+/// What `add_member` did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddMemberOutcome {
+    Added,
+    /// The user was already a member; their role is left as it was.
+    AlreadyMember,
+    /// The channel is at its cap.
+    Full,
+}
+
+/// Adds a member unless they already are one or the channel is at its cap.
+///
+/// Locks the channel row so concurrent joins can't both take the last seat, or both
+/// insert the same user.
+pub async fn add_member(
+    tx: &mut Transaction<'_, Postgres>,
+    channel_id: Uuid,
+    user_id: Uuid,
+    role: Role,
+) -> Result<AddMemberOutcome, sqlx::Error> {
+    let cap = sqlx::query_scalar::<_, Option<i32>>(
+        r#"
+        SELECT COALESCE(max_members, $2) FROM channels
+        WHERE id = $1
+        FOR UPDATE
+        "#,
+    )
+    .bind(channel_id)
+    .bind(default_max_members())
+    .fetch_one(&mut **tx)
+    .await?;
+
+    let is_member = sqlx::query_scalar::<_, bool>(
+        r#"
+        SELECT EXISTS(
+            SELECT 1 FROM channel_members
+            WHERE channel_id = $1 AND user_id = $2
+        )
+        "#,
+    )
+    .bind(channel_id)
+    .bind(user_id)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    if is_member {
+        return Ok(AddMemberOutcome::AlreadyMember);
+    }
+
+    if let Some(cap) = cap {
+        let members = sqlx::query_scalar::<_, i64>(
+            r#"
+            SELECT COUNT(*) FROM channel_members
+            WHERE channel_id = $1
+            "#,
+        )
+        .bind(channel_id)
+        .fetch_one(&mut **tx)
+        .await?;
+
+        if members >= i64::from(cap) {
+            return Ok(AddMemberOutcome::Full);
+        }
+    }
+
+    // the channel lock already rules out a duplicate, this keeps it from ever being a 500
+    let inserted = sqlx::query(
+        r#"
+        INSERT INTO channel_members (channel_id, user_id, role)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (channel_id, user_id) DO NOTHING
+        "#,
+    )
+    .bind(channel_id)
+    .bind(user_id)
+    .bind(role)
+    .execute(&mut **tx)
+    .await?;
+
+    if inserted.rows_affected() == 0 {
+        return Ok(AddMemberOutcome::AlreadyMember);
+    }
+
+    Ok(AddMemberOutcome::Added)
+}
@@ -1 +1,4 @@
+pub mod membership;
 pub mod pool;
+pub mod retention;
+pub mod transaction;
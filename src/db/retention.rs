@@ -0,0 +1,119 @@
+use sqlx::PgPool;
+use std::{env, time::Duration};
+
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Reads `MESSAGE_RETENTION_DAYS`, the window for channels without their own `retention_days`.
+pub fn default_retention_days() -> Option<i32> {
+    env::var("MESSAGE_RETENTION_DAYS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .filter(|days| *days > 0)
+}
+
+/// Deletes messages older than their channel's retention window, returning how many went.
+///
+/// Channels with neither a `retention_days` nor a default keep everything, and pinned
+/// messages are kept until they're unpinned.
+pub async fn prune_expired_messages(
+    pool: &PgPool,
+    default_days: Option<i32>,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM messages m
+        USING channels c
+        WHERE m.channel_id = c.id
+            AND COALESCE(c.retention_days, $1) IS NOT NULL
+            AND m.created_at < NOW() - make_interval(days => COALESCE(c.retention_days, $1))
+            AND NOT EXISTS (SELECT 1 FROM pinned_messages p WHERE p.message_id = m.id)
+        "#,
+    )
+    .bind(default_days)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Prunes expired messages every hour. Nothing is broadcast, clients just stop seeing them.
+pub async fn run_retention_task(pool: PgPool) {
+    let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        match prune_expired_messages(&pool, default_retention_days()).await {
+            Ok(0) => {}
+            Ok(pruned) => log::info!("Pruned {} expired messages", pruned),
+            Err(e) => log::error!("Failed to prune expired messages: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{create_channel, create_user, insert_message, TestUser};
+    use uuid::Uuid;
+
+    async fn insert_aged(pool: &PgPool, channel_id: Uuid, user: &TestUser, days: i32) -> Uuid {
+        let id = insert_message(pool, channel_id, user, "old news").await;
+        sqlx::query(
+            "UPDATE messages SET created_at = NOW() - make_interval(days => $1) WHERE id = $2",
+        )
+        .bind(days)
+        .bind(id)
+        .execute(pool)
+        .await
+        .unwrap();
+        id
+    }
+
+    async fn remaining(pool: &PgPool) -> Vec<Uuid> {
+        sqlx::query_scalar::<_, Uuid>("SELECT id FROM messages ORDER BY id")
+            .fetch_all(pool)
+            .await
+            .unwrap()
+    }
+
+    #[sqlx::test]
+    async fn pruning_respects_each_window_and_spares_pins(pool: PgPool) {
+        let alice = create_user(&pool, "alice").await;
+        let short = create_channel(&pool, &alice, "short").await;
+        let unset = create_channel(&pool, &alice, "unset").await;
+        sqlx::query("UPDATE channels SET retention_days = 7 WHERE id = $1")
+            .bind(short)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        // past the channel's window
+        insert_aged(&pool, short, &alice, 10).await;
+        let recent = insert_aged(&pool, short, &alice, 3).await;
+        let pinned = insert_aged(&pool, short, &alice, 10).await;
+        sqlx::query(
+            "INSERT INTO pinned_messages (message_id, channel_id, pinned_by) VALUES ($1, $2, $3)",
+        )
+        .bind(pinned)
+        .bind(short)
+        .bind(alice.id)
+        .execute(&pool)
+        .await
+        .unwrap();
+        let old_elsewhere = insert_aged(&pool, unset, &alice, 10).await;
+
+        // without a default, the channel with no window keeps everything
+        assert_eq!(prune_expired_messages(&pool, None).await.unwrap(), 1);
+        let mut expected = vec![recent, pinned, old_elsewhere];
+        expected.sort();
+        assert_eq!(remaining(&pool).await, expected);
+
+        // the default applies only where the channel has no window of its own
+        assert_eq!(prune_expired_messages(&pool, Some(30)).await.unwrap(), 0);
+        assert_eq!(prune_expired_messages(&pool, Some(5)).await.unwrap(), 1);
+        let mut expected = vec![recent, pinned];
+        expected.sort();
+        assert_eq!(remaining(&pool).await, expected);
+    }
+}
@@ -0,0 +1,110 @@
+use sqlx::{PgPool, Postgres, Transaction};
+
+pub type PgTransaction = Transaction<'static, Postgres>;
+
+/// Starts a transaction for a handler, failing with a 500 like any other database error.
+///
+/// A transaction dropped without `commit` is rolled back, so returning early with `?`
+/// halfway through a multi-statement change never leaves the first half behind.
+pub async fn begin(pool: &PgPool) -> Result<PgTransaction, actix_web::Error> {
+    pool.begin()
+        .await
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))
+}
+
+pub async fn commit(tx: PgTransaction) -> Result<(), actix_web::Error> {
+    tx.commit()
+        .await
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{app, create_channel, create_user, start_server};
+    use actix_web::{http::StatusCode, test};
+    use serde_json::{json, Value};
+    use sqlx::PgPool;
+
+    /// Makes every insert into `channel_members` fail from now on.
+    async fn break_member_inserts(pool: &PgPool) {
+        sqlx::query(
+            r#"
+            CREATE FUNCTION refuse_member() RETURNS trigger AS $$
+            BEGIN
+                RAISE EXCEPTION 'simulated failure';
+            END;
+            $$ LANGUAGE plpgsql
+            "#,
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            "CREATE TRIGGER refuse_member BEFORE INSERT ON channel_members \
+             FOR EACH ROW EXECUTE FUNCTION refuse_member()",
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    #[sqlx::test]
+    async fn failing_to_add_the_creator_leaves_no_channel(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        break_member_inserts(&pool).await;
+
+        let req = test::TestRequest::post()
+            .uri("/api/channels")
+            .insert_header(alice.bearer())
+            .set_json(json!({ "name": "general" }))
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+
+        let channels: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM channels")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(channels, 0);
+    }
+
+    #[sqlx::test]
+    async fn failing_to_add_the_invitee_keeps_the_invitation_pending(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/api/channels/{}/invite", channel_id))
+            .insert_header(alice.bearer())
+            .set_json(json!({ "email": "bob@example.com" }))
+            .to_request();
+        let invitation: Value = test::call_and_read_body_json(&app, req).await;
+        break_member_inserts(&pool).await;
+
+        let req = test::TestRequest::post()
+            .uri(&format!(
+                "/api/invitations/{}/respond",
+                invitation["id"].as_str().unwrap()
+            ))
+            .insert_header(bob.bearer())
+            .set_json(json!({ "accept": true }))
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+
+        let status: String = sqlx::query_scalar("SELECT status FROM invitations")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(status, "pending");
+    }
+}
@@ -1,45 +1,92 @@
 use crate::{
-    models::user::{AuthResponse, LoginRequest, RegisterRequest, User},
-    utils::jwt::create_jwt,
+    config::is_development,
+    db::transaction,
+    handlers::websocket::ChatServerHandle,
+    middleware::auth::AuthUser,
+    models::{
+        error::ErrorResponse,
+        user::{
+            AuthResponse, LoginRequest, RegisterRequest, SendVerificationResponse,
+            TotpSetupResponse, TotpVerifyRequest, User, VerifyEmailQuery,
+        },
+    },
+    utils::{
+        jwt::create_jwt,
+        password::{hash_password, needs_rehash, verify_password},
+        totp::{self, TotpError},
+        validation::{validate_email, validate_password, validate_username},
+    },
 };
 use actix_web::{web, HttpResponse};
-use bcrypt::{hash, verify, DEFAULT_COST};
+use chrono::{DateTime, Duration, Utc};
 use sqlx::PgPool;
-use std::env;
+use uuid::Uuid;
+
+const VERIFICATION_TOKEN_TTL_HOURS: i64 = 24;
+
+fn totp_error(e: TotpError) -> actix_web::Error {
+    match e {
+        TotpError::NotConfigured => {
+            log::error!("{}", e);
+            actix_web::error::ErrorInternalServerError(
+                "Two-factor authentication is not configured",
+            )
+        }
+        _ => actix_web::error::ErrorInternalServerError("Two-factor authentication error"),
+    }
+}
 
 pub async fn register(
     pool: web::Data<PgPool>,
     req: web::Json<RegisterRequest>,
 ) -> Result<HttpResponse, actix_web::Error> {
+    if let Err(reason) = validate_username(&req.username) {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse::for_field("username", reason)));
+    }
+
+    if let Err(reason) = validate_email(&req.email) {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse::for_field("email", reason)));
+    }
+
+    if let Err(reason) = validate_password(&req.password) {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse::for_field("password", reason)));
+    }
+
     // hash password
-    let password_hash = hash(&req.password, DEFAULT_COST)
+    let password_hash = hash_password(&req.password)
         .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to hash password"))?;
 
-    let user = sqlx::query_as::<_, User>(
+    let inserted = sqlx::query_as::<_, User>(
         r#"
         INSERT INTO users (username, email, password_hash)
         VALUES ($1, $2, $3)
-        RETURNING id, username, email, password_hash, created_at
+        RETURNING id, username, email, password_hash, avatar_url, created_at, token_version
         "#,
     )
     .bind(&req.username)
     .bind(&req.email)
     .bind(&password_hash)
     .fetch_one(pool.get_ref())
-    .await
-    .map_err(|e| match e {
-        sqlx::Error::Database(db_err) => {
-            if db_err.constraint().is_some() {
-                actix_web::error::ErrorConflict("Username or email already exists")
-            } else {
-                actix_web::error::ErrorInternalServerError("Database error")
-            }
+    .await;
+
+    let user = match inserted {
+        Ok(user) => user,
+        Err(sqlx::Error::Database(db_err)) if db_err.constraint().is_some() => {
+            let body = match db_err.constraint() {
+                Some("users_username_key") => {
+                    ErrorResponse::for_field("username", "Username already exists")
+                }
+                Some("users_email_key") => {
+                    ErrorResponse::for_field("email", "Email already exists")
+                }
+                _ => ErrorResponse::new("Username or email already exists"),
+            };
+            return Ok(HttpResponse::Conflict().json(body));
         }
-        _ => actix_web::error::ErrorInternalServerError("Database error"),
-    })?;
+        Err(_) => return Err(actix_web::error::ErrorInternalServerError("Database error")),
+    };
 
-    let secret = env::var("JWT_SECRET").unwrap_or_else(|_| "secret".to_string());
-    let token = create_jwt(user.id, &req.username, &secret)
+    let token = create_jwt(user.id, &user.username, user.token_version)
         .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to create token"))?;
 
     Ok(HttpResponse::Created().json(AuthResponse {
@@ -54,9 +101,9 @@ pub async fn login(
 ) -> Result<HttpResponse, actix_web::Error> {
     let user = sqlx::query_as::<_, User>(
         r#"
-        SELECT id, username, email, password_hash, created_at
+        SELECT id, username, email, password_hash, avatar_url, created_at, token_version
         FROM users
-        WHERE email = $1
+        WHERE email = $1 AND deleted_at IS NULL
         "#,
     )
     .bind(&req.email)
@@ -65,15 +112,305 @@ pub async fn login(
     .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?
     .ok_or_else(|| actix_web::error::ErrorUnauthorized("Invalid credentials"))?;
 
-    let valid = verify(&req.password, &user.password_hash)
+    let valid = verify_password(&req.password, &user.password_hash)
         .map_err(|_| actix_web::error::ErrorInternalServerError("Password verification failed"))?;
 
     if !valid {
         return Err(actix_web::error::ErrorUnauthorized("Invalid credentials"));
     }
 
-    let secret = env::var("JWT_SECRET").unwrap_or_else(|_| "secret".to_string());
-    let token = create_jwt(user.id, &user.username, &secret)
+    let totp_secret = sqlx::query_scalar::<_, Option<String>>(
+        r#"
+        SELECT totp_secret_encrypted FROM users
+        WHERE id = $1 AND totp_enabled
+        "#,
+    )
+    .bind(user.id)
+    .fetch_optional(pool.get_ref())
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?
+    .flatten();
+
+    if let Some(stored) = totp_secret {
+        let Some(code) = req.totp_code.as_deref() else {
+            return Ok(HttpResponse::Unauthorized().json(ErrorResponse::for_field(
+                "totp_code",
+                "Two-factor code required",
+            )));
+        };
+
+        let secret = totp::decrypt_secret(&stored).map_err(totp_error)?;
+        let totp = totp::build_totp(secret, &user.email).map_err(totp_error)?;
+
+        if !totp::verify_code(&totp, code) {
+            return Err(actix_web::error::ErrorUnauthorized(
+                "Invalid two-factor code",
+            ));
+        }
+    }
+
+    // upgrade hashes created with a different cost while we have the plaintext
+    if needs_rehash(&user.password_hash) {
+        match hash_password(&req.password) {
+            Ok(new_hash) => {
+                if let Err(e) = sqlx::query(
+                    r#"
+                    UPDATE users
+                    SET password_hash = $1
+                    WHERE id = $2
+                    "#,
+                )
+                .bind(&new_hash)
+                .bind(user.id)
+                .execute(pool.get_ref())
+                .await
+                {
+                    log::warn!("Failed to rehash password for user {}: {}", user.id, e);
+                }
+            }
+            Err(e) => log::warn!("Failed to rehash password for user {}: {}", user.id, e),
+        }
+    }
+
+    let token = create_jwt(user.id, &user.username, user.token_version)
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to create token"))?;
+
+    Ok(HttpResponse::Ok().json(AuthResponse {
+        token,
+        user: user.into(),
+    }))
+}
+
+pub async fn setup_totp(
+    pool: web::Data<PgPool>,
+    user: AuthUser,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user.id;
+
+    let (email, enabled) = sqlx::query_as::<_, (String, bool)>(
+        r#"
+        SELECT email, totp_enabled FROM users
+        WHERE id = $1 AND deleted_at IS NULL
+        "#,
+    )
+    .bind(user_id)
+    .fetch_optional(pool.get_ref())
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?
+    .ok_or_else(|| actix_web::error::ErrorNotFound("User not found"))?;
+
+    if enabled {
+        return Err(actix_web::error::ErrorConflict(
+            "Two-factor authentication is already enabled",
+        ));
+    }
+
+    let secret = totp::generate_secret().map_err(totp_error)?;
+    let encrypted = totp::encrypt_secret(&secret).map_err(totp_error)?;
+    let totp = totp::build_totp(secret, &email).map_err(totp_error)?;
+
+    // stays disabled until the user proves they can produce a code
+    sqlx::query(
+        r#"
+        UPDATE users
+        SET totp_secret_encrypted = $1
+        WHERE id = $2
+        "#,
+    )
+    .bind(&encrypted)
+    .bind(user_id)
+    .execute(pool.get_ref())
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+
+    Ok(HttpResponse::Ok().json(TotpSetupResponse {
+        secret: totp.get_secret_base32(),
+        otpauth_url: totp.get_url(),
+    }))
+}
+
+pub async fn verify_totp(
+    pool: web::Data<PgPool>,
+    user: AuthUser,
+    body: web::Json<TotpVerifyRequest>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user.id;
+
+    let (email, stored) = sqlx::query_as::<_, (String, Option<String>)>(
+        r#"
+        SELECT email, totp_secret_encrypted FROM users
+        WHERE id = $1 AND deleted_at IS NULL
+        "#,
+    )
+    .bind(user_id)
+    .fetch_optional(pool.get_ref())
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?
+    .ok_or_else(|| actix_web::error::ErrorNotFound("User not found"))?;
+
+    let stored = stored.ok_or_else(|| {
+        actix_web::error::ErrorBadRequest("Two-factor setup has not been started")
+    })?;
+
+    let secret = totp::decrypt_secret(&stored).map_err(totp_error)?;
+    let totp = totp::build_totp(secret, &email).map_err(totp_error)?;
+
+    if !totp::verify_code(&totp, &body.code) {
+        return Err(actix_web::error::ErrorBadRequest("Invalid two-factor code"));
+    }
+
+    sqlx::query(
+        r#"
+        UPDATE users
+        SET totp_enabled = true
+        WHERE id = $1
+        "#,
+    )
+    .bind(user_id)
+    .execute(pool.get_ref())
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn send_verification(
+    pool: web::Data<PgPool>,
+    user: AuthUser,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user.id;
+
+    let verified = sqlx::query_scalar::<_, bool>(
+        r#"
+        SELECT email_verified FROM users
+        WHERE id = $1 AND deleted_at IS NULL
+        "#,
+    )
+    .bind(user_id)
+    .fetch_optional(pool.get_ref())
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?
+    .ok_or_else(|| actix_web::error::ErrorNotFound("User not found"))?;
+
+    if verified {
+        return Err(actix_web::error::ErrorConflict("Email is already verified"));
+    }
+
+    let token = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+    let expires_at = Utc::now() + Duration::hours(VERIFICATION_TOKEN_TTL_HOURS);
+
+    let mut tx = transaction::begin(pool.get_ref()).await?;
+
+    // only the most recent link stays valid
+    sqlx::query(
+        r#"
+        DELETE FROM email_verifications
+        WHERE user_id = $1
+        "#,
+    )
+    .bind(user_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO email_verifications (token, user_id, expires_at)
+        VALUES ($1, $2, $3)
+        "#,
+    )
+    .bind(&token)
+    .bind(user_id)
+    .bind(expires_at)
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to create verification"))?;
+
+    transaction::commit(tx).await?;
+
+    let token = if is_development() {
+        Some(token)
+    } else {
+        // no mailer is wired up yet, so the token is never exposed outside development
+        log::warn!(
+            "No mailer configured, verification email for {} not sent",
+            user_id
+        );
+        None
+    };
+
+    Ok(HttpResponse::Accepted().json(SendVerificationResponse { expires_at, token }))
+}
+
+pub async fn verify_email(
+    pool: web::Data<PgPool>,
+    query: web::Query<VerifyEmailQuery>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut tx = transaction::begin(pool.get_ref()).await?;
+
+    // tokens are single-use, consumed whether or not they have expired
+    let (user_id, expires_at) = sqlx::query_as::<_, (Uuid, DateTime<Utc>)>(
+        r#"
+        DELETE FROM email_verifications
+        WHERE token = $1
+        RETURNING user_id, expires_at
+        "#,
+    )
+    .bind(&query.token)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?
+    .ok_or_else(|| actix_web::error::ErrorBadRequest("Invalid verification token"))?;
+
+    if expires_at < Utc::now() {
+        transaction::commit(tx).await?;
+        return Err(actix_web::error::ErrorBadRequest(
+            "Verification token has expired",
+        ));
+    }
+
+    sqlx::query(
+        r#"
+        UPDATE users
+        SET email_verified = true
+        WHERE id = $1
+        "#,
+    )
+    .bind(user_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to verify email"))?;
+
+    transaction::commit(tx).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Invalidates every token issued to the user so far, e.g. after a leaked token, and
+/// returns a fresh one so the calling session stays signed in.
+pub async fn revoke_all(
+    pool: web::Data<PgPool>,
+    server: web::Data<ChatServerHandle>,
+    user: AuthUser,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user = sqlx::query_as::<_, User>(
+        r#"
+        UPDATE users
+        SET token_version = token_version + 1
+        WHERE id = $1 AND deleted_at IS NULL
+        RETURNING id, username, email, password_hash, avatar_url, created_at, token_version
+        "#,
+    )
+    .bind(user.id)
+    .fetch_optional(pool.get_ref())
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?
+    .ok_or_else(|| actix_web::error::ErrorNotFound("User not found"))?;
+
+    // open sockets were authenticated with the old tokens
+    server.disconnect_user(user.id);
+
+    let token = create_jwt(user.id, &user.username, user.token_version)
         .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to create token"))?;
 
     Ok(HttpResponse::Ok().json(AuthResponse {
@@ -81,3 +418,276 @@ pub async fn login(
         user: user.into(),
     }))
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        test_support::{app, create_user, start_server, PASSWORD},
+        utils::totp,
+    };
+    use actix_web::{http::StatusCode, test};
+    use serde_json::{json, Value};
+    use sqlx::PgPool;
+    use totp_rs::Secret;
+
+    #[sqlx::test]
+    async fn login_rehashes_a_password_stored_with_another_cost(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        sqlx::query("UPDATE users SET password_hash = $1 WHERE id = $2")
+            .bind(bcrypt::hash(PASSWORD, 5).unwrap())
+            .bind(alice.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let login = || {
+            test::TestRequest::post()
+                .uri("/api/auth/login")
+                .set_json(json!({ "email": "alice@example.com", "password": PASSWORD }))
+                .to_request()
+        };
+        assert_eq!(
+            test::call_service(&app, login()).await.status(),
+            StatusCode::OK
+        );
+
+        // the tests run with BCRYPT_COST=4
+        let stored =
+            sqlx::query_scalar::<_, String>("SELECT password_hash FROM users WHERE id = $1")
+                .bind(alice.id)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert!(stored.starts_with("$2b$04$"), "{}", stored);
+        assert_eq!(
+            test::call_service(&app, login()).await.status(),
+            StatusCode::OK
+        );
+    }
+
+    #[sqlx::test]
+    async fn register_names_the_field_that_is_taken(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        create_user(&pool, "alice").await;
+
+        for (username, email, field, error) in [
+            (
+                "alice",
+                "new@example.com",
+                "username",
+                "Username already exists",
+            ),
+            (
+                "newcomer",
+                "alice@example.com",
+                "email",
+                "Email already exists",
+            ),
+        ] {
+            let req = test::TestRequest::post()
+                .uri("/api/auth/register")
+                .set_json(json!({ "username": username, "email": email, "password": PASSWORD }))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), StatusCode::CONFLICT);
+            let body: Value = test::read_body_json(resp).await;
+            assert_eq!(body["field"], field);
+            assert_eq!(body["error"], error);
+        }
+
+        let req = test::TestRequest::post()
+            .uri("/api/auth/register")
+            .set_json(
+                json!({ "username": "newcomer", "email": "new@example.com", "password": PASSWORD }),
+            )
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::CREATED
+        );
+    }
+
+    #[sqlx::test]
+    async fn two_factor_setup_then_login_needs_the_current_code(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+
+        let req = test::TestRequest::post()
+            .uri("/api/auth/2fa/setup")
+            .insert_header(alice.bearer())
+            .to_request();
+        let setup: Value = test::call_and_read_body_json(&app, req).await;
+        let base32 = setup["secret"].as_str().unwrap();
+        assert!(setup["otpauth_url"]
+            .as_str()
+            .unwrap()
+            .contains(&format!("secret={}", base32)));
+
+        let stored = sqlx::query_scalar::<_, String>(
+            "SELECT totp_secret_encrypted FROM users WHERE id = $1",
+        )
+        .bind(alice.id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert!(!stored.contains(base32), "secret must be stored encrypted");
+
+        let secret = Secret::Encoded(base32.to_string()).to_bytes().unwrap();
+        let totp = totp::build_totp(secret, "alice@example.com").unwrap();
+        let code = totp.generate_current().unwrap();
+        // every digit changed, so it can't match this or a neighbouring step by accident
+        let wrong: String = code
+            .chars()
+            .map(|c| char::from_digit((c.to_digit(10).unwrap() + 5) % 10, 10).unwrap())
+            .collect();
+
+        let verify = |code: &str| {
+            test::TestRequest::post()
+                .uri("/api/auth/2fa/verify")
+                .insert_header(alice.bearer())
+                .set_json(json!({ "code": code }))
+                .to_request()
+        };
+        assert_eq!(
+            test::call_service(&app, verify(&wrong)).await.status(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            test::call_service(&app, verify(&code)).await.status(),
+            StatusCode::NO_CONTENT
+        );
+
+        let login = |totp_code: Option<&str>| {
+            test::TestRequest::post()
+                .uri("/api/auth/login")
+                .set_json(json!({
+                    "email": "alice@example.com",
+                    "password": PASSWORD,
+                    "totp_code": totp_code,
+                }))
+                .to_request()
+        };
+        let resp = test::call_service(&app, login(None)).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["field"], "totp_code");
+
+        assert_eq!(
+            test::call_service(&app, login(Some(&wrong))).await.status(),
+            StatusCode::UNAUTHORIZED
+        );
+        let resp = test::call_service(&app, login(Some(&code))).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[sqlx::test]
+    async fn email_verification_link_works_once_and_not_after_expiry(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+
+        let send = || {
+            test::TestRequest::post()
+                .uri("/api/auth/send-verification")
+                .insert_header(alice.bearer())
+                .to_request()
+        };
+        let verify = |token: &str| {
+            test::TestRequest::get()
+                .uri(&format!("/api/auth/verify?token={}", token))
+                .to_request()
+        };
+        let verified = || {
+            sqlx::query_scalar::<_, bool>("SELECT email_verified FROM users WHERE id = $1")
+                .bind(alice.id)
+                .fetch_one(&pool)
+        };
+
+        // APP_ENV=test hands the token back instead of mailing it
+        let body: Value = test::call_and_read_body_json(&app, send()).await;
+        let token = body["token"].as_str().unwrap().to_string();
+        sqlx::query("UPDATE email_verifications SET expires_at = NOW() - INTERVAL '1 minute'")
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert_eq!(
+            test::call_service(&app, verify(&token)).await.status(),
+            StatusCode::BAD_REQUEST
+        );
+        assert!(!verified().await.unwrap());
+
+        let body: Value = test::call_and_read_body_json(&app, send()).await;
+        let token = body["token"].as_str().unwrap().to_string();
+        assert_eq!(
+            test::call_service(&app, verify("not-a-token"))
+                .await
+                .status(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            test::call_service(&app, verify(&token)).await.status(),
+            StatusCode::NO_CONTENT
+        );
+        assert!(verified().await.unwrap());
+
+        // single use
+        assert_eq!(
+            test::call_service(&app, verify(&token)).await.status(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            test::call_service(&app, send()).await.status(),
+            StatusCode::CONFLICT
+        );
+    }
+
+    #[sqlx::test]
+    async fn revoking_all_sessions_invalidates_earlier_tokens_only(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+
+        let channels = |token: &str| {
+            test::TestRequest::get()
+                .uri("/api/channels")
+                .insert_header(("Authorization", format!("Bearer {}", token)))
+                .to_request()
+        };
+        let revoke = |token: &str| {
+            test::TestRequest::post()
+                .uri("/api/auth/revoke-all")
+                .insert_header(("Authorization", format!("Bearer {}", token)))
+                .to_request()
+        };
+
+        let body: Value = test::call_and_read_body_json(&app, revoke(&alice.token)).await;
+        let fresh = body["token"].as_str().unwrap().to_string();
+
+        for req in [channels(&alice.token), revoke(&alice.token)] {
+            assert_eq!(
+                test::call_service(&app, req).await.status(),
+                StatusCode::UNAUTHORIZED
+            );
+        }
+        assert_eq!(
+            test::call_service(&app, channels(&fresh)).await.status(),
+            StatusCode::OK
+        );
+
+        // logging in again mints a token at the new version
+        let req = test::TestRequest::post()
+            .uri("/api/auth/login")
+            .set_json(json!({ "email": "alice@example.com", "password": PASSWORD }))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        let token = body["token"].as_str().unwrap();
+        assert_eq!(
+            test::call_service(&app, channels(token)).await.status(),
+            StatusCode::OK
+        );
+    }
+}
@@ -1,133 +1,403 @@
 use crate::{
+    config::{require_email_verification, unique_channel_names},
+    db::{
+        membership::{add_member, AddMemberOutcome},
+        transaction,
+    },
+    handlers::websocket::{
+        insert_message_with_retry, is_archived, post_system_message, retry_after_secs,
+        slow_mode_wait, ChatServerHandle,
+    },
+    middleware::auth::AuthUser,
     models::{
         channel::{
-            Channel, ChannelMemberInfo, ChannelResponse, ChannelWithMembers, CreateChannelRequest,
+            ActiveMember, Channel, ChannelMemberInfo, ChannelPreferences, ChannelResponse,
+            ChannelStatsResponse, ChannelWithMembers, CreateChannelRequest, DailyMessageCount,
+            ListChannelsQuery, MemberRole, MemberSummaryEntry, MembersSummaryResponse,
+            PublicChannelResponse, RoleCount, UpdateChannelPreferencesRequest,
+            UpdateChannelRequest, UpdateMemberRoleRequest,
+        },
+        error::ErrorResponse,
+        pagination::{Direction, KeysetCursor, KeysetQuery, ListQuery, Page},
+        role::Role,
+        EditMessageRequest, ExportFormat, ExportQuery, ExportedMessage, Message,
+        MessageEditResponse, MessageResponse, PinnedMessage, PostMessageRequest, WsMessage,
+    },
+    utils::{
+        access::{authorize_message_access, member_role, ChannelMember},
+        sanitize::sanitize_content,
+        validation::{
+            sanitize_channel_name, validate_attachments, validate_message_content,
+            MAX_CHANNEL_NAME_LENGTH,
         },
-        MessageResponse,
+        word_filter::word_filter,
     },
-    utils::jwt::Claims,
 };
-use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use futures_util::StreamExt;
 use sqlx::PgPool;
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+};
+use tokio::sync::mpsc;
 use uuid::Uuid;
 
+const DEFAULT_MAX_CHANNELS_PER_USER: i64 = 100;
+const MAX_DESCRIPTION_LENGTH: usize = 1000;
+const MAX_TOPIC_LENGTH: usize = 250;
+const MAX_IDEMPOTENCY_KEY_LENGTH: usize = 255;
+/// How long a replayed `Idempotency-Key` returns the original channel.
+const IDEMPOTENCY_KEY_TTL_HOURS: i32 = 24;
+/// Channel stats only look this far back so the aggregates stay cheap on old channels.
+const STATS_WINDOW_DAYS: i64 = 30;
+const STATS_MOST_ACTIVE_LIMIT: i64 = 10;
+/// Export chunks queued ahead of a slow client before the database read pauses.
+const EXPORT_BUFFER: usize = 64;
+const MAX_BULK_ROLE_UPDATES: usize = 100;
+const DEFAULT_MAX_PINNED_MESSAGES: i64 = 50;
+/// Six hours, the longest interval a channel can throttle its members to.
+const MAX_SLOW_MODE_SECONDS: i32 = 21_600;
+
+fn validate_details(
+    description: Option<&str>,
+    topic: Option<&str>,
+) -> Result<(), actix_web::Error> {
+    if description.is_some_and(|d| d.chars().count() > MAX_DESCRIPTION_LENGTH) {
+        return Err(actix_web::error::ErrorBadRequest("Description is too long"));
+    }
+
+    if topic.is_some_and(|t| t.chars().count() > MAX_TOPIC_LENGTH) {
+        return Err(actix_web::error::ErrorBadRequest("Topic is too long"));
+    }
+
+    Ok(())
+}
+
+/// Reads `MAX_CHANNEL_NAME_LENGTH`, never above what the column can hold.
+fn max_channel_name_length() -> usize {
+    env::var("MAX_CHANNEL_NAME_LENGTH")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .filter(|length| (1..=MAX_CHANNEL_NAME_LENGTH).contains(length))
+        .unwrap_or(MAX_CHANNEL_NAME_LENGTH)
+}
+
+/// Reads `MAX_PINNED_MESSAGES`, the per-channel pin cap; 0 or invalid uses the default.
+fn max_pinned_messages() -> i64 {
+    env::var("MAX_PINNED_MESSAGES")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .filter(|pins| *pins > 0)
+        .unwrap_or(DEFAULT_MAX_PINNED_MESSAGES)
+}
+
+fn max_channels_per_user() -> i64 {
+    env::var("MAX_CHANNELS_PER_USER")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_MAX_CHANNELS_PER_USER)
+}
+
 pub async fn create_channel(
     pool: web::Data<PgPool>,
     req: HttpRequest,
+    user: AuthUser,
     body: web::Json<CreateChannelRequest>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let claims = req
-        .extensions()
-        .get::<Claims>()
-        .cloned()
-        .ok_or_else(|| actix_web::error::ErrorUnauthorized("No claims found"))?;
+    let user_id = user.id;
 
-    let user_id = Uuid::parse_str(&claims.sub)
-        .map_err(|_| actix_web::error::ErrorUnauthorized("Invalid user ID"))?;
+    let name = sanitize_channel_name(&body.name, max_channel_name_length())
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    validate_details(body.description.as_deref(), body.topic.as_deref())?;
 
-    let channel = sqlx::query_as::<_, Channel>(
+    // every channel needs an admin, so a creator who won't be one has to name who is
+    let creator_role = body.creator_role.unwrap_or(Role::Admin);
+    if !creator_role.is_admin() && body.admin_id.is_none() {
+        return Err(actix_web::error::ErrorBadRequest(
+            "admin_id is required when creator_role is not admin",
+        ));
+    }
+
+    if let Some(admin_id) = body.admin_id {
+        if admin_id == user_id {
+            return Err(actix_web::error::ErrorBadRequest(
+                "admin_id must be another user, use creator_role instead",
+            ));
+        }
+
+        let exists = sqlx::query_scalar::<_, bool>(
+            r#"
+            SELECT EXISTS(SELECT 1 FROM users WHERE id = $1 AND deleted_at IS NULL)
+            "#,
+        )
+        .bind(admin_id)
+        .fetch_one(pool.get_ref())
+        .await
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+
+        if !exists {
+            return Err(actix_web::error::ErrorBadRequest("admin_id user not found"));
+        }
+    }
+
+    if require_email_verification() {
+        let verified = sqlx::query_scalar::<_, bool>(
+            r#"
+            SELECT email_verified FROM users WHERE id = $1
+            "#,
+        )
+        .bind(user_id)
+        .fetch_one(pool.get_ref())
+        .await
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+
+        if !verified {
+            return Err(actix_web::error::ErrorForbidden(
+                "Verify your email before creating channels",
+            ));
+        }
+    }
+
+    let idempotency_key = req
+        .headers()
+        .get("Idempotency-Key")
+        .map(|value| {
+            value
+                .to_str()
+                .ok()
+                .filter(|key| !key.is_empty() && key.len() <= MAX_IDEMPOTENCY_KEY_LENGTH)
+                .map(str::to_string)
+                .ok_or_else(|| actix_web::error::ErrorBadRequest("Invalid Idempotency-Key"))
+        })
+        .transpose()?;
+
+    if let Some(key) = &idempotency_key {
+        let existing = sqlx::query_as::<_, ChannelResponse>(
+            r#"
+            SELECT c.id, c.name, c.description, c.topic, c.created_by, c.created_at, c.archived_at,
+            c.is_public,
+                cm.role,
+                COALESCE(cp.muted, false) AS muted,
+                NULL::json AS last_message
+            FROM idempotency_keys k
+            INNER JOIN channels c ON c.id = k.channel_id
+            INNER JOIN channel_members cm ON cm.channel_id = c.id AND cm.user_id = k.user_id
+            LEFT JOIN channel_preferences cp ON cp.channel_id = c.id AND cp.user_id = k.user_id
+            WHERE k.user_id = $1 AND k.key = $2
+                AND k.created_at > NOW() - make_interval(hours => $3)
+            "#,
+        )
+        .bind(user_id)
+        .bind(key)
+        .bind(IDEMPOTENCY_KEY_TTL_HOURS)
+        .fetch_optional(pool.get_ref())
+        .await
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+
+        if let Some(channel) = existing {
+            return Ok(HttpResponse::Ok().json(channel));
+        }
+    }
+
+    let owned_channels = sqlx::query_scalar::<_, i64>(
         r#"
-        INSERT INTO channels (name, created_by)
-        VALUES ($1, $2)
-        RETURNING id, name, created_by, created_at
+        SELECT COUNT(*) FROM channels
+        WHERE created_by = $1
         "#,
     )
-    .bind(&body.name)
     .bind(user_id)
     .fetch_one(pool.get_ref())
     .await
-    .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to create channel"))?;
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
 
-    sqlx::query(
+    if owned_channels >= max_channels_per_user() {
+        return Err(actix_web::error::ErrorConflict(
+            "Channel limit reached for this user",
+        ));
+    }
+
+    // a channel must never be visible without its admin or idempotency key
+    let mut tx = transaction::begin(pool.get_ref()).await?;
+
+    if unique_channel_names() {
+        // serializes the creator's concurrent creates so two can't both pass the check
+        sqlx::query(
+            r#"
+            SELECT id FROM users WHERE id = $1 FOR UPDATE
+            "#,
+        )
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+
+        let taken = sqlx::query_scalar::<_, bool>(
+            r#"
+            SELECT EXISTS(
+                SELECT 1 FROM channels
+                WHERE created_by = $1 AND lower(name) = lower($2)
+            )
+            "#,
+        )
+        .bind(user_id)
+        .bind(&name)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+
+        if taken {
+            return Ok(HttpResponse::Conflict().json(ErrorResponse::for_field(
+                "name",
+                "You already have a channel with this name",
+            )));
+        }
+    }
+
+    let channel = sqlx::query_as::<_, Channel>(
         r#"
-        INSERT INTO channel_members (channel_id, user_id, role)
-        VALUES ($1, $2, 'admin')
+        INSERT INTO channels (name, description, topic, created_by, is_public)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id, name, description, topic, created_by, created_at, archived_at,
+            retention_days, is_public, max_members, slow_mode_seconds, invite_policy
         "#,
     )
-    .bind(channel.id)
+    .bind(&name)
+    .bind(&body.description)
+    .bind(&body.topic)
     .bind(user_id)
-    .execute(pool.get_ref())
+    .bind(body.is_public)
+    .fetch_one(&mut *tx)
     .await
-    .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to add member"))?;
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to create channel"))?;
+
+    let members = std::iter::once((user_id, creator_role))
+        .chain(body.admin_id.map(|admin_id| (admin_id, Role::Admin)));
+    for (member_id, role) in members {
+        sqlx::query(
+            r#"
+            INSERT INTO channel_members (channel_id, user_id, role)
+            VALUES ($1, $2, $3)
+            "#,
+        )
+        .bind(channel.id)
+        .bind(member_id)
+        .bind(role)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to add member"))?;
+    }
+
+    // stored with the channel, so a retry after a failure here creates it afresh
+    if let Some(key) = &idempotency_key {
+        sqlx::query(
+            r#"
+            INSERT INTO idempotency_keys (user_id, key, channel_id)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (user_id, key)
+            DO UPDATE SET channel_id = EXCLUDED.channel_id, created_at = NOW()
+            "#,
+        )
+        .bind(user_id)
+        .bind(key)
+        .bind(channel.id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| {
+            actix_web::error::ErrorInternalServerError("Failed to store idempotency key")
+        })?;
+    }
+
+    transaction::commit(tx).await?;
 
     Ok(HttpResponse::Ok().json(ChannelResponse {
         id: channel.id,
         name: channel.name,
+        description: channel.description,
+        topic: channel.topic,
         created_by: channel.created_by,
         created_at: channel.created_at,
-        role: "admin".to_string(),
+        archived_at: channel.archived_at,
+        is_public: channel.is_public,
+        role: creator_role,
+        muted: false,
+        last_message: None,
     }))
 }
 
 pub async fn list_channels(
     pool: web::Data<PgPool>,
-    req: HttpRequest,
+    user: AuthUser,
+    filter: web::Query<ListChannelsQuery>,
+    query: web::Query<ListQuery>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let claims = req
-        .extensions()
-        .get::<Claims>()
-        .cloned()
-        .ok_or_else(|| actix_web::error::ErrorInternalServerError("No claims found"))?;
+    let user_id = user.id;
 
-    let user_id = Uuid::parse_str(&claims.sub)
-        .map_err(|_| actix_web::error::ErrorInternalServerError("Invalid user ID"))?;
+    let (limit, offset) = if query.envelope {
+        (Some(query.page_limit() + 1), query.offset()?)
+    } else {
+        (None, 0)
+    };
 
     let channels: Vec<ChannelResponse> = sqlx::query_as::<_, ChannelResponse>(
         r#"
-        SELECT c.id, c.name, c.created_by, c.created_at, cm.role
+        SELECT c.id, c.name, c.description, c.topic, c.created_by, c.created_at, c.archived_at,
+            c.is_public,
+            cm.role,
+            COALESCE(cp.muted, false) AS muted,
+            CASE WHEN lm.id IS NULL THEN NULL ELSE json_build_object(
+                'id', lm.id,
+                'user_id', lm.user_id,
+                'username', lm.username,
+                'content', lm.content,
+                'created_at', lm.created_at
+            ) END AS last_message
         FROM channels c
         INNER JOIN channel_members cm ON c.id = cm.channel_id
-        WHERE cm.user_id = $1
-        ORDER BY created_at DESC
+        LEFT JOIN channel_preferences cp ON cp.channel_id = c.id AND cp.user_id = cm.user_id
+        LEFT JOIN LATERAL (
+            SELECT m.id, m.user_id, u.username, LEFT(m.content, 100) AS content, m.created_at
+            FROM messages m
+            INNER JOIN users u ON m.user_id = u.id
+            WHERE m.channel_id = c.id AND m.deleted_at IS NULL
+            ORDER BY m.created_at DESC, m.id DESC
+            LIMIT 1
+        ) lm ON true
+        WHERE cm.user_id = $1 AND ($4 OR c.archived_at IS NULL)
+        ORDER BY c.created_at DESC, c.id DESC
+        LIMIT $2 OFFSET $3
         "#,
     )
     .bind(user_id)
+    .bind(limit)
+    .bind(offset)
+    .bind(filter.include_archived)
     .fetch_all(pool.get_ref())
     .await
     .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to fetch channels"))?;
 
+    if query.envelope {
+        return Ok(HttpResponse::Ok().json(Page::from_overfetch(
+            channels,
+            query.page_limit(),
+            offset,
+        )));
+    }
+
     Ok(HttpResponse::Ok().json(channels))
 }
 
 pub async fn get_channel(
     pool: web::Data<PgPool>,
-    req: HttpRequest,
-    path: web::Path<Uuid>,
+    member: ChannelMember,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let claims = req
-        .extensions()
-        .get::<Claims>()
-        .cloned()
-        .ok_or_else(|| actix_web::error::ErrorInternalServerError("No claims found"))?;
-
-    let user_id = Uuid::parse_str(&claims.sub)
-        .map_err(|_| actix_web::error::ErrorInternalServerError("Invalid user id"))?;
-
-    let channel_id = path.into_inner();
-
-    let is_member = sqlx::query_scalar::<_, bool>(
-        r#"
-    SELECT EXISTS(
-        SELECT 1 FROM channel_members
-        WHERE channel_id = $1 AND user_id = $2
-    )
-        "#,
-    )
-    .bind(channel_id)
-    .bind(user_id)
-    .fetch_one(pool.get_ref())
-    .await
-    .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
-
-    if !is_member {
-        return Err(actix_web::error::ErrorInternalServerError(
-            "Not a member of this channel",
-        ));
-    }
+    let channel_id = member.channel_id;
 
     let channel = sqlx::query_as::<_, Channel>(
         r#"
-        SELECT id, name, created_by, created_at
+        SELECT id, name, description, topic, created_by, created_at, archived_at,
+            retention_days, is_public, max_members, slow_mode_seconds, invite_policy
         FROM channels
         WHERE id = $1
     "#,
@@ -140,7 +410,7 @@ pub async fn get_channel(
 
     let members = sqlx::query_as::<_, ChannelMemberInfo>(
         r#"
-    SELECT cm.user_id, u.username, cm.role, false as is_online
+    SELECT cm.user_id, u.username, u.avatar_url, cm.role, false as is_online
     FROM channel_members cm 
     INNER JOIN users u ON cm.user_id = u.id
     WHERE cm.channel_id = $1
@@ -155,62 +425,2762 @@ pub async fn get_channel(
     Ok(HttpResponse::Ok().json(ChannelWithMembers {
         id: channel.id,
         name: channel.name,
+        description: channel.description,
+        topic: channel.topic,
         created_by: channel.created_by,
         created_at: channel.created_at,
+        archived_at: channel.archived_at,
+        retention_days: channel.retention_days,
+        is_public: channel.is_public,
+        max_members: channel.max_members,
+        slow_mode_seconds: channel.slow_mode_seconds,
+        invite_policy: channel.invite_policy,
         members,
     }))
 }
 
 pub async fn get_messages(
     pool: web::Data<PgPool>,
-    req: HttpRequest,
-    path: web::Path<Uuid>,
+    member: ChannelMember,
+    query: web::Query<ListQuery>,
+    keyset: web::Query<KeysetQuery>,
+) -> Result<HttpResponse, actix_web::Error> {
+    fetch_messages(pool.get_ref(), member.channel_id, &query, keyset.direction).await
+}
+
+/// Lists public channels; reachable without a token when `GUEST_READ_ACCESS` is on.
+pub async fn list_public_channels(
+    pool: web::Data<PgPool>,
+    query: web::Query<ListQuery>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let claims = req
-        .extensions()
-        .get::<Claims>()
-        .cloned()
-        .ok_or_else(|| actix_web::error::ErrorUnauthorized("No claims found"))?;
+    let (limit, offset) = if query.envelope {
+        (Some(query.page_limit() + 1), query.offset()?)
+    } else {
+        (None, 0)
+    };
 
-    let user_id = Uuid::parse_str(&claims.sub)
-        .map_err(|_| actix_web::error::ErrorInternalServerError("Invalid user id"))?;
+    let channels = sqlx::query_as::<_, PublicChannelResponse>(
+        r#"
+        SELECT id, name, description, topic, created_at
+        FROM channels
+        WHERE is_public AND archived_at IS NULL
+        ORDER BY created_at DESC, id DESC
+        LIMIT $1 OFFSET $2
+        "#,
+    )
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(pool.get_ref())
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to fetch channels"))?;
+
+    if query.envelope {
+        return Ok(HttpResponse::Ok().json(Page::from_overfetch(
+            channels,
+            query.page_limit(),
+            offset,
+        )));
+    }
+
+    Ok(HttpResponse::Ok().json(channels))
+}
 
+/// Lets any authenticated user join a public, unarchived channel as a member.
+pub async fn join_channel(
+    pool: web::Data<PgPool>,
+    server: web::Data<ChatServerHandle>,
+    user: AuthUser,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, actix_web::Error> {
     let channel_id = path.into_inner();
 
-    let is_member = sqlx::query_scalar::<_, bool>(
+    #[derive(sqlx::FromRow)]
+    struct JoinableRow {
+        is_public: bool,
+        is_archived: bool,
+    }
+
+    let channel = sqlx::query_as::<_, JoinableRow>(
         r#"
-    SELECT EXISTS(
-        SELECT 1 FROM channel_members
-        WHERE channel_id = $1 AND user_id = $2
+        SELECT is_public, archived_at IS NOT NULL AS is_archived
+        FROM channels
+        WHERE id = $1
+        "#,
+    )
+    .bind(channel_id)
+    .fetch_optional(pool.get_ref())
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?
+    .filter(|channel| channel.is_public)
+    .ok_or_else(|| actix_web::error::ErrorNotFound("Channel not found"))?;
+
+    if channel.is_archived {
+        return Err(actix_web::error::ErrorConflict("Channel is archived"));
+    }
+
+    if member_role(pool.get_ref(), channel_id, user.id)
+        .await
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?
+        .is_some()
+    {
+        return Err(actix_web::error::ErrorConflict(
+            "Already a member of this channel",
+        ));
+    }
+
+    let mut tx = transaction::begin(pool.get_ref()).await?;
+
+    let outcome = add_member(&mut tx, channel_id, user.id, Role::Member)
+        .await
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to join channel"))?;
+
+    match outcome {
+        AddMemberOutcome::Added => {}
+        // a concurrent join got there first
+        AddMemberOutcome::AlreadyMember => {
+            return Err(actix_web::error::ErrorConflict(
+                "Already a member of this channel",
+            ));
+        }
+        AddMemberOutcome::Full => {
+            return Err(actix_web::error::ErrorConflict("Channel is full"));
+        }
+    }
+
+    transaction::commit(tx).await?;
+
+    server.broadcast(
+        channel_id,
+        WsMessage::MemberAdded {
+            user_id: user.id,
+            username: user.username.clone(),
+        },
+    );
+    post_system_message(
+        pool.get_ref(),
+        server.get_ref(),
+        channel_id,
+        user.id,
+        &user.username,
+        format!("{} joined the channel", user.username),
     )
+    .await;
+
+    Ok(HttpResponse::Ok().json("Joined channel"))
+}
+
+/// Read-only message listing for guests; private channels look like they don't exist.
+pub async fn get_public_messages(
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    query: web::Query<ListQuery>,
+    keyset: web::Query<KeysetQuery>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let channel_id = path.into_inner();
+
+    let is_public = sqlx::query_scalar::<_, bool>(
+        r#"
+        SELECT is_public FROM channels
+        WHERE id = $1
         "#,
     )
     .bind(channel_id)
-    .bind(user_id)
+    .fetch_optional(pool.get_ref())
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?
+    .unwrap_or(false);
+
+    if !is_public {
+        return Err(actix_web::error::ErrorNotFound("Channel not found"));
+    }
+
+    fetch_messages(pool.get_ref(), channel_id, &query, keyset.direction).await
+}
+
+/// Newest-first messages; with `envelope=true` paged by an opaque `(created_at, id)` cursor,
+/// so deep pages cost the same as the first one.
+async fn fetch_messages(
+    pool: &PgPool,
+    channel_id: Uuid,
+    query: &ListQuery,
+    direction: Direction,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (limit, cursor, direction) = if query.envelope {
+        (query.page_limit(), query.keyset_cursor()?, direction)
+    } else {
+        (100, None, Direction::Older)
+    };
+
+    // only the comparison and sort order differ, both come from this fixed set
+    let (compare, order) = match direction {
+        Direction::Older => ("<", "DESC"),
+        Direction::Newer => (">", "ASC"),
+    };
+
+    let sql = format!(
+        r#"
+    SELECT m.id, m.channel_id, m.user_id, u.username, m.content, m.created_at, m.client_sent_at,
+        m.edited_at, m.version, m.kind, m.format,
+        COALESCE(
+            (
+                SELECT json_agg(
+                    json_build_object('url', a.url, 'mime_type', a.mime_type, 'size', a.size)
+                    ORDER BY a.created_at
+                )
+                FROM attachments a
+                WHERE a.message_id = m.id
+            ),
+            '[]'::json
+        ) AS attachments
+    FROM messages m
+    INNER JOIN users u ON m.user_id = u.id
+    WHERE m.channel_id = $1 AND m.deleted_at IS NULL
+        AND ($2::timestamptz IS NULL OR (m.created_at, m.id) {compare} ($2, $3))
+    ORDER BY m.created_at {order}, m.id {order}
+    LIMIT $4
+        "#
+    );
+
+    let mut messages = sqlx::query_as::<_, MessageResponse>(&sql)
+        .bind(channel_id)
+        .bind(cursor.map(|cursor| cursor.created_at))
+        .bind(cursor.map(|cursor| cursor.id))
+        .bind(limit + 1)
+        .fetch_all(pool)
+        .await
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to fetch"))?;
+
+    let has_more = messages.len() as i64 > limit;
+    messages.truncate(limit as usize);
+    if direction == Direction::Newer {
+        messages.reverse();
+    }
+
+    if !query.envelope {
+        return Ok(HttpResponse::Ok().json(messages));
+    }
+
+    let key = |message: &MessageResponse| KeysetCursor::new(message.created_at, message.id);
+    let newest = messages.first().map(key);
+    let oldest = messages.last().map(key);
+
+    // next keeps going the requested way, prev turns back (there is nothing behind page one)
+    let (next_cursor, prev_cursor) = match direction {
+        Direction::Older => (
+            oldest.filter(|_| has_more),
+            newest.filter(|_| cursor.is_some()),
+        ),
+        Direction::Newer => (
+            newest.filter(|_| has_more),
+            oldest.filter(|_| cursor.is_some()),
+        ),
+    };
+
+    Ok(HttpResponse::Ok().json(Page::keyset(messages, next_cursor, prev_cursor)))
+}
+
+pub async fn get_message(
+    pool: web::Data<PgPool>,
+    user: AuthUser,
+    path: web::Path<(Uuid, Uuid)>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user.id;
+
+    let (channel_id, message_id) = path.into_inner();
+
+    authorize_message_access(pool.get_ref(), user_id, channel_id, message_id).await?;
+
+    let message = sqlx::query_as::<_, MessageResponse>(
+        r#"
+    SELECT m.id, m.channel_id, m.user_id, u.username, m.content, m.created_at, m.client_sent_at,
+        m.edited_at, m.version, m.kind, m.format,
+        COALESCE(
+            (
+                SELECT json_agg(
+                    json_build_object('url', a.url, 'mime_type', a.mime_type, 'size', a.size)
+                    ORDER BY a.created_at
+                )
+                FROM attachments a
+                WHERE a.message_id = m.id
+            ),
+            '[]'::json
+        ) AS attachments
+    FROM messages m
+    INNER JOIN users u ON m.user_id = u.id
+    WHERE m.id = $1
+        "#,
+    )
+    .bind(message_id)
     .fetch_one(pool.get_ref())
     .await
-    .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to fetch"))?;
+
+    Ok(HttpResponse::Ok().json(message))
+}
+
+pub async fn delete_channel(
+    pool: web::Data<PgPool>,
+    server: web::Data<ChatServerHandle>,
+    member: ChannelMember,
+) -> Result<HttpResponse, actix_web::Error> {
+    let channel_id = member.channel_id;
 
-    if !is_member {
+    if !member.is_admin() {
         return Err(actix_web::error::ErrorForbidden(
-            "Not a memmber of this channel",
+            "Only admins can delete channels",
         ));
     }
 
-    let messages = sqlx::query_as::<_, MessageResponse>(
+    sqlx::query(
         r#"
-    SELECT m.id, m.channel_id, m.user_id, u.username, m.content, m.created_at
-    FROM messages m 
-    INNER JOIN users u ON m.user_id = u.id
-    WHERE m.channel_id = $1
-    ORDER BY m.created_at DESC
-    LIMIT 100
+        DELETE FROM channels
+        WHERE id = $1
+        "#,
+    )
+    .bind(channel_id)
+    .execute(pool.get_ref())
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to delete channel"))?;
+
+    server.close_channel(channel_id);
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn update_channel(
+    pool: web::Data<PgPool>,
+    server: web::Data<ChatServerHandle>,
+    user: AuthUser,
+    member: ChannelMember,
+    body: web::Json<UpdateChannelRequest>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let channel_id = member.channel_id;
+
+    let name = body
+        .name
+        .as_deref()
+        .map(|name| sanitize_channel_name(name, max_channel_name_length()))
+        .transpose()
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    validate_details(body.description.as_deref(), body.topic.as_deref())?;
+
+    if body.retention_days.is_some_and(|days| days < 0) {
+        return Err(actix_web::error::ErrorBadRequest(
+            "Retention days cannot be negative",
+        ));
+    }
+
+    if body.max_members.is_some_and(|members| members < 0) {
+        return Err(actix_web::error::ErrorBadRequest(
+            "Max members cannot be negative",
+        ));
+    }
+
+    if body
+        .slow_mode_seconds
+        .is_some_and(|seconds| !(0..=MAX_SLOW_MODE_SECONDS).contains(&seconds))
+    {
+        return Err(actix_web::error::ErrorBadRequest(format!(
+            "Slow mode must be between 0 and {} seconds",
+            MAX_SLOW_MODE_SECONDS
+        )));
+    }
+
+    if !member.is_admin() {
+        return Err(actix_web::error::ErrorForbidden(
+            "Only admins can update channels",
+        ));
+    }
+
+    let mut tx = transaction::begin(pool.get_ref()).await?;
+
+    let old_name = match &name {
+        Some(name) => {
+            let (old_name, created_by) = sqlx::query_as::<_, (String, Uuid)>(
+                r#"
+                SELECT name, created_by FROM channels WHERE id = $1
+                "#,
+            )
+            .bind(channel_id)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?
+            .ok_or_else(|| actix_web::error::ErrorNotFound("Channel not found"))?;
+
+            if unique_channel_names() {
+                // same lock as create_channel, so a rename can't race a create
+                sqlx::query(
+                    r#"
+                    SELECT id FROM users WHERE id = $1 FOR UPDATE
+                    "#,
+                )
+                .bind(created_by)
+                .execute(&mut *tx)
+                .await
+                .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+
+                let taken = sqlx::query_scalar::<_, bool>(
+                    r#"
+                    SELECT EXISTS(
+                        SELECT 1 FROM channels
+                        WHERE created_by = $1 AND lower(name) = lower($2) AND id <> $3
+                    )
+                    "#,
+                )
+                .bind(created_by)
+                .bind(name)
+                .bind(channel_id)
+                .fetch_one(&mut *tx)
+                .await
+                .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+
+                if taken {
+                    return Ok(HttpResponse::Conflict().json(ErrorResponse::for_field(
+                        "name",
+                        "You already have a channel with this name",
+                    )));
+                }
+            }
+
+            Some(old_name)
+        }
+        None => None,
+    };
+
+    // omitted fields are left untouched, an empty string clears description/topic
+    // and a retention or member cap of 0 falls back to the server default,
+    // a slow mode of 0 turns it off
+    let channel = sqlx::query_as::<_, Channel>(
+        r#"
+        UPDATE channels
+        SET name = COALESCE($2, name),
+            description = NULLIF(COALESCE($3, description), ''),
+            topic = NULLIF(COALESCE($4, topic), ''),
+            retention_days = NULLIF(COALESCE($5, retention_days), 0),
+            is_public = COALESCE($6, is_public),
+            max_members = NULLIF(COALESCE($7, max_members), 0),
+            slow_mode_seconds = NULLIF(COALESCE($8, slow_mode_seconds), 0),
+            invite_policy = COALESCE($9, invite_policy)
+        WHERE id = $1
+        RETURNING id, name, description, topic, created_by, created_at, archived_at,
+            retention_days, is_public, max_members, slow_mode_seconds, invite_policy
+        "#,
+    )
+    .bind(channel_id)
+    .bind(&name)
+    .bind(&body.description)
+    .bind(&body.topic)
+    .bind(body.retention_days)
+    .bind(body.is_public)
+    .bind(body.max_members)
+    .bind(body.slow_mode_seconds)
+    .bind(body.invite_policy)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to update channel"))?
+    .ok_or_else(|| actix_web::error::ErrorNotFound("Channel not found"))?;
+
+    transaction::commit(tx).await?;
+
+    server.broadcast(
+        channel.id,
+        WsMessage::ChannelUpdated {
+            channel_id: channel.id,
+            name: channel.name.clone(),
+            description: channel.description.clone(),
+            topic: channel.topic.clone(),
+        },
+    );
+
+    if old_name.is_some_and(|old_name| old_name != channel.name) {
+        post_system_message(
+            pool.get_ref(),
+            server.get_ref(),
+            channel.id,
+            user.id,
+            &user.username,
+            format!("{} renamed the channel to {}", user.username, channel.name),
+        )
+        .await;
+    }
+
+    Ok(HttpResponse::Ok().json(channel))
+}
+
+/// The caller's own role, so clients can decide whether to show admin controls.
+pub async fn get_my_role(member: ChannelMember) -> Result<HttpResponse, actix_web::Error> {
+    Ok(HttpResponse::Ok().json(MemberRole {
+        user_id: member.user_id,
+        role: member.role,
+    }))
+}
+
+pub async fn get_members_summary(
+    pool: web::Data<PgPool>,
+    member: ChannelMember,
+) -> Result<HttpResponse, actix_web::Error> {
+    let channel_id = member.channel_id;
+
+    if !member.is_admin() {
+        return Err(actix_web::error::ErrorForbidden(
+            "Only admins can view the member summary",
+        ));
+    }
+
+    let roles = sqlx::query_as::<_, RoleCount>(
+        r#"
+        SELECT role, COUNT(*) AS count
+        FROM channel_members
+        WHERE channel_id = $1
+        GROUP BY role
+        ORDER BY role
         "#,
     )
     .bind(channel_id)
     .fetch_all(pool.get_ref())
     .await
-    .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to fetch"))?;
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+
+    let members = sqlx::query_as::<_, MemberSummaryEntry>(
+        r#"
+        SELECT cm.user_id, u.username, u.avatar_url, cm.role, cm.joined_at
+        FROM channel_members cm
+        INNER JOIN users u ON cm.user_id = u.id
+        WHERE cm.channel_id = $1
+        ORDER BY cm.joined_at, u.username
+        "#,
+    )
+    .bind(channel_id)
+    .fetch_all(pool.get_ref())
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to fetch members"))?;
+
+    Ok(HttpResponse::Ok().json(MembersSummaryResponse {
+        channel_id,
+        total: members.len() as i64,
+        roles,
+        members,
+    }))
+}
+
+/// Changes another member's role; the channel always keeps at least one admin.
+pub async fn update_member_role(
+    pool: web::Data<PgPool>,
+    server: web::Data<ChatServerHandle>,
+    member: ChannelMember,
+    path: web::Path<(Uuid, Uuid)>,
+    body: web::Json<UpdateMemberRoleRequest>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (channel_id, target_id) = path.into_inner();
+    let role = body.role;
+
+    if !member.is_admin() {
+        return Err(actix_web::error::ErrorForbidden(
+            "Only admins can change member roles",
+        ));
+    }
 
-    Ok(HttpResponse::Ok().json(messages))
+    let mut tx = transaction::begin(pool.get_ref()).await?;
+
+    // serializes role changes so two concurrent demotions can't remove every admin
+    sqlx::query(
+        r#"
+        SELECT id FROM channels WHERE id = $1 FOR UPDATE
+        "#,
+    )
+    .bind(channel_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+
+    let current = sqlx::query_scalar::<_, Role>(
+        r#"
+        SELECT role FROM channel_members
+        WHERE channel_id = $1 AND user_id = $2
+        "#,
+    )
+    .bind(channel_id)
+    .bind(target_id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?
+    .ok_or_else(|| actix_web::error::ErrorNotFound("Member not found"))?;
+
+    if current.is_admin() && !role.is_admin() {
+        let other_admins = sqlx::query_scalar::<_, i64>(
+            r#"
+            SELECT COUNT(*) FROM channel_members
+            WHERE channel_id = $1 AND role = 'admin' AND user_id <> $2
+            "#,
+        )
+        .bind(channel_id)
+        .bind(target_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+
+        if other_admins == 0 {
+            return Err(actix_web::error::ErrorConflict(
+                "A channel needs at least one admin",
+            ));
+        }
+    }
+
+    let updated = sqlx::query_as::<_, MemberRole>(
+        r#"
+        UPDATE channel_members
+        SET role = $3
+        WHERE channel_id = $1 AND user_id = $2
+        RETURNING user_id, role
+        "#,
+    )
+    .bind(channel_id)
+    .bind(target_id)
+    .bind(role)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to update role"))?;
+
+    transaction::commit(tx).await?;
+
+    if current != role {
+        server.change_role(channel_id, target_id, role);
+    }
+
+    Ok(HttpResponse::Ok().json(updated))
+}
+
+/// Sets several members' roles at once; either every change applies or none does.
+pub async fn update_member_roles(
+    pool: web::Data<PgPool>,
+    server: web::Data<ChatServerHandle>,
+    member: ChannelMember,
+    body: web::Json<Vec<MemberRole>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let channel_id = member.channel_id;
+    let changes = body.into_inner();
+
+    if !member.is_admin() {
+        return Err(actix_web::error::ErrorForbidden(
+            "Only admins can change member roles",
+        ));
+    }
+
+    if changes.is_empty() {
+        return Err(actix_web::error::ErrorBadRequest("No role changes given"));
+    }
+
+    if changes.len() > MAX_BULK_ROLE_UPDATES {
+        return Err(actix_web::error::ErrorBadRequest(format!(
+            "At most {} role changes per request",
+            MAX_BULK_ROLE_UPDATES
+        )));
+    }
+
+    let user_ids: Vec<Uuid> = changes.iter().map(|change| change.user_id).collect();
+    if user_ids.iter().collect::<HashSet<_>>().len() != user_ids.len() {
+        return Err(actix_web::error::ErrorBadRequest(
+            "Each member may only appear once",
+        ));
+    }
+    let roles: Vec<&str> = changes.iter().map(|change| change.role.as_str()).collect();
+
+    let mut tx = transaction::begin(pool.get_ref()).await?;
+
+    // same lock as the single-member update, so the two can't race past the admin check
+    sqlx::query(
+        r#"
+        SELECT id FROM channels WHERE id = $1 FOR UPDATE
+        "#,
+    )
+    .bind(channel_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+
+    let current: HashMap<Uuid, Role> = sqlx::query_as::<_, MemberRole>(
+        r#"
+        SELECT user_id, role FROM channel_members
+        WHERE channel_id = $1 AND user_id = ANY($2)
+        "#,
+    )
+    .bind(channel_id)
+    .bind(&user_ids)
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?
+    .into_iter()
+    .map(|row| (row.user_id, row.role))
+    .collect();
+
+    if let Some(missing) = user_ids.iter().find(|id| !current.contains_key(id)) {
+        return Err(actix_web::error::ErrorNotFound(format!(
+            "{} is not a member of this channel",
+            missing
+        )));
+    }
+
+    let untouched_admins = sqlx::query_scalar::<_, i64>(
+        r#"
+        SELECT COUNT(*) FROM channel_members
+        WHERE channel_id = $1 AND role = 'admin' AND NOT (user_id = ANY($2))
+        "#,
+    )
+    .bind(channel_id)
+    .bind(&user_ids)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+
+    if untouched_admins == 0 && !changes.iter().any(|change| change.role.is_admin()) {
+        return Err(actix_web::error::ErrorConflict(
+            "A channel needs at least one admin",
+        ));
+    }
+
+    let updated = sqlx::query_as::<_, MemberRole>(
+        r#"
+        UPDATE channel_members cm
+        SET role = changes.role
+        FROM UNNEST($2::uuid[], $3::text[]) AS changes(user_id, role)
+        WHERE cm.channel_id = $1 AND cm.user_id = changes.user_id
+        RETURNING cm.user_id, cm.role
+        "#,
+    )
+    .bind(channel_id)
+    .bind(&user_ids)
+    .bind(&roles)
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to update roles"))?;
+
+    transaction::commit(tx).await?;
+
+    for change in &changes {
+        if current.get(&change.user_id) != Some(&change.role) {
+            server.change_role(channel_id, change.user_id, change.role);
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(updated))
+}
+
+/// Streams every live message in the channel as a JSON array or a CSV download.
+///
+/// Rows go out as they are read, so a large channel is never held in memory; a database
+/// error part-way through aborts the response instead of ending it cleanly.
+pub async fn export_messages(
+    pool: web::Data<PgPool>,
+    member: ChannelMember,
+    query: web::Query<ExportQuery>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !member.is_admin() {
+        return Err(actix_web::error::ErrorForbidden(
+            "Only admins can export messages",
+        ));
+    }
+
+    let channel_id = member.channel_id;
+    let format = query.format;
+    let pool = pool.get_ref().clone();
+    let (tx, rx) = mpsc::channel::<Result<web::Bytes, std::io::Error>>(EXPORT_BUFFER);
+
+    tokio::spawn(async move {
+        let mut rows = sqlx::query_as::<_, ExportedMessage>(
+            r#"
+            SELECT m.id, m.user_id, u.username, m.content, m.created_at, m.edited_at, m.kind
+            FROM messages m
+            INNER JOIN users u ON m.user_id = u.id
+            WHERE m.channel_id = $1 AND m.deleted_at IS NULL
+            ORDER BY m.created_at, m.id
+            "#,
+        )
+        .bind(channel_id)
+        .fetch(&pool);
+
+        let opening = match format {
+            ExportFormat::Json => "[",
+            ExportFormat::Csv => ExportedMessage::CSV_HEADER,
+        };
+        if tx.send(Ok(web::Bytes::from(opening))).await.is_err() {
+            return;
+        }
+
+        let mut first = true;
+        while let Some(row) = rows.next().await {
+            let chunk = match row {
+                Ok(message) => match format {
+                    ExportFormat::Json => {
+                        let separator = if first { "" } else { "," };
+                        format!(
+                            "{}{}",
+                            separator,
+                            serde_json::to_string(&message).unwrap_or_default()
+                        )
+                    }
+                    ExportFormat::Csv => message.to_csv_row(),
+                },
+                Err(e) => {
+                    log::error!("Failed to export channel {}: {}", channel_id, e);
+                    let _ = tx.send(Err(std::io::Error::other("Export failed"))).await;
+                    return;
+                }
+            };
+            first = false;
+
+            // the client went away
+            if tx.send(Ok(web::Bytes::from(chunk))).await.is_err() {
+                return;
+            }
+        }
+
+        if format == ExportFormat::Json {
+            let _ = tx.send(Ok(web::Bytes::from("]"))).await;
+        }
+    });
+
+    let body = futures_util::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (chunk, rx))
+    });
+
+    let (content_type, extension) = match format {
+        ExportFormat::Json => ("application/json", "json"),
+        ExportFormat::Csv => ("text/csv; charset=utf-8", "csv"),
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!(
+                "attachment; filename=\"channel-{}.{}\"",
+                channel_id, extension
+            ),
+        ))
+        .streaming(body))
+}
+
+/// Sets the caller's notification preferences for the channel; any member may mute.
+pub async fn update_channel_preferences(
+    pool: web::Data<PgPool>,
+    member: ChannelMember,
+    body: web::Json<UpdateChannelPreferencesRequest>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let preferences = sqlx::query_as::<_, ChannelPreferences>(
+        r#"
+        INSERT INTO channel_preferences (channel_id, user_id, muted)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (channel_id, user_id) DO UPDATE
+        SET muted = EXCLUDED.muted, updated_at = NOW()
+        RETURNING channel_id, muted
+        "#,
+    )
+    .bind(member.channel_id)
+    .bind(member.user_id)
+    .bind(body.muted)
+    .fetch_one(pool.get_ref())
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to update preferences"))?;
+
+    Ok(HttpResponse::Ok().json(preferences))
+}
+
+pub async fn get_channel_stats(
+    pool: web::Data<PgPool>,
+    member: ChannelMember,
+) -> Result<HttpResponse, actix_web::Error> {
+    let channel_id = member.channel_id;
+
+    if !member.is_admin() {
+        return Err(actix_web::error::ErrorForbidden(
+            "Only admins can view channel stats",
+        ));
+    }
+
+    let since = Utc::now() - chrono::Duration::days(STATS_WINDOW_DAYS);
+
+    let member_count = sqlx::query_scalar::<_, i64>(
+        r#"
+        SELECT COUNT(*) FROM channel_members
+        WHERE channel_id = $1
+        "#,
+    )
+    .bind(channel_id)
+    .fetch_one(pool.get_ref())
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+
+    let most_active = sqlx::query_as::<_, ActiveMember>(
+        r#"
+        SELECT m.user_id, u.username, COUNT(*) AS message_count
+        FROM messages m
+        INNER JOIN users u ON m.user_id = u.id
+        WHERE m.channel_id = $1 AND m.created_at >= $2
+            AND m.deleted_at IS NULL AND m.kind = 'user'
+        GROUP BY m.user_id, u.username
+        ORDER BY message_count DESC, u.username
+        LIMIT $3
+        "#,
+    )
+    .bind(channel_id)
+    .bind(since)
+    .bind(STATS_MOST_ACTIVE_LIMIT)
+    .fetch_all(pool.get_ref())
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to fetch stats"))?;
+
+    // days without messages are left out rather than padded with zeroes
+    let messages_per_day = sqlx::query_as::<_, DailyMessageCount>(
+        r#"
+        SELECT (created_at AT TIME ZONE 'UTC')::date AS day, COUNT(*) AS count
+        FROM messages
+        WHERE channel_id = $1 AND created_at >= $2
+            AND deleted_at IS NULL AND kind = 'user'
+        GROUP BY day
+        ORDER BY day
+        "#,
+    )
+    .bind(channel_id)
+    .bind(since)
+    .fetch_all(pool.get_ref())
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to fetch stats"))?;
+
+    let message_count = messages_per_day.iter().map(|day| day.count).sum();
+
+    Ok(HttpResponse::Ok().json(ChannelStatsResponse {
+        channel_id,
+        since,
+        member_count,
+        message_count,
+        most_active,
+        messages_per_day,
+    }))
+}
+
+/// Pins a message; pinning it again keeps the original pin time.
+pub async fn pin_message(
+    pool: web::Data<PgPool>,
+    member: ChannelMember,
+    path: web::Path<(Uuid, Uuid)>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (channel_id, message_id) = path.into_inner();
+
+    if !member.role.can_post() {
+        return Err(actix_web::error::ErrorForbidden(
+            "Viewers can't pin messages",
+        ));
+    }
+
+    let mut tx = transaction::begin(pool.get_ref()).await?;
+
+    // serializes pins per channel so two requests can't both take the last slot
+    sqlx::query(
+        r#"
+        SELECT id FROM channels WHERE id = $1 FOR UPDATE
+        "#,
+    )
+    .bind(channel_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+
+    lock_message(&mut tx, channel_id, message_id).await?;
+
+    let pinned = sqlx::query_scalar::<_, i64>(
+        r#"
+        SELECT COUNT(*) FROM pinned_messages p
+        INNER JOIN messages m ON m.id = p.message_id
+        WHERE p.channel_id = $1 AND p.message_id <> $2 AND m.deleted_at IS NULL
+        "#,
+    )
+    .bind(channel_id)
+    .bind(message_id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+
+    let max_pins = max_pinned_messages();
+    if pinned >= max_pins {
+        return Err(actix_web::error::ErrorConflict(format!(
+            "A channel can have at most {} pinned messages",
+            max_pins
+        )));
+    }
+
+    sqlx::query(
+        r#"
+        INSERT INTO pinned_messages (message_id, channel_id, pinned_by)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (message_id) DO NOTHING
+        "#,
+    )
+    .bind(message_id)
+    .bind(channel_id)
+    .bind(member.user_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to pin message"))?;
+
+    transaction::commit(tx).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn unpin_message(
+    pool: web::Data<PgPool>,
+    member: ChannelMember,
+    path: web::Path<(Uuid, Uuid)>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (channel_id, message_id) = path.into_inner();
+
+    if !member.role.can_post() {
+        return Err(actix_web::error::ErrorForbidden(
+            "Viewers can't unpin messages",
+        ));
+    }
+
+    let result = sqlx::query(
+        r#"
+        DELETE FROM pinned_messages
+        WHERE channel_id = $1 AND message_id = $2
+        "#,
+    )
+    .bind(channel_id)
+    .bind(message_id)
+    .execute(pool.get_ref())
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to unpin message"))?;
+
+    if result.rows_affected() == 0 {
+        return Err(actix_web::error::ErrorNotFound("Message is not pinned"));
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Pinned messages, most recently pinned first.
+pub async fn list_pins(
+    pool: web::Data<PgPool>,
+    member: ChannelMember,
+) -> Result<HttpResponse, actix_web::Error> {
+    let pins = sqlx::query_as::<_, PinnedMessage>(
+        r#"
+        SELECT m.id AS message_id, m.user_id, u.username, m.content, m.created_at,
+            p.pinned_by, p.pinned_at
+        FROM pinned_messages p
+        INNER JOIN messages m ON m.id = p.message_id
+        INNER JOIN users u ON u.id = m.user_id
+        WHERE p.channel_id = $1 AND m.deleted_at IS NULL
+        ORDER BY p.pinned_at DESC, p.message_id DESC
+        "#,
+    )
+    .bind(member.channel_id)
+    .fetch_all(pool.get_ref())
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to fetch pins"))?;
+
+    Ok(HttpResponse::Ok().json(pins))
+}
+
+/// Locks a live message in the channel for an edit or delete, returning its author and content.
+async fn lock_message(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    channel_id: Uuid,
+    message_id: Uuid,
+) -> Result<(Uuid, String, i32), actix_web::Error> {
+    sqlx::query_as::<_, (Uuid, String, i32)>(
+        r#"
+        SELECT user_id, content, version FROM messages
+        WHERE id = $1 AND channel_id = $2 AND deleted_at IS NULL AND kind = 'user'
+        FOR UPDATE
+        "#,
+    )
+    .bind(message_id)
+    .bind(channel_id)
+    .fetch_optional(&mut **tx)
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?
+    .ok_or_else(|| actix_web::error::ErrorNotFound("Message not found"))
+}
+
+async fn record_message_edit(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    message_id: Uuid,
+    editor_id: Uuid,
+    action: &str,
+    old_content: &str,
+) -> Result<(), actix_web::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO message_edits (message_id, editor_id, action, old_content)
+        VALUES ($1, $2, $3, $4)
+        "#,
+    )
+    .bind(message_id)
+    .bind(editor_id)
+    .bind(action)
+    .bind(old_content)
+    .execute(&mut **tx)
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to record message history"))?;
+
+    Ok(())
+}
+
+/// Posts a message without a socket, e.g. for bots, with the same checks as `send_message`.
+pub async fn post_message(
+    pool: web::Data<PgPool>,
+    server: web::Data<ChatServerHandle>,
+    user: AuthUser,
+    member: ChannelMember,
+    body: web::Json<PostMessageRequest>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let channel_id = member.channel_id;
+    let body = body.into_inner();
+
+    if !member.role.can_post() {
+        return Err(actix_web::error::ErrorForbidden(
+            "You don't have permission to post in this channel",
+        ));
+    }
+
+    validate_message_content(&body.content, !body.attachments.is_empty())
+        .map_err(|e| actix_web::error::ErrorBadRequest(e.to_string()))?;
+
+    let archived = is_archived(pool.get_ref(), channel_id)
+        .await
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+    if archived {
+        return Err(actix_web::error::ErrorConflict("Channel is archived"));
+    }
+
+    if !member.is_admin() {
+        let remaining = slow_mode_wait(pool.get_ref(), channel_id, user.id, None)
+            .await
+            .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+        if let Some(remaining) = remaining {
+            let seconds = retry_after_secs(remaining);
+            return Ok(HttpResponse::TooManyRequests()
+                .insert_header((header::RETRY_AFTER, seconds.to_string()))
+                .json(ErrorResponse::new(format!(
+                    "Slow mode is on, wait {} seconds",
+                    seconds
+                ))));
+        }
+    }
+
+    validate_attachments(&body.attachments).map_err(actix_web::error::ErrorBadRequest)?;
+
+    let content = word_filter()
+        .apply(&body.content)
+        .map(|content| sanitize_content(&content))
+        .map_err(|_| actix_web::error::ErrorBadRequest("Message contains blocked words"))?;
+
+    // shares the socket's double-submit window, so a retry over either path is caught
+    if server
+        .is_duplicate(user.id, channel_id, &content, &body.attachments)
+        .await
+    {
+        return Err(actix_web::error::ErrorConflict("Duplicate message"));
+    }
+
+    let message = insert_message_with_retry(
+        pool.get_ref(),
+        channel_id,
+        user.id,
+        &content,
+        &body.attachments,
+        body.client_sent_at,
+        body.format,
+    )
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to send message"))?;
+
+    server.broadcast(
+        channel_id,
+        WsMessage::ChatMessage {
+            id: message.id,
+            user_id: user.id,
+            username: user.username.clone(),
+            content: message.content.clone(),
+            created_at: message.created_at,
+            client_sent_at: message.client_sent_at,
+            attachments: body.attachments.clone(),
+            kind: message.kind,
+            format: message.format,
+        },
+    );
+
+    Ok(HttpResponse::Created().json(MessageResponse {
+        id: message.id,
+        channel_id,
+        user_id: user.id,
+        username: user.username,
+        content: message.content,
+        created_at: message.created_at,
+        client_sent_at: message.client_sent_at,
+        edited_at: message.edited_at,
+        version: message.version,
+        kind: message.kind,
+        format: message.format,
+        attachments: body.attachments,
+    }))
+}
+
+pub async fn edit_message(
+    pool: web::Data<PgPool>,
+    server: web::Data<ChatServerHandle>,
+    member: ChannelMember,
+    path: web::Path<(Uuid, Uuid)>,
+    body: web::Json<EditMessageRequest>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (channel_id, message_id) = path.into_inner();
+
+    // edits never touch attachments, so the text can't be emptied
+    validate_message_content(&body.content, false)
+        .map_err(|e| actix_web::error::ErrorBadRequest(e.to_string()))?;
+
+    let content = word_filter()
+        .apply(&body.content)
+        .map(|content| sanitize_content(&content))
+        .map_err(|_| actix_web::error::ErrorBadRequest("Message contains blocked words"))?;
+
+    let mut tx = transaction::begin(pool.get_ref()).await?;
+
+    let (author_id, old_content, version) = lock_message(&mut tx, channel_id, message_id).await?;
+
+    if author_id != member.user_id {
+        return Err(actix_web::error::ErrorForbidden(
+            "You can only edit your own messages",
+        ));
+    }
+
+    if version != body.expected_version {
+        return Err(actix_web::error::ErrorConflict(format!(
+            "Message has changed since version {}, it is now at version {}",
+            body.expected_version, version
+        )));
+    }
+
+    record_message_edit(&mut tx, message_id, member.user_id, "edit", &old_content).await?;
+
+    let message = sqlx::query_as::<_, Message>(
+        r#"
+        UPDATE messages
+        SET content = $1, edited_at = NOW(), version = version + 1
+        WHERE id = $2
+        RETURNING id, channel_id, user_id, content, created_at, client_sent_at, edited_at,
+            version, kind, format
+        "#,
+    )
+    .bind(&content)
+    .bind(message_id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to edit message"))?;
+
+    transaction::commit(tx).await?;
+
+    server.broadcast(
+        channel_id,
+        WsMessage::MessageEdited {
+            id: message.id,
+            channel_id,
+            content: message.content.clone(),
+            edited_at: message.edited_at.unwrap_or_else(Utc::now),
+            version: message.version,
+        },
+    );
+
+    Ok(HttpResponse::Ok().json(message))
+}
+
+pub async fn delete_message(
+    pool: web::Data<PgPool>,
+    server: web::Data<ChatServerHandle>,
+    member: ChannelMember,
+    path: web::Path<(Uuid, Uuid)>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (channel_id, message_id) = path.into_inner();
+
+    let mut tx = transaction::begin(pool.get_ref()).await?;
+
+    let (author_id, old_content, _) = lock_message(&mut tx, channel_id, message_id).await?;
+
+    if author_id != member.user_id && !member.is_admin() {
+        return Err(actix_web::error::ErrorForbidden(
+            "Only the author or an admin can delete this message",
+        ));
+    }
+
+    record_message_edit(&mut tx, message_id, member.user_id, "delete", &old_content).await?;
+
+    // the row stays so history and read positions keep pointing at it
+    sqlx::query(
+        r#"
+        UPDATE messages
+        SET content = '', deleted_at = NOW()
+        WHERE id = $1
+        "#,
+    )
+    .bind(message_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to delete message"))?;
+
+    transaction::commit(tx).await?;
+
+    server.broadcast(
+        channel_id,
+        WsMessage::MessageDeleted {
+            id: message_id,
+            channel_id,
+        },
+    );
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn get_message_history(
+    pool: web::Data<PgPool>,
+    member: ChannelMember,
+    path: web::Path<(Uuid, Uuid)>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (channel_id, message_id) = path.into_inner();
+
+    if !member.is_admin() {
+        return Err(actix_web::error::ErrorForbidden(
+            "Only admins can view message history",
+        ));
+    }
+
+    // deleted messages are included, that's the point of keeping history
+    let exists = sqlx::query_scalar::<_, bool>(
+        r#"
+        SELECT EXISTS(
+            SELECT 1 FROM messages
+            WHERE id = $1 AND channel_id = $2
+        )
+        "#,
+    )
+    .bind(message_id)
+    .bind(channel_id)
+    .fetch_one(pool.get_ref())
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+
+    if !exists {
+        return Err(actix_web::error::ErrorNotFound("Message not found"));
+    }
+
+    let history = sqlx::query_as::<_, MessageEditResponse>(
+        r#"
+        SELECT e.id, e.message_id, e.editor_id, u.username AS editor_username,
+            e.action, e.old_content, e.created_at
+        FROM message_edits e
+        INNER JOIN users u ON e.editor_id = u.id
+        WHERE e.message_id = $1
+        ORDER BY e.created_at, e.id
+        "#,
+    )
+    .bind(message_id)
+    .fetch_all(pool.get_ref())
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to fetch history"))?;
+
+    Ok(HttpResponse::Ok().json(history))
+}
+
+pub async fn archive_channel(
+    pool: web::Data<PgPool>,
+    member: ChannelMember,
+) -> Result<HttpResponse, actix_web::Error> {
+    set_archived(pool.get_ref(), member, true).await
+}
+
+pub async fn unarchive_channel(
+    pool: web::Data<PgPool>,
+    member: ChannelMember,
+) -> Result<HttpResponse, actix_web::Error> {
+    set_archived(pool.get_ref(), member, false).await
+}
+
+async fn set_archived(
+    pool: &PgPool,
+    member: ChannelMember,
+    archived: bool,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !member.is_admin() {
+        return Err(actix_web::error::ErrorForbidden(
+            "Only admins can archive channels",
+        ));
+    }
+
+    // archiving twice keeps the original timestamp
+    let channel = sqlx::query_as::<_, Channel>(
+        r#"
+        UPDATE channels
+        SET archived_at = CASE WHEN $2 THEN COALESCE(archived_at, NOW()) ELSE NULL END
+        WHERE id = $1
+        RETURNING id, name, description, topic, created_by, created_at, archived_at,
+            retention_days, is_public, max_members, slow_mode_seconds, invite_policy
+        "#,
+    )
+    .bind(member.channel_id)
+    .bind(archived)
+    .fetch_optional(pool)
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to update channel"))?
+    .ok_or_else(|| actix_web::error::ErrorNotFound("Channel not found"))?;
+
+    Ok(HttpResponse::Ok().json(channel))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        DEFAULT_MAX_CHANNELS_PER_USER, DEFAULT_MAX_PINNED_MESSAGES, MAX_CHANNEL_NAME_LENGTH,
+    };
+    use crate::{
+        handlers::websocket::open_session,
+        models::role::Role,
+        test_support::{
+            add_member, app, create_channel, create_user, insert_message, next_frame, start_server,
+            TestUser,
+        },
+    };
+    use actix_web::{http::StatusCode, test};
+    use serde_json::{json, Value};
+    use sqlx::PgPool;
+    use uuid::Uuid;
+
+    #[sqlx::test]
+    async fn rest_post_reaches_live_sessions(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+        add_member(&pool, channel_id, &bob, Role::Member).await;
+        let (_, mut bob_rx) = open_session(&server, bob.id, "bob", channel_id, Role::Member);
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/api/channels/{}/messages", channel_id))
+            .insert_header(alice.bearer())
+            .set_json(json!({ "content": "hello from the api" }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["username"], "alice");
+
+        let frame = next_frame(&mut bob_rx, "chat").await;
+        assert_eq!(frame["id"], body["id"]);
+        assert_eq!(frame["content"], "hello from the api");
+        assert_eq!(frame["username"], "alice");
+    }
+
+    #[sqlx::test]
+    async fn rest_post_rejects_a_double_submit(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+
+        let post = || {
+            test::TestRequest::post()
+                .uri(&format!("/api/channels/{}/messages", channel_id))
+                .insert_header(alice.bearer())
+                .set_json(json!({ "content": "hello" }))
+                .to_request()
+        };
+        assert_eq!(
+            test::call_service(&app, post()).await.status(),
+            StatusCode::CREATED
+        );
+        assert_eq!(
+            test::call_service(&app, post()).await.status(),
+            StatusCode::CONFLICT
+        );
+
+        let stored =
+            sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM messages WHERE channel_id = $1")
+                .bind(channel_id)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(stored, 1);
+    }
+
+    #[sqlx::test]
+    async fn viewers_cannot_post_over_rest(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let viewer = create_user(&pool, "viewer").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+        add_member(&pool, channel_id, &viewer, Role::Viewer).await;
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/api/channels/{}/messages", channel_id))
+            .insert_header(viewer.bearer())
+            .set_json(json!({ "content": "hello" }))
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[sqlx::test]
+    async fn attachments_are_stored_with_the_message(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+        let png = json!({ "url": "https://cdn.example.com/a.png", "mime_type": "image/png", "size": 2048 });
+
+        for body in [
+            json!({ "content": "with a picture", "attachments": [png] }),
+            json!({ "content": "just text" }),
+        ] {
+            let req = test::TestRequest::post()
+                .uri(&format!("/api/channels/{}/messages", channel_id))
+                .insert_header(alice.bearer())
+                .set_json(body)
+                .to_request();
+            assert_eq!(
+                test::call_service(&app, req).await.status(),
+                StatusCode::CREATED
+            );
+        }
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/api/channels/{}/messages", channel_id))
+            .insert_header(alice.bearer())
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        let messages = body.as_array().unwrap();
+        let with = messages
+            .iter()
+            .find(|m| m["content"] == "with a picture")
+            .unwrap();
+        let without = messages
+            .iter()
+            .find(|m| m["content"] == "just text")
+            .unwrap();
+        assert_eq!(with["attachments"], json!([png]));
+        assert_eq!(without["attachments"], json!([]));
+    }
+
+    #[sqlx::test]
+    async fn disallowed_attachment_type_is_rejected(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/api/channels/{}/messages", channel_id))
+            .insert_header(alice.bearer())
+            .set_json(json!({
+                "content": "run me",
+                "attachments": [{ "url": "https://cdn.example.com/a.exe", "mime_type": "application/x-msdownload", "size": 2048 }],
+            }))
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::BAD_REQUEST
+        );
+
+        let stored = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM attachments")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(stored, 0);
+    }
+
+    #[sqlx::test]
+    async fn channel_list_previews_the_latest_message(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let busy = create_channel(&pool, &alice, "busy").await;
+        let quiet = create_channel(&pool, &alice, "quiet").await;
+
+        let older = insert_message(&pool, busy, &alice, "first").await;
+        sqlx::query("UPDATE messages SET created_at = NOW() - INTERVAL '1 minute' WHERE id = $1")
+            .bind(older)
+            .execute(&pool)
+            .await
+            .unwrap();
+        let latest = insert_message(&pool, busy, &alice, &"x".repeat(150)).await;
+
+        let req = test::TestRequest::get()
+            .uri("/api/channels")
+            .insert_header(alice.bearer())
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        let channel = |id: Uuid| {
+            body.as_array()
+                .unwrap()
+                .iter()
+                .find(|channel| channel["id"] == id.to_string())
+                .unwrap()
+                .clone()
+        };
+
+        let preview = &channel(busy)["last_message"];
+        assert_eq!(preview["id"], latest.to_string());
+        assert_eq!(preview["username"], "alice");
+        assert_eq!(preview["content"], "x".repeat(100));
+        assert_eq!(channel(quiet)["last_message"], Value::Null);
+    }
+
+    #[sqlx::test]
+    async fn channel_cap_allows_the_last_channel_and_refuses_the_next(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        sqlx::query(
+            "INSERT INTO channels (name, created_by) SELECT 'seed-' || n, $1 FROM generate_series(1, $2) n",
+        )
+        .bind(alice.id)
+        .bind(DEFAULT_MAX_CHANNELS_PER_USER - 1)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        for (name, status) in [
+            ("last", StatusCode::OK),
+            ("one-too-many", StatusCode::CONFLICT),
+        ] {
+            let req = test::TestRequest::post()
+                .uri("/api/channels")
+                .insert_header(alice.bearer())
+                .set_json(json!({ "name": name }))
+                .to_request();
+            assert_eq!(
+                test::call_service(&app, req).await.status(),
+                status,
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[sqlx::test]
+    async fn description_and_topic_are_stored_and_topic_changes_broadcast(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+
+        let req = test::TestRequest::post()
+            .uri("/api/channels")
+            .insert_header(alice.bearer())
+            .set_json(json!({ "name": "general", "description": "Anything goes" }))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["description"], "Anything goes");
+        assert_eq!(body["topic"], Value::Null);
+        let channel_id: Uuid = body["id"].as_str().unwrap().parse().unwrap();
+
+        let (_, mut alice_rx) = open_session(&server, alice.id, "alice", channel_id, Role::Admin);
+        let req = test::TestRequest::patch()
+            .uri(&format!("/api/channels/{}", channel_id))
+            .insert_header(alice.bearer())
+            .set_json(json!({ "topic": "Release week" }))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["topic"], "Release week");
+        assert_eq!(body["description"], "Anything goes");
+
+        let frame = next_frame(&mut alice_rx, "channel_updated").await;
+        assert_eq!(frame["channel_id"], channel_id.to_string());
+        assert_eq!(frame["topic"], "Release week");
+        assert_eq!(frame["description"], "Anything goes");
+    }
+
+    #[sqlx::test]
+    async fn channel_list_envelope_pages_to_the_end(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        for name in ["one", "two", "three"] {
+            create_channel(&pool, &alice, name).await;
+        }
+
+        let page = |cursor: &str| {
+            test::TestRequest::get()
+                .uri(&format!("/api/channels?envelope=true&limit=2{}", cursor))
+                .insert_header(alice.bearer())
+                .to_request()
+        };
+
+        let body: Value = test::call_and_read_body_json(&app, page("")).await;
+        assert_eq!(body["data"].as_array().unwrap().len(), 2);
+        assert_eq!(body["page_info"]["has_more"], true);
+        let cursor = body["page_info"]["next_cursor"]
+            .as_str()
+            .unwrap()
+            .to_string();
+
+        let body: Value =
+            test::call_and_read_body_json(&app, page(&format!("&cursor={}", cursor))).await;
+        assert_eq!(body["data"].as_array().unwrap().len(), 1);
+        assert_eq!(body["page_info"]["has_more"], false);
+        assert_eq!(body["page_info"]["next_cursor"], Value::Null);
+
+        // without the flag the list stays a bare array
+        let req = test::TestRequest::get()
+            .uri("/api/channels")
+            .insert_header(alice.bearer())
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body.as_array().unwrap().len(), 3);
+    }
+
+    #[sqlx::test]
+    async fn idempotency_key_replays_the_original_channel(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+
+        let create = |key: &str, name: &str| {
+            test::TestRequest::post()
+                .uri("/api/channels")
+                .insert_header(alice.bearer())
+                .insert_header(("Idempotency-Key", key.to_string()))
+                .set_json(json!({ "name": name }))
+                .to_request()
+        };
+
+        let first: Value = test::call_and_read_body_json(&app, create("key-1", "general")).await;
+        let replay: Value = test::call_and_read_body_json(&app, create("key-1", "general")).await;
+        assert_eq!(replay["id"], first["id"]);
+
+        let other: Value = test::call_and_read_body_json(&app, create("key-2", "random")).await;
+        assert_ne!(other["id"], first["id"]);
+
+        // an expired key creates afresh
+        sqlx::query("UPDATE idempotency_keys SET created_at = NOW() - INTERVAL '25 hours'")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let fresh: Value = test::call_and_read_body_json(&app, create("key-1", "fresh")).await;
+        assert_ne!(fresh["id"], first["id"]);
+
+        let channels = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM channels")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(channels, 3);
+    }
+
+    #[sqlx::test]
+    async fn member_summary_counts_roles_for_admins_only(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+        let carol = create_user(&pool, "carol").await;
+        let viewer = create_user(&pool, "viewer").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+        add_member(&pool, channel_id, &bob, Role::Member).await;
+        add_member(&pool, channel_id, &carol, Role::Member).await;
+        add_member(&pool, channel_id, &viewer, Role::Viewer).await;
+
+        let summary = |user: &TestUser| {
+            test::TestRequest::get()
+                .uri(&format!("/api/channels/{}/members/summary", channel_id))
+                .insert_header(user.bearer())
+                .to_request()
+        };
+        let body: Value = test::call_and_read_body_json(&app, summary(&alice)).await;
+        assert_eq!(body["total"], 4);
+        let counts: Vec<(&str, i64)> = body["roles"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| {
+                (
+                    entry["role"].as_str().unwrap(),
+                    entry["count"].as_i64().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(counts, [("admin", 1), ("member", 2), ("viewer", 1)]);
+        let members = body["members"].as_array().unwrap();
+        assert_eq!(members.len(), 4);
+        assert!(members.iter().all(|member| member["joined_at"].is_string()));
+
+        assert_eq!(
+            test::call_service(&app, summary(&bob)).await.status(),
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[sqlx::test]
+    async fn client_timestamp_is_kept_but_server_time_orders(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+
+        // the client clock claims the first message was sent after the second
+        for (content, client_sent_at) in [
+            ("first", "2099-01-01T00:00:00Z"),
+            ("second", "2000-01-01T00:00:00Z"),
+        ] {
+            let req = test::TestRequest::post()
+                .uri(&format!("/api/channels/{}/messages", channel_id))
+                .insert_header(alice.bearer())
+                .set_json(json!({ "content": content, "client_sent_at": client_sent_at }))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["client_sent_at"], client_sent_at);
+            assert!(body["created_at"].is_string());
+        }
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/api/channels/{}/messages", channel_id))
+            .insert_header(alice.bearer())
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        let contents: Vec<&str> = body
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|message| message["content"].as_str().unwrap())
+            .collect();
+        assert_eq!(contents, ["second", "first"]);
+        assert_eq!(body[1]["client_sent_at"], "2099-01-01T00:00:00Z");
+    }
+
+    #[sqlx::test]
+    async fn edits_and_deletes_are_kept_as_history_for_admins(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+        add_member(&pool, channel_id, &bob, Role::Member).await;
+        let message_id = insert_message(&pool, channel_id, &bob, "first draft").await;
+        let uri = format!("/api/channels/{}/messages/{}", channel_id, message_id);
+
+        let req = test::TestRequest::patch()
+            .uri(&uri)
+            .insert_header(bob.bearer())
+            .set_json(json!({ "content": "second draft", "expected_version": 1 }))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+        let req = test::TestRequest::delete()
+            .uri(&uri)
+            .insert_header(bob.bearer())
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+
+        let history = |user: &TestUser| {
+            test::TestRequest::get()
+                .uri(&format!("{}/history", uri))
+                .insert_header(user.bearer())
+                .to_request()
+        };
+        let body: Value = test::call_and_read_body_json(&app, history(&alice)).await;
+        let entries: Vec<(&str, &str)> = body
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| {
+                (
+                    entry["action"].as_str().unwrap(),
+                    entry["old_content"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            entries,
+            [("edit", "first draft"), ("delete", "second draft")]
+        );
+        assert_eq!(body[0]["editor_username"], "bob");
+
+        // the author of the message isn't enough
+        assert_eq!(
+            test::call_service(&app, history(&bob)).await.status(),
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[sqlx::test]
+    async fn archiving_hides_the_channel_and_stops_posts_until_unarchived(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+        add_member(&pool, channel_id, &bob, Role::Member).await;
+
+        let toggle = |user: &TestUser, action: &str| {
+            test::TestRequest::post()
+                .uri(&format!("/api/channels/{}/{}", channel_id, action))
+                .insert_header(user.bearer())
+                .to_request()
+        };
+        let listed = |query: &str| {
+            test::TestRequest::get()
+                .uri(&format!("/api/channels{}", query))
+                .insert_header(alice.bearer())
+                .to_request()
+        };
+        let post = |content: &str| {
+            test::TestRequest::post()
+                .uri(&format!("/api/channels/{}/messages", channel_id))
+                .insert_header(alice.bearer())
+                .set_json(json!({ "content": content }))
+                .to_request()
+        };
+
+        assert_eq!(
+            test::call_service(&app, toggle(&bob, "archive"))
+                .await
+                .status(),
+            StatusCode::FORBIDDEN
+        );
+        let channel: Value = test::call_and_read_body_json(&app, toggle(&alice, "archive")).await;
+        assert!(channel["archived_at"].is_string());
+
+        let body: Value = test::call_and_read_body_json(&app, listed("")).await;
+        assert_eq!(body.as_array().unwrap().len(), 0);
+        let body: Value =
+            test::call_and_read_body_json(&app, listed("?include_archived=true")).await;
+        assert_eq!(body[0]["id"], channel_id.to_string());
+        assert_eq!(
+            test::call_service(&app, post("while archived"))
+                .await
+                .status(),
+            StatusCode::CONFLICT
+        );
+
+        let channel: Value = test::call_and_read_body_json(&app, toggle(&alice, "unarchive")).await;
+        assert_eq!(channel["archived_at"], Value::Null);
+        let body: Value = test::call_and_read_body_json(&app, listed("")).await;
+        assert_eq!(body.as_array().unwrap().len(), 1);
+        assert_eq!(
+            test::call_service(&app, post("back again")).await.status(),
+            StatusCode::CREATED
+        );
+    }
+
+    #[sqlx::test]
+    async fn invalid_channel_names_are_refused_on_create_and_rename(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+
+        for name in [
+            " \u{7} ".to_string(),
+            "x".repeat(MAX_CHANNEL_NAME_LENGTH + 1),
+        ] {
+            let req = test::TestRequest::post()
+                .uri("/api/channels")
+                .insert_header(alice.bearer())
+                .set_json(json!({ "name": name }))
+                .to_request();
+            assert_eq!(
+                test::call_service(&app, req).await.status(),
+                StatusCode::BAD_REQUEST
+            );
+
+            let req = test::TestRequest::patch()
+                .uri(&format!("/api/channels/{}", channel_id))
+                .insert_header(alice.bearer())
+                .set_json(json!({ "name": name }))
+                .to_request();
+            assert_eq!(
+                test::call_service(&app, req).await.status(),
+                StatusCode::BAD_REQUEST
+            );
+        }
+
+        let req = test::TestRequest::patch()
+            .uri(&format!("/api/channels/{}", channel_id))
+            .insert_header(alice.bearer())
+            .set_json(json!({ "name": "  ran\u{0}dom  " }))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["name"], "random");
+    }
+
+    #[sqlx::test]
+    async fn edits_and_deletes_reach_live_sessions(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+        add_member(&pool, channel_id, &bob, Role::Member).await;
+        let message_id = insert_message(&pool, channel_id, &alice, "helo").await;
+        let (_, mut bob_rx) = open_session(&server, bob.id, "bob", channel_id, Role::Member);
+        let uri = format!("/api/channels/{}/messages/{}", channel_id, message_id);
+
+        let req = test::TestRequest::patch()
+            .uri(&uri)
+            .insert_header(alice.bearer())
+            .set_json(json!({ "content": "hello", "expected_version": 1 }))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+        let frame = next_frame(&mut bob_rx, "message_edited").await;
+        assert_eq!(frame["id"], message_id.to_string());
+        assert_eq!(frame["content"], "hello");
+        assert_eq!(frame["version"], 2);
+
+        let req = test::TestRequest::delete()
+            .uri(&uri)
+            .insert_header(alice.bearer())
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        let frame = next_frame(&mut bob_rx, "message_deleted").await;
+        assert_eq!(frame["id"], message_id.to_string());
+        assert_eq!(frame["channel_id"], channel_id.to_string());
+    }
+
+    #[sqlx::test]
+    async fn guests_can_read_public_channels_but_not_write(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let public = create_channel(&pool, &alice, "lobby").await;
+        let private = create_channel(&pool, &alice, "secret").await;
+        sqlx::query("UPDATE channels SET is_public = TRUE WHERE id = $1")
+            .bind(public)
+            .execute(&pool)
+            .await
+            .unwrap();
+        insert_message(&pool, public, &alice, "welcome").await;
+        insert_message(&pool, private, &alice, "hush").await;
+
+        let req = test::TestRequest::get()
+            .uri("/api/channels/public")
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        let listed: Vec<&str> = body
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|channel| channel["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(listed, ["lobby"]);
+
+        let messages = |channel_id: Uuid| {
+            test::TestRequest::get()
+                .uri(&format!("/api/channels/{}/messages", channel_id))
+                .to_request()
+        };
+        let body: Value = test::call_and_read_body_json(&app, messages(public)).await;
+        assert_eq!(body[0]["content"], "welcome");
+        assert!(!test::call_service(&app, messages(private))
+            .await
+            .status()
+            .is_success());
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/api/channels/{}/messages", public))
+            .set_json(json!({ "content": "hello from nowhere" }))
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[sqlx::test]
+    async fn message_cursors_walk_both_ways_without_gaps(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+        for i in 1..=5 {
+            let id = insert_message(&pool, channel_id, &alice, &format!("m{}", i)).await;
+            sqlx::query(
+                "UPDATE messages SET created_at = NOW() - make_interval(mins => 10 - $1) WHERE id = $2",
+            )
+            .bind(i)
+            .bind(id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        let page = |query: String| {
+            let req = test::TestRequest::get()
+                .uri(&format!(
+                    "/api/channels/{}/messages?envelope=true&limit=2{}",
+                    channel_id, query
+                ))
+                .insert_header(alice.bearer())
+                .to_request();
+            test::call_and_read_body_json::<_, _, Value>(&app, req)
+        };
+        let contents = |page: &Value| -> Vec<String> {
+            page["data"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|message| message["content"].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        let mut seen = Vec::new();
+        let mut query = String::new();
+        let last = loop {
+            let body = page(query).await;
+            seen.extend(contents(&body));
+            match body["page_info"]["next_cursor"].as_str() {
+                Some(cursor) => query = format!("&cursor={}", cursor),
+                None => break body,
+            }
+        };
+        assert_eq!(seen, ["m5", "m4", "m3", "m2", "m1"]);
+        assert_eq!(last["page_info"]["has_more"], false);
+
+        // and back up from the oldest page
+        let cursor = last["page_info"]["prev_cursor"].as_str().unwrap();
+        let body = page(format!("&direction=newer&cursor={}", cursor)).await;
+        assert_eq!(contents(&body), ["m3", "m2"]);
+        let cursor = body["page_info"]["next_cursor"].as_str().unwrap();
+        let body = page(format!("&direction=newer&cursor={}", cursor)).await;
+        assert_eq!(contents(&body), ["m5", "m4"]);
+        assert_eq!(body["page_info"]["has_more"], false);
+    }
+
+    #[sqlx::test]
+    async fn stale_edits_conflict_instead_of_overwriting(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+        let message_id = insert_message(&pool, channel_id, &alice, "draft").await;
+        let edit = |content: &str, expected_version: i32| {
+            test::TestRequest::patch()
+                .uri(&format!(
+                    "/api/channels/{}/messages/{}",
+                    channel_id, message_id
+                ))
+                .insert_header(alice.bearer())
+                .set_json(json!({ "content": content, "expected_version": expected_version }))
+                .to_request()
+        };
+
+        let message: Value = test::call_and_read_body_json(&app, edit("from tab one", 1)).await;
+        assert_eq!(message["version"], 2);
+        assert_eq!(message["content"], "from tab one");
+
+        // a second tab still holding version 1
+        assert_eq!(
+            test::call_service(&app, edit("from tab two", 1))
+                .await
+                .status(),
+            StatusCode::CONFLICT
+        );
+        let stored: String = sqlx::query_scalar("SELECT content FROM messages WHERE id = $1")
+            .bind(message_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(stored, "from tab one");
+
+        let message: Value = test::call_and_read_body_json(&app, edit("from tab two", 2)).await;
+        assert_eq!(message["version"], 3);
+    }
+
+    #[sqlx::test]
+    async fn renaming_leaves_a_system_message_in_history(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+        insert_message(&pool, channel_id, &alice, "before the rename").await;
+        let (_, mut rx) = open_session(&server, alice.id, "alice", channel_id, Role::Admin);
+
+        let rename = |name: &str| {
+            test::TestRequest::patch()
+                .uri(&format!("/api/channels/{}", channel_id))
+                .insert_header(alice.bearer())
+                .set_json(json!({ "name": name }))
+                .to_request()
+        };
+        assert_eq!(
+            test::call_service(&app, rename("lobby")).await.status(),
+            StatusCode::OK
+        );
+        // same name again isn't a rename
+        assert_eq!(
+            test::call_service(&app, rename("lobby")).await.status(),
+            StatusCode::OK
+        );
+
+        let frame = next_frame(&mut rx, "chat").await;
+        assert_eq!(frame["kind"], "system");
+        assert_eq!(frame["content"], "alice renamed the channel to lobby");
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/api/channels/{}/messages", channel_id))
+            .insert_header(alice.bearer())
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        let messages: Vec<(&str, &str)> = body
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|message| {
+                (
+                    message["kind"].as_str().unwrap(),
+                    message["content"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                ("system", "alice renamed the channel to lobby"),
+                ("user", "before the rename"),
+            ]
+        );
+    }
+
+    #[sqlx::test]
+    async fn stats_count_recent_user_messages_for_admins(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+        add_member(&pool, channel_id, &bob, Role::Member).await;
+
+        for content in ["one", "two", "three"] {
+            insert_message(&pool, channel_id, &alice, content).await;
+        }
+        insert_message(&pool, channel_id, &bob, "today").await;
+        let backdate = |id: Uuid, days: i32| {
+            sqlx::query(
+                "UPDATE messages SET created_at = NOW() - make_interval(days => $2) WHERE id = $1",
+            )
+            .bind(id)
+            .bind(days)
+            .execute(&pool)
+        };
+        let earlier = insert_message(&pool, channel_id, &bob, "earlier").await;
+        backdate(earlier, 2).await.unwrap();
+        let ancient = insert_message(&pool, channel_id, &bob, "too old").await;
+        backdate(ancient, 40).await.unwrap();
+        let deleted = insert_message(&pool, channel_id, &bob, "deleted").await;
+        sqlx::query("UPDATE messages SET deleted_at = NOW() WHERE id = $1")
+            .bind(deleted)
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO messages (channel_id, user_id, content, kind) VALUES ($1, $2, 'joined', 'system')")
+            .bind(channel_id)
+            .bind(bob.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let stats = |user: &TestUser| {
+            test::TestRequest::get()
+                .uri(&format!("/api/channels/{}/stats", channel_id))
+                .insert_header(user.bearer())
+                .to_request()
+        };
+        assert_eq!(
+            test::call_service(&app, stats(&bob)).await.status(),
+            StatusCode::FORBIDDEN
+        );
+
+        let body: Value = test::call_and_read_body_json(&app, stats(&alice)).await;
+        assert_eq!(body["member_count"], 2);
+        assert_eq!(body["message_count"], 5);
+        let most_active: Vec<(&str, i64)> = body["most_active"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|member| {
+                (
+                    member["username"].as_str().unwrap(),
+                    member["message_count"].as_i64().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(most_active, [("alice", 3), ("bob", 2)]);
+        let per_day: Vec<i64> = body["messages_per_day"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|day| day["count"].as_i64().unwrap())
+            .collect();
+        assert_eq!(per_day, [1, 4]);
+    }
+
+    #[sqlx::test]
+    async fn creator_is_admin_unless_another_admin_is_named(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+        let create = |body: Value| {
+            test::TestRequest::post()
+                .uri("/api/channels")
+                .insert_header(alice.bearer())
+                .set_json(body)
+                .to_request()
+        };
+        let role_of = |channel_id: &str, user: &TestUser| {
+            sqlx::query_scalar::<_, Role>(
+                "SELECT role FROM channel_members WHERE channel_id = $1 AND user_id = $2",
+            )
+            .bind(Uuid::parse_str(channel_id).unwrap())
+            .bind(user.id)
+            .fetch_one(&pool)
+        };
+
+        let channel: Value =
+            test::call_and_read_body_json(&app, create(json!({ "name": "general" }))).await;
+        assert_eq!(channel["role"], "admin");
+        let id = channel["id"].as_str().unwrap();
+        assert_eq!(role_of(id, &alice).await.unwrap(), Role::Admin);
+
+        for body in [
+            json!({ "name": "managed", "creator_role": "member" }),
+            json!({ "name": "managed", "creator_role": "member", "admin_id": alice.id }),
+            json!({ "name": "managed", "creator_role": "member", "admin_id": Uuid::new_v4() }),
+        ] {
+            assert_eq!(
+                test::call_service(&app, create(body)).await.status(),
+                StatusCode::BAD_REQUEST
+            );
+        }
+
+        let channel: Value = test::call_and_read_body_json(
+            &app,
+            create(json!({ "name": "managed", "creator_role": "member", "admin_id": bob.id })),
+        )
+        .await;
+        assert_eq!(channel["role"], "member");
+        let id = channel["id"].as_str().unwrap();
+        assert_eq!(role_of(id, &alice).await.unwrap(), Role::Member);
+        assert_eq!(role_of(id, &bob).await.unwrap(), Role::Admin);
+    }
+
+    #[sqlx::test]
+    async fn muting_is_reported_but_messages_still_arrive(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+        add_member(&pool, channel_id, &bob, Role::Member).await;
+        let (_, mut bob_rx) = open_session(&server, bob.id, "bob", channel_id, Role::Member);
+
+        let req = test::TestRequest::put()
+            .uri(&format!("/api/channels/{}/preferences", channel_id))
+            .insert_header(bob.bearer())
+            .set_json(json!({ "muted": true }))
+            .to_request();
+        let preferences: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(preferences["muted"], true);
+
+        let listed = |user: &TestUser| {
+            test::TestRequest::get()
+                .uri("/api/channels")
+                .insert_header(user.bearer())
+                .to_request()
+        };
+        let body: Value = test::call_and_read_body_json(&app, listed(&bob)).await;
+        assert_eq!(body[0]["muted"], true);
+        // the preference is per user
+        let body: Value = test::call_and_read_body_json(&app, listed(&alice)).await;
+        assert_eq!(body[0]["muted"], false);
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/api/channels/{}/messages", channel_id))
+            .insert_header(alice.bearer())
+            .set_json(json!({ "content": "@bob still here?" }))
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::CREATED
+        );
+        let frame = next_frame(&mut bob_rx, "chat").await;
+        assert_eq!(frame["content"], "@bob still here?");
+    }
+
+    #[sqlx::test]
+    async fn export_streams_json_or_csv_for_admins(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+        add_member(&pool, channel_id, &bob, Role::Member).await;
+        let first = insert_message(&pool, channel_id, &alice, "hello").await;
+        insert_message(&pool, channel_id, &bob, "say \"hi\", alice").await;
+        let deleted = insert_message(&pool, channel_id, &bob, "oops").await;
+        sqlx::query("UPDATE messages SET deleted_at = NOW() WHERE id = $1")
+            .bind(deleted)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let export = |user: &TestUser, format: &str| {
+            test::TestRequest::get()
+                .uri(&format!(
+                    "/api/channels/{}/export?format={}",
+                    channel_id, format
+                ))
+                .insert_header(user.bearer())
+                .to_request()
+        };
+        assert_eq!(
+            test::call_service(&app, export(&bob, "json"))
+                .await
+                .status(),
+            StatusCode::FORBIDDEN
+        );
+
+        let resp = test::call_service(&app, export(&alice, "json")).await;
+        assert_eq!(
+            resp.headers().get("content-type").unwrap(),
+            "application/json"
+        );
+        assert!(resp
+            .headers()
+            .get("content-disposition")
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .ends_with(&format!("channel-{}.json\"", channel_id)));
+        let body: Value = test::read_body_json(resp).await;
+        let rows: Vec<(&str, &str)> = body
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|row| {
+                (
+                    row["username"].as_str().unwrap(),
+                    row["content"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(rows, [("alice", "hello"), ("bob", "say \"hi\", alice")]);
+        assert_eq!(body[0]["id"], first.to_string());
+        assert!(body[0]["created_at"].is_string());
+
+        let resp = test::call_service(&app, export(&alice, "csv")).await;
+        assert_eq!(
+            resp.headers().get("content-type").unwrap(),
+            "text/csv; charset=utf-8"
+        );
+        let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+        let lines: Vec<&str> = body.split_terminator("\r\n").collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[0],
+            "id,user_id,username,content,created_at,edited_at,kind"
+        );
+        assert!(lines[1].starts_with(&format!("{},{},alice,hello,", first, alice.id)));
+        assert!(lines[1].ends_with(",,user"));
+        assert!(lines[2].contains(",bob,\"say \"\"hi\"\", alice\","));
+    }
+
+    #[sqlx::test]
+    async fn history_pages_break_timestamp_ties_and_skip_deleted(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+        let mut live = Vec::new();
+        for i in 0..7 {
+            let id = insert_message(&pool, channel_id, &alice, &format!("m{}", i)).await;
+            if i % 3 == 1 {
+                sqlx::query("UPDATE messages SET deleted_at = NOW() WHERE id = $1")
+                    .bind(id)
+                    .execute(&pool)
+                    .await
+                    .unwrap();
+            } else {
+                live.push(id.to_string());
+            }
+        }
+        // every message at the same instant leaves only the id to order by
+        sqlx::query(
+            "UPDATE messages SET created_at = '2024-01-01T00:00:00Z' WHERE channel_id = $1",
+        )
+        .bind(channel_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let mut seen = Vec::new();
+        let mut query = String::new();
+        loop {
+            let req = test::TestRequest::get()
+                .uri(&format!(
+                    "/api/channels/{}/messages?envelope=true&limit=2{}",
+                    channel_id, query
+                ))
+                .insert_header(alice.bearer())
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            for message in body["data"].as_array().unwrap() {
+                seen.push(message["id"].as_str().unwrap().to_string());
+            }
+            match body["page_info"]["next_cursor"].as_str() {
+                Some(cursor) => query = format!("&cursor={}", cursor),
+                None => break,
+            }
+        }
+
+        assert_eq!(seen.len(), live.len());
+        seen.sort();
+        live.sort();
+        assert_eq!(seen, live);
+    }
+
+    #[sqlx::test]
+    async fn bulk_roles_apply_together_but_keep_an_admin(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+        let carol = create_user(&pool, "carol").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+        add_member(&pool, channel_id, &bob, Role::Member).await;
+        add_member(&pool, channel_id, &carol, Role::Member).await;
+        let (_, mut rx) = open_session(&server, alice.id, "alice", channel_id, Role::Admin);
+
+        let update = |changes: Value| {
+            test::TestRequest::patch()
+                .uri(&format!("/api/channels/{}/members/roles", channel_id))
+                .insert_header(alice.bearer())
+                .set_json(changes)
+                .to_request()
+        };
+        let roles = || {
+            sqlx::query_as::<_, (Uuid, Role)>(
+                "SELECT user_id, role FROM channel_members WHERE channel_id = $1",
+            )
+            .bind(channel_id)
+            .fetch_all(&pool)
+        };
+
+        let updated: Value = test::call_and_read_body_json(
+            &app,
+            update(json!([
+                { "user_id": bob.id, "role": "admin" },
+                { "user_id": carol.id, "role": "viewer" },
+            ])),
+        )
+        .await;
+        assert_eq!(updated.as_array().unwrap().len(), 2);
+        let mut announced = Vec::new();
+        for _ in 0..2 {
+            let frame = next_frame(&mut rx, "role_changed").await;
+            announced.push((frame["user_id"].clone(), frame["role"].clone()));
+        }
+        announced.sort_by_key(|(_, role)| role.to_string());
+        assert_eq!(
+            announced,
+            [
+                (json!(bob.id), json!("admin")),
+                (json!(carol.id), json!("viewer")),
+            ]
+        );
+
+        // demoting every admin at once is refused and changes nothing
+        let mut before = roles().await.unwrap();
+        assert_eq!(
+            test::call_service(
+                &app,
+                update(json!([
+                    { "user_id": alice.id, "role": "member" },
+                    { "user_id": bob.id, "role": "member" },
+                ]))
+            )
+            .await
+            .status(),
+            StatusCode::CONFLICT
+        );
+        let mut after = roles().await.unwrap();
+        before.sort_by_key(|(user_id, _)| *user_id);
+        after.sort_by_key(|(user_id, _)| *user_id);
+        assert_eq!(before, after);
+    }
+
+    #[sqlx::test]
+    async fn pins_list_newest_first_and_stop_at_the_cap(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+
+        let mut pinned = Vec::new();
+        for i in 0..DEFAULT_MAX_PINNED_MESSAGES {
+            let id = insert_message(&pool, channel_id, &alice, &format!("m{}", i)).await;
+            sqlx::query(
+                "INSERT INTO pinned_messages (message_id, channel_id, pinned_by, pinned_at) \
+                 VALUES ($1, $2, $3, NOW() - make_interval(mins => $4))",
+            )
+            .bind(id)
+            .bind(channel_id)
+            .bind(alice.id)
+            .bind(i as i32)
+            .execute(&pool)
+            .await
+            .unwrap();
+            pinned.push(id);
+        }
+        let extra = insert_message(&pool, channel_id, &alice, "one too many").await;
+
+        let pin = |method: test::TestRequest, message_id: Uuid| {
+            method
+                .uri(&format!(
+                    "/api/channels/{}/messages/{}/pin",
+                    channel_id, message_id
+                ))
+                .insert_header(alice.bearer())
+                .to_request()
+        };
+        let list = || {
+            test::TestRequest::get()
+                .uri(&format!("/api/channels/{}/pins", channel_id))
+                .insert_header(alice.bearer())
+                .to_request()
+        };
+
+        let body: Value = test::call_and_read_body_json(&app, list()).await;
+        let contents: Vec<&str> = body
+            .as_array()
+            .unwrap()
+            .iter()
+            .take(3)
+            .map(|pin| pin["content"].as_str().unwrap())
+            .collect();
+        assert_eq!(contents, ["m0", "m1", "m2"]);
+
+        assert_eq!(
+            test::call_service(&app, pin(test::TestRequest::put(), extra))
+                .await
+                .status(),
+            StatusCode::CONFLICT
+        );
+        // pinning one that already is doesn't need a free slot
+        assert_eq!(
+            test::call_service(&app, pin(test::TestRequest::put(), pinned[5]))
+                .await
+                .status(),
+            StatusCode::NO_CONTENT
+        );
+
+        let oldest = *pinned.last().unwrap();
+        assert_eq!(
+            test::call_service(&app, pin(test::TestRequest::delete(), oldest))
+                .await
+                .status(),
+            StatusCode::NO_CONTENT
+        );
+        assert_eq!(
+            test::call_service(&app, pin(test::TestRequest::put(), extra))
+                .await
+                .status(),
+            StatusCode::NO_CONTENT
+        );
+        let body: Value = test::call_and_read_body_json(&app, list()).await;
+        assert_eq!(
+            body.as_array().unwrap().len() as i64,
+            DEFAULT_MAX_PINNED_MESSAGES
+        );
+        assert_eq!(body[0]["content"], "one too many");
+    }
+
+    #[sqlx::test]
+    async fn channel_names_are_unique_per_creator_only(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+
+        let create = |user: &TestUser, name: &str| {
+            test::TestRequest::post()
+                .uri("/api/channels")
+                .insert_header(user.bearer())
+                .set_json(json!({ "name": name }))
+                .to_request()
+        };
+
+        assert_eq!(
+            test::call_service(&app, create(&alice, "General"))
+                .await
+                .status(),
+            StatusCode::OK
+        );
+        let resp = test::call_service(&app, create(&alice, "general")).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["field"], "name");
+
+        // another owner may reuse it
+        assert_eq!(
+            test::call_service(&app, create(&bob, "general"))
+                .await
+                .status(),
+            StatusCode::OK
+        );
+
+        let channel: Value = test::call_and_read_body_json(&app, create(&alice, "random")).await;
+        let req = test::TestRequest::patch()
+            .uri(&format!(
+                "/api/channels/{}",
+                channel["id"].as_str().unwrap()
+            ))
+            .insert_header(alice.bearer())
+            .set_json(json!({ "name": "GENERAL" }))
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::CONFLICT
+        );
+    }
+
+    #[sqlx::test]
+    async fn my_role_reports_the_callers_role_or_refuses_outsiders(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+        let carol = create_user(&pool, "carol").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+        add_member(&pool, channel_id, &bob, Role::Member).await;
+
+        let my_role = |user: &TestUser, channel_id: Uuid| {
+            test::TestRequest::get()
+                .uri(&format!("/api/channels/{}/my-role", channel_id))
+                .insert_header(user.bearer())
+                .to_request()
+        };
+
+        for (user, role) in [(&alice, "admin"), (&bob, "member")] {
+            let body: Value = test::call_and_read_body_json(&app, my_role(user, channel_id)).await;
+            assert_eq!(body["role"], role);
+            assert_eq!(body["user_id"], json!(user.id));
+        }
+        // an unknown channel looks the same as one the caller isn't in
+        for req in [my_role(&carol, channel_id), my_role(&alice, Uuid::new_v4())] {
+            assert_eq!(
+                test::call_service(&app, req).await.status(),
+                StatusCode::FORBIDDEN
+            );
+        }
+    }
 }
@@ -1,47 +1,46 @@
 use crate::{
-    models::invitation::{InvitationResponse, InviteByEmailRequest, RespondToInvitationRequest},
-    utils::jwt::Claims,
+    db::{
+        membership::{add_member, AddMemberOutcome},
+        transaction,
+    },
+    handlers::websocket::{post_system_message, ChatServerHandle},
+    middleware::auth::AuthUser,
+    models::{
+        channel::InvitePolicy,
+        invitation::{
+            BulkInviteRequest, BulkInviteResult, BulkInviteStatus, ChannelInvitationsQuery,
+            InvitableQuery, InvitableUser, InvitationCountResponse, InvitationDirection,
+            InvitationResponse, InvitationStatus, InviteByEmailRequest, ListInvitationsQuery,
+            MarkInvitationsSeenResponse, RespondToInvitationRequest, SentInvitationSummary,
+        },
+        pagination::{clamp_limit, ListQuery, Page},
+        role::Role,
+        WsMessage,
+    },
+    utils::{
+        access::{member_role, ChannelMember},
+        validation::escape_like,
+    },
 };
-use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use actix_web::{web, HttpResponse};
 use sqlx::PgPool;
 use uuid::Uuid;
 
+const MAX_BULK_INVITES: usize = 50;
+const DEFAULT_INVITABLE_LIMIT: i64 = 20;
+const MAX_INVITABLE_LIMIT: i64 = 50;
+const MIN_INVITABLE_QUERY_LENGTH: usize = 2;
+
 pub async fn invite_user(
     pool: web::Data<PgPool>,
-    req: HttpRequest,
-    path: web::Path<Uuid>,
+    server: web::Data<ChatServerHandle>,
+    member: ChannelMember,
     body: web::Json<InviteByEmailRequest>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let claims = req
-        .extensions()
-        .get::<Claims>()
-        .cloned()
-        .ok_or_else(|| actix_web::error::ErrorUnauthorized("No claims found"))?;
-
-    let inviter_id = Uuid::parse_str(&claims.sub)
-        .map_err(|_| actix_web::error::ErrorInternalServerError("Invalid user id"))?;
+    let channel_id = member.channel_id;
+    let inviter_id = member.user_id;
 
-    let channel_id = path.into_inner();
-
-    let is_admin = sqlx::query_scalar::<_, bool>(
-        r#"
-        SELECT EXISTS(
-            SELECT 1 from channel_members
-            WHERE channel_id = $1 AND user_id = $2 AND role = 'admin'
-        )
-        "#,
-    )
-    .bind(channel_id)
-    .bind(inviter_id)
-    .fetch_one(pool.get_ref())
-    .await
-    .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
-
-    if !is_admin {
-        return Err(actix_web::error::ErrorForbidden(
-            "Only admins can invite users",
-        ));
-    }
+    ensure_can_invite(pool.get_ref(), &member).await?;
 
     let invitee_id = sqlx::query_scalar::<_, Uuid>(
         r#"
@@ -72,90 +71,484 @@ pub async fn invite_user(
         return Err(actix_web::error::ErrorConflict("User is already a member"));
     }
 
+    // a rejected or stale invitation is reopened, a pending one is left alone
     let invitation_id = sqlx::query_scalar::<_, Uuid>(
         r#"
         INSERT INTO invitations (channel_id, inviter_id, invitee_id, status)
         VALUES ($1, $2, $3, 'pending')
         ON CONFLICT (channel_id, invitee_id)
-        DO UPDATE SET status = 'pending', created_at = NOW()
+        DO UPDATE SET status = 'pending', inviter_id = $2, created_at = NOW(), seen_at = NULL
+        WHERE invitations.status <> 'pending'
         RETURNING id
         "#,
     )
     .bind(channel_id)
     .bind(inviter_id)
     .bind(invitee_id)
-    .fetch_one(pool.get_ref())
+    .fetch_optional(pool.get_ref())
     .await
-    .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to create new invitation"))?;
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to create new invitation"))?
+    .ok_or_else(|| actix_web::error::ErrorConflict("User is already invited"))?;
+
+    let invitation = fetch_invitation(pool.get_ref(), invitation_id)
+        .await
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+
+    server.send_to_user(
+        invitation.invitee_id,
+        WsMessage::InvitationReceived {
+            invitation: invitation.clone(),
+        },
+    );
+
+    Ok(HttpResponse::Created().json(invitation))
+}
+
+/// Admins can always invite; members only when the channel's `invite_policy` is `members`.
+async fn ensure_can_invite(pool: &PgPool, member: &ChannelMember) -> Result<(), actix_web::Error> {
+    if member.is_admin() {
+        return Ok(());
+    }
+
+    let policy = sqlx::query_scalar::<_, InvitePolicy>(
+        r#"
+        SELECT invite_policy FROM channels WHERE id = $1
+        "#,
+    )
+    .bind(member.channel_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?
+    .ok_or_else(|| actix_web::error::ErrorNotFound("Channel not found"))?;
+
+    if !policy.allows(member.role) {
+        return Err(actix_web::error::ErrorForbidden(match policy {
+            InvitePolicy::AdminsOnly => "Only admins can invite users",
+            InvitePolicy::Members => "Viewers can't invite users",
+        }));
+    }
+
+    Ok(())
+}
 
-    let invitation = sqlx::query_as::<_, InvitationResponse>(
+async fn fetch_invitation(
+    pool: &PgPool,
+    invitation_id: Uuid,
+) -> Result<InvitationResponse, sqlx::Error> {
+    sqlx::query_as::<_, InvitationResponse>(
         r#"
           SELECT
             i.id, i.channel_id, c.name as channel_name,
             i.inviter_id, u.username as inviter_username,
-            i.status, i.created_at
+            i.invitee_id, iu.username as invitee_username,
+            i.status, i.created_at, i.seen_at
           FROM invitations i
           INNER JOIN channels c ON i.channel_id = c.id
           INNER JOIN users u ON i.inviter_id = u.id
+          INNER JOIN users iu ON i.invitee_id = iu.id
           WHERE i.id = $1
         "#,
     )
     .bind(invitation_id)
-    .fetch_one(pool.get_ref())
+    .fetch_one(pool)
     .await
-    .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+}
 
-    Ok(HttpResponse::Created().json(invitation))
+/// Reopens a rejected invitation and notifies the invitee again.
+///
+/// Only the original inviter or a channel admin may resend.
+pub async fn resend_invitation(
+    pool: web::Data<PgPool>,
+    server: web::Data<ChatServerHandle>,
+    user: AuthUser,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let invitation_id = path.into_inner();
+
+    #[derive(sqlx::FromRow)]
+    struct InvitationRow {
+        channel_id: Uuid,
+        inviter_id: Uuid,
+        status: InvitationStatus,
+    }
+
+    let invitation = sqlx::query_as::<_, InvitationRow>(
+        r#"
+        SELECT channel_id, inviter_id, status
+        FROM invitations
+        WHERE id = $1
+        "#,
+    )
+    .bind(invitation_id)
+    .fetch_optional(pool.get_ref())
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?
+    .ok_or_else(|| actix_web::error::ErrorNotFound("Invitation not found"))?;
+
+    let role = member_role(pool.get_ref(), invitation.channel_id, user.id)
+        .await
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+
+    // the inviter may have left, been demoted or lost the right under the current policy
+    match role {
+        Some(role) if invitation.inviter_id == user.id => {
+            let member = ChannelMember {
+                user_id: user.id,
+                channel_id: invitation.channel_id,
+                role,
+            };
+            ensure_can_invite(pool.get_ref(), &member).await?;
+        }
+        Some(role) if role.is_admin() => {}
+        None if invitation.inviter_id == user.id => {
+            return Err(actix_web::error::ErrorForbidden(
+                "Not a member of this channel",
+            ));
+        }
+        _ => {
+            return Err(actix_web::error::ErrorForbidden(
+                "Only the inviter or an admin can resend invitations",
+            ));
+        }
+    }
+
+    match invitation.status {
+        InvitationStatus::Rejected => {}
+        InvitationStatus::Accepted => {
+            return Err(actix_web::error::ErrorConflict(
+                "Invitation already accepted",
+            ))
+        }
+        InvitationStatus::Pending => {
+            return Err(actix_web::error::ErrorConflict(
+                "Invitation is still pending",
+            ))
+        }
+    }
+
+    // the status check repeats here so a concurrent accept or resend isn't overwritten
+    let reopened = sqlx::query(
+        r#"
+        UPDATE invitations
+        SET status = 'pending', created_at = NOW(), seen_at = NULL
+        WHERE id = $1 AND status = 'rejected'
+        "#,
+    )
+    .bind(invitation_id)
+    .execute(pool.get_ref())
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to resend invitation"))?;
+
+    if reopened.rows_affected() == 0 {
+        return Err(actix_web::error::ErrorConflict(
+            "Invitation already processed",
+        ));
+    }
+
+    let invitation = fetch_invitation(pool.get_ref(), invitation_id)
+        .await
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+
+    server.send_to_user(
+        invitation.invitee_id,
+        WsMessage::InvitationReceived {
+            invitation: invitation.clone(),
+        },
+    );
+
+    Ok(HttpResponse::Ok().json(invitation))
+}
+
+/// Users the caller could invite: not members, no pending invitation, not deleted.
+///
+/// `query` matches anywhere in the username, but only a complete email address,
+/// so the search can't be used to harvest emails.
+pub async fn list_invitable_users(
+    pool: web::Data<PgPool>,
+    member: ChannelMember,
+    query: web::Query<InvitableQuery>,
+) -> Result<HttpResponse, actix_web::Error> {
+    ensure_can_invite(pool.get_ref(), &member).await?;
+
+    let term = query.query.trim();
+    if term.chars().count() < MIN_INVITABLE_QUERY_LENGTH {
+        return Err(actix_web::error::ErrorBadRequest(
+            "Search query is too short",
+        ));
+    }
+
+    let pattern = format!("%{}%", escape_like(term));
+    let limit = clamp_limit(query.limit, DEFAULT_INVITABLE_LIMIT, MAX_INVITABLE_LIMIT);
+
+    let users = sqlx::query_as::<_, InvitableUser>(
+        r#"
+        SELECT u.id, u.username, u.avatar_url
+        FROM users u
+        WHERE u.deleted_at IS NULL
+            AND (u.username ILIKE $2 OR LOWER(u.email) = LOWER($3))
+            AND NOT EXISTS (
+                SELECT 1 FROM channel_members cm
+                WHERE cm.channel_id = $1 AND cm.user_id = u.id
+            )
+            AND NOT EXISTS (
+                SELECT 1 FROM invitations i
+                WHERE i.channel_id = $1 AND i.invitee_id = u.id AND i.status = 'pending'
+            )
+        ORDER BY u.username
+        LIMIT $4
+        "#,
+    )
+    .bind(member.channel_id)
+    .bind(&pattern)
+    .bind(term)
+    .bind(limit)
+    .fetch_all(pool.get_ref())
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to search users"))?;
+
+    Ok(HttpResponse::Ok().json(users))
+}
+
+pub async fn invite_users_bulk(
+    pool: web::Data<PgPool>,
+    member: ChannelMember,
+    body: web::Json<BulkInviteRequest>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let channel_id = member.channel_id;
+    let inviter_id = member.user_id;
+
+    if body.emails.is_empty() {
+        return Err(actix_web::error::ErrorBadRequest("No emails provided"));
+    }
+
+    if body.emails.len() > MAX_BULK_INVITES {
+        return Err(actix_web::error::ErrorBadRequest(format!(
+            "Cannot invite more than {} users at once",
+            MAX_BULK_INVITES
+        )));
+    }
+
+    ensure_can_invite(pool.get_ref(), &member).await?;
+
+    let mut tx = transaction::begin(pool.get_ref()).await?;
+
+    let mut results = Vec::with_capacity(body.emails.len());
+
+    for email in &body.emails {
+        let invitee_id = sqlx::query_scalar::<_, Uuid>(
+            r#"
+            SELECT id FROM users WHERE email = $1
+            "#,
+        )
+        .bind(email)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+
+        let Some(invitee_id) = invitee_id else {
+            results.push(BulkInviteResult {
+                email: email.clone(),
+                status: BulkInviteStatus::NotFound,
+                invitation_id: None,
+            });
+            continue;
+        };
+
+        let is_member = sqlx::query_scalar::<_, bool>(
+            r#"
+            SELECT EXISTS(
+                SELECT 1 FROM channel_members
+                WHERE channel_id = $1 AND user_id = $2
+            )
+            "#,
+        )
+        .bind(channel_id)
+        .bind(invitee_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+
+        if is_member {
+            results.push(BulkInviteResult {
+                email: email.clone(),
+                status: BulkInviteStatus::AlreadyMember,
+                invitation_id: None,
+            });
+            continue;
+        }
+
+        let pending_id = sqlx::query_scalar::<_, Uuid>(
+            r#"
+            SELECT id FROM invitations
+            WHERE channel_id = $1 AND invitee_id = $2 AND status = 'pending'
+            "#,
+        )
+        .bind(channel_id)
+        .bind(invitee_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+
+        if let Some(pending_id) = pending_id {
+            results.push(BulkInviteResult {
+                email: email.clone(),
+                status: BulkInviteStatus::AlreadyInvited,
+                invitation_id: Some(pending_id),
+            });
+            continue;
+        }
+
+        let invitation_id = sqlx::query_scalar::<_, Uuid>(
+            r#"
+            INSERT INTO invitations (channel_id, inviter_id, invitee_id, status)
+            VALUES ($1, $2, $3, 'pending')
+            ON CONFLICT (channel_id, invitee_id)
+            DO UPDATE SET status = 'pending', inviter_id = $2, created_at = NOW(), seen_at = NULL
+            RETURNING id
+            "#,
+        )
+        .bind(channel_id)
+        .bind(inviter_id)
+        .bind(invitee_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|_| {
+            actix_web::error::ErrorInternalServerError("Failed to create new invitation")
+        })?;
+
+        results.push(BulkInviteResult {
+            email: email.clone(),
+            status: BulkInviteStatus::Invited,
+            invitation_id: Some(invitation_id),
+        });
+    }
+
+    transaction::commit(tx).await?;
+
+    Ok(HttpResponse::Ok().json(results))
 }
 
 pub async fn list_invitations(
     pool: web::Data<PgPool>,
-    req: HttpRequest,
+    user: AuthUser,
+    query: web::Query<ListInvitationsQuery>,
+    page: web::Query<ListQuery>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let claims = req
-        .extensions()
-        .get::<Claims>()
-        .cloned()
-        .ok_or_else(|| actix_web::error::ErrorUnauthorized("No claims found"))?;
+    let user_id = user.id;
+
+    let user_column = match query.direction {
+        InvitationDirection::Received => "i.invitee_id",
+        InvitationDirection::Sent => "i.inviter_id",
+    };
+
+    let status = query.status.status();
+
+    let (limit, offset) = if page.envelope {
+        (Some(page.page_limit() + 1), page.offset()?)
+    } else {
+        (None, 0)
+    };
 
-    let user_id = Uuid::parse_str(&claims.sub)
-        .map_err(|_| actix_web::error::ErrorInternalServerError("Invalid user id"))?;
+    let sql = format!(
+        r#"
+        SELECT
+            i.id, i.channel_id, c.name as channel_name,
+            i.inviter_id, u.username as inviter_username,
+            i.invitee_id, iu.username as invitee_username,
+            i.status, i.created_at, i.seen_at
+        FROM invitations i
+        INNER JOIN channels c ON i.channel_id = c.id
+        INNER JOIN users u ON i.inviter_id = u.id
+        INNER JOIN users iu ON i.invitee_id = iu.id
+        WHERE {} = $1 AND ($2::text IS NULL OR i.status = $2)
+        ORDER BY i.created_at DESC, i.id DESC
+        LIMIT $3 OFFSET $4
+        "#,
+        user_column
+    );
+
+    let invitations = sqlx::query_as::<_, InvitationResponse>(&sql)
+        .bind(user_id)
+        .bind(status)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(pool.get_ref())
+        .await
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to fetch invitations"))?;
+
+    if page.envelope {
+        return Ok(HttpResponse::Ok().json(Page::from_overfetch(
+            invitations,
+            page.page_limit(),
+            offset,
+        )));
+    }
+
+    Ok(HttpResponse::Ok().json(invitations))
+}
+
+/// Invitations to the channel, newest first; `pending` unless `status` says otherwise.
+pub async fn list_channel_invitations(
+    pool: web::Data<PgPool>,
+    member: ChannelMember,
+    query: web::Query<ChannelInvitationsQuery>,
+    page: web::Query<ListQuery>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !member.is_admin() {
+        return Err(actix_web::error::ErrorForbidden(
+            "Only admins can view channel invitations",
+        ));
+    }
+
+    let (limit, offset) = if page.envelope {
+        (Some(page.page_limit() + 1), page.offset()?)
+    } else {
+        (None, 0)
+    };
 
     let invitations = sqlx::query_as::<_, InvitationResponse>(
         r#"
         SELECT
             i.id, i.channel_id, c.name as channel_name,
             i.inviter_id, u.username as inviter_username,
-            i.status, i.created_at
+            i.invitee_id, iu.username as invitee_username,
+            i.status, i.created_at, i.seen_at
         FROM invitations i
-        INNER JOIN channels c ON i.channel_id = c.id 
-        INNER JOIN users u ON i.inviter_id = u.id 
-        WHERE i.invitee_id = $1 AND i.status = 'pending'
-        ORDER BY i.created_at DESC
+        INNER JOIN channels c ON i.channel_id = c.id
+        INNER JOIN users u ON i.inviter_id = u.id
+        INNER JOIN users iu ON i.invitee_id = iu.id
+        WHERE i.channel_id = $1 AND ($2::text IS NULL OR i.status = $2)
+        ORDER BY i.created_at DESC, i.id DESC
+        LIMIT $3 OFFSET $4
         "#,
     )
-    .bind(user_id)
+    .bind(member.channel_id)
+    .bind(query.status.status())
+    .bind(limit)
+    .bind(offset)
     .fetch_all(pool.get_ref())
     .await
     .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to fetch invitations"))?;
 
+    if page.envelope {
+        return Ok(HttpResponse::Ok().json(Page::from_overfetch(
+            invitations,
+            page.page_limit(),
+            offset,
+        )));
+    }
+
     Ok(HttpResponse::Ok().json(invitations))
 }
 
 pub async fn respond_to_invitation(
     pool: web::Data<PgPool>,
-    req: HttpRequest,
+    server: web::Data<ChatServerHandle>,
+    user: AuthUser,
     path: web::Path<Uuid>,
     body: web::Json<RespondToInvitationRequest>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let claims = req
-        .extensions()
-        .get::<Claims>()
-        .cloned()
-        .ok_or_else(|| actix_web::error::ErrorUnauthorized("No claims found"))?;
-
-    let user_id = Uuid::parse_str(&claims.sub)
-        .map_err(|_| actix_web::error::ErrorInternalServerError("Invalid user id"))?;
+    let user_id = user.id;
 
     let invitation_id = path.into_inner();
 
@@ -163,7 +556,7 @@ pub async fn respond_to_invitation(
     struct InvitationRow {
         channel_id: Uuid,
         invitee_id: Uuid,
-        status: String,
+        status: InvitationStatus,
     }
 
     let invitation = sqlx::query_as::<_, InvitationRow>(
@@ -183,48 +576,835 @@ pub async fn respond_to_invitation(
         return Err(actix_web::error::ErrorForbidden("Not your invitation"));
     }
 
-    if invitation.status != "pending" {
+    if invitation.status != InvitationStatus::Pending {
         return Err(actix_web::error::ErrorConflict(
             "Invitation already processed",
         ));
     }
 
-    let new_status = if body.accept { "accepted" } else { "rejected" };
+    let new_status = if body.accept {
+        InvitationStatus::Accepted
+    } else {
+        InvitationStatus::Rejected
+    };
+
+    let mut tx = transaction::begin(pool.get_ref()).await?;
 
-    sqlx::query(
+    // re-checked under the row lock, so of two concurrent responses only one gets through
+    let updated = sqlx::query(
         r#"
         UPDATE invitations
         SET status = $1
-        WHERE id = $2
+        WHERE id = $2 AND status = 'pending'
         "#,
     )
     .bind(new_status)
     .bind(invitation_id)
-    .execute(pool.get_ref())
+    .execute(&mut *tx)
     .await
     .map_err(|_| {
         actix_web::error::ErrorInternalServerError("Failed to update status invitation")
     })?;
 
-    if body.accept {
-        sqlx::query(
+    if updated.rows_affected() == 0 {
+        return Err(actix_web::error::ErrorConflict(
+            "Invitation already processed",
+        ));
+    }
+
+    // a full channel leaves the invitation pending so it can be accepted later
+    let outcome = if body.accept {
+        let outcome = add_member(&mut tx, invitation.channel_id, user_id, Role::Member)
+            .await
+            .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to add members"))?;
+
+        if outcome == AddMemberOutcome::Full {
+            return Err(actix_web::error::ErrorConflict("Channel is full"));
+        }
+        Some(outcome)
+    } else {
+        None
+    };
+
+    transaction::commit(tx).await?;
+
+    if outcome == Some(AddMemberOutcome::Added) {
+        server.broadcast(
+            invitation.channel_id,
+            WsMessage::MemberAdded {
+                user_id,
+                username: user.username.clone(),
+            },
+        );
+        post_system_message(
+            pool.get_ref(),
+            server.get_ref(),
+            invitation.channel_id,
+            user_id,
+            &user.username,
+            format!("{} joined the channel", user.username),
+        )
+        .await;
+    }
+
+    // still accepted, there's just nothing to join
+    let response = match outcome {
+        Some(AddMemberOutcome::Added) => "Invitation accepted",
+        Some(_) => "Invitation accepted, already a member of this channel",
+        None => "Invitation rejected",
+    };
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+pub async fn mark_invitations_seen(
+    pool: web::Data<PgPool>,
+    user: AuthUser,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user.id;
+
+    // clears the badge only, the invitations stay pending and actionable
+    let result = sqlx::query(
+        r#"
+        UPDATE invitations
+        SET seen_at = NOW()
+        WHERE invitee_id = $1 AND status = 'pending' AND seen_at IS NULL
+        "#,
+    )
+    .bind(user_id)
+    .execute(pool.get_ref())
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to update invitations"))?;
+
+    Ok(HttpResponse::Ok().json(MarkInvitationsSeenResponse {
+        updated: result.rows_affected(),
+    }))
+}
+
+pub async fn count_invitations(
+    pool: web::Data<PgPool>,
+    user: AuthUser,
+) -> Result<HttpResponse, actix_web::Error> {
+    let counts = sqlx::query_as::<_, InvitationCountResponse>(
+        r#"
+        SELECT
+            COUNT(*) AS pending,
+            COUNT(*) FILTER (WHERE seen_at IS NULL) AS unseen
+        FROM invitations
+        WHERE invitee_id = $1 AND status = 'pending'
+        "#,
+    )
+    .bind(user.id)
+    .fetch_one(pool.get_ref())
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to count invitations"))?;
+
+    Ok(HttpResponse::Ok().json(counts))
+}
+
+pub async fn sent_invitations_summary(
+    pool: web::Data<PgPool>,
+    user: AuthUser,
+) -> Result<HttpResponse, actix_web::Error> {
+    let summary = sqlx::query_as::<_, SentInvitationSummary>(
+        r#"
+        SELECT
+            COUNT(*) AS total,
+            COUNT(*) FILTER (WHERE status = 'pending') AS pending,
+            COUNT(*) FILTER (WHERE status = 'accepted') AS accepted,
+            COUNT(*) FILTER (WHERE status = 'rejected') AS rejected
+        FROM invitations
+        WHERE inviter_id = $1
+        "#,
+    )
+    .bind(user.id)
+    .fetch_one(pool.get_ref())
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to count invitations"))?;
+
+    Ok(HttpResponse::Ok().json(summary))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        handlers::websocket::open_session,
+        models::role::Role,
+        test_support::{
+            add_member, app, create_channel, create_user, next_frame, start_server, TestUser,
+        },
+    };
+    use actix_web::{http::StatusCode, test};
+    use serde_json::{json, Value};
+    use sqlx::PgPool;
+    use uuid::Uuid;
+
+    async fn insert_invitation(
+        pool: &PgPool,
+        channel_id: Uuid,
+        inviter: &TestUser,
+        invitee: &TestUser,
+        status: &str,
+    ) -> Uuid {
+        sqlx::query_scalar::<_, Uuid>(
             r#"
-            INSERT INTO channel_members (channel_id, user_id, role)
-            VALUES ($1, $2, 'member')
+            INSERT INTO invitations (channel_id, inviter_id, invitee_id, status)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id
             "#,
         )
-        .bind(invitation.channel_id)
-        .bind(user_id)
-        .execute(pool.get_ref())
+        .bind(channel_id)
+        .bind(inviter.id)
+        .bind(invitee.id)
+        .bind(status)
+        .fetch_one(pool)
         .await
-        .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to add members"))?;
+        .unwrap()
     }
 
-    let response = if body.accept {
-        "Invitation accepted"
-    } else {
-        "Invitation rejected"
-    };
+    #[sqlx::test]
+    async fn bulk_invite_reports_each_address(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+        create_user(&pool, "carol").await;
+        create_user(&pool, "dave").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+        add_member(&pool, channel_id, &bob, Role::Member).await;
 
-    Ok(HttpResponse::Ok().json(response))
+        let req = test::TestRequest::post()
+            .uri(&format!("/api/channels/{}/invite", channel_id))
+            .insert_header(alice.bearer())
+            .set_json(json!({ "email": "dave@example.com" }))
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::CREATED
+        );
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/api/channels/{}/invite-bulk", channel_id))
+            .insert_header(alice.bearer())
+            .set_json(json!({ "emails": [
+                "carol@example.com",
+                "nobody@example.com",
+                "bob@example.com",
+                "dave@example.com",
+            ] }))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        let statuses: Vec<_> = body
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|result| {
+                (
+                    result["email"].as_str().unwrap(),
+                    result["status"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                ("carol@example.com", "invited"),
+                ("nobody@example.com", "not_found"),
+                ("bob@example.com", "already_member"),
+                ("dave@example.com", "already_invited"),
+            ]
+        );
+        assert!(body[0]["invitation_id"].is_string());
+        assert!(body[1]["invitation_id"].is_null());
+    }
+
+    #[sqlx::test]
+    async fn bulk_invite_caps_the_batch(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+
+        let emails: Vec<_> = (0..51).map(|i| format!("user{}@example.com", i)).collect();
+        for emails in [Vec::new(), emails] {
+            let req = test::TestRequest::post()
+                .uri(&format!("/api/channels/{}/invite-bulk", channel_id))
+                .insert_header(alice.bearer())
+                .set_json(json!({ "emails": emails }))
+                .to_request();
+            assert_eq!(
+                test::call_service(&app, req).await.status(),
+                StatusCode::BAD_REQUEST
+            );
+        }
+    }
+
+    #[sqlx::test]
+    async fn accepting_announces_the_new_member_rejecting_does_not(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let carol = create_user(&pool, "carol").await;
+        let dave = create_user(&pool, "dave").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+        let (_, mut alice_rx) = open_session(&server, alice.id, "alice", channel_id, Role::Admin);
+
+        for (invitee, name, accept) in [(&dave, "dave", false), (&carol, "carol", true)] {
+            let req = test::TestRequest::post()
+                .uri(&format!("/api/channels/{}/invite", channel_id))
+                .insert_header(alice.bearer())
+                .set_json(json!({ "email": format!("{}@example.com", name) }))
+                .to_request();
+            let invitation: Value = test::call_and_read_body_json(&app, req).await;
+
+            let req = test::TestRequest::post()
+                .uri(&format!(
+                    "/api/invitations/{}/respond",
+                    invitation["id"].as_str().unwrap()
+                ))
+                .insert_header(invitee.bearer())
+                .set_json(json!({ "accept": accept }))
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+        }
+
+        // dave's rejection came first, so his would have been the first frame
+        let frame = next_frame(&mut alice_rx, "member_added").await;
+        assert_eq!(frame["user_id"], carol.id.to_string());
+        assert_eq!(frame["username"], "carol");
+    }
+
+    #[sqlx::test]
+    async fn invitations_filter_by_status_and_direction(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+        for (name, status) in [
+            ("pending", "pending"),
+            ("accepted", "accepted"),
+            ("rejected", "rejected"),
+        ] {
+            let channel_id = create_channel(&pool, &alice, name).await;
+            insert_invitation(&pool, channel_id, &alice, &bob, status).await;
+        }
+
+        let list = |user: &TestUser, query: &str| {
+            test::TestRequest::get()
+                .uri(&format!("/api/invitations{}", query))
+                .insert_header(user.bearer())
+                .to_request()
+        };
+        let names = |body: Value| {
+            let mut names: Vec<String> = body
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|invitation| invitation["channel_name"].as_str().unwrap().to_string())
+                .collect();
+            names.sort();
+            names
+        };
+
+        for (query, expected) in [
+            ("", vec!["pending"]),
+            ("?status=pending", vec!["pending"]),
+            ("?status=accepted", vec!["accepted"]),
+            ("?status=rejected", vec!["rejected"]),
+            ("?status=all", vec!["accepted", "pending", "rejected"]),
+        ] {
+            let body = test::call_and_read_body_json(&app, list(&bob, query)).await;
+            assert_eq!(names(body), expected, "received{}", query);
+
+            // the inviter sees none of them as received, all of them as sent
+            let body = test::call_and_read_body_json(&app, list(&alice, query)).await;
+            assert!(names(body).is_empty(), "received{}", query);
+            let sent = if query.is_empty() {
+                "?direction=sent".to_string()
+            } else {
+                format!("{}&direction=sent", query)
+            };
+            let body = test::call_and_read_body_json(&app, list(&alice, &sent)).await;
+            assert_eq!(names(body), expected, "{}", sent);
+        }
+    }
+
+    #[sqlx::test]
+    async fn marking_all_seen_keeps_invitations_pending(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+        let mut pending = Vec::new();
+        for name in ["general", "random"] {
+            let channel_id = create_channel(&pool, &alice, name).await;
+            pending.push(insert_invitation(&pool, channel_id, &alice, &bob, "pending").await);
+        }
+        let old = create_channel(&pool, &alice, "old").await;
+        insert_invitation(&pool, old, &alice, &bob, "rejected").await;
+
+        let req = test::TestRequest::post()
+            .uri("/api/invitations/read-all")
+            .insert_header(bob.bearer())
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["updated"], 2);
+
+        let req = test::TestRequest::get()
+            .uri("/api/invitations?status=all")
+            .insert_header(bob.bearer())
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body.as_array().unwrap().len(), 3);
+        for invitation in body.as_array().unwrap() {
+            let was_pending = pending.iter().any(|id| invitation["id"] == id.to_string());
+            assert_eq!(invitation["seen_at"].is_string(), was_pending);
+            assert_eq!(invitation["status"] == "pending", was_pending);
+        }
+
+        // still actionable
+        let req = test::TestRequest::post()
+            .uri(&format!("/api/invitations/{}/respond", pending[0]))
+            .insert_header(bob.bearer())
+            .set_json(json!({ "accept": true }))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    }
+
+    #[sqlx::test]
+    async fn pending_invite_conflicts_but_a_rejected_one_can_be_renewed(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+
+        let invite = || {
+            test::TestRequest::post()
+                .uri(&format!("/api/channels/{}/invite", channel_id))
+                .insert_header(alice.bearer())
+                .set_json(json!({ "email": "bob@example.com" }))
+                .to_request()
+        };
+        let resp = test::call_service(&app, invite()).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let first: Value = test::read_body_json(resp).await;
+        assert_eq!(
+            test::call_service(&app, invite()).await.status(),
+            StatusCode::CONFLICT
+        );
+
+        let req = test::TestRequest::post()
+            .uri(&format!(
+                "/api/invitations/{}/respond",
+                first["id"].as_str().unwrap()
+            ))
+            .insert_header(bob.bearer())
+            .set_json(json!({ "accept": false }))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+        let resp = test::call_service(&app, invite()).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let renewed: Value = test::read_body_json(resp).await;
+        assert_eq!(renewed["status"], "pending");
+
+        let rows = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM invitations WHERE channel_id = $1 AND invitee_id = $2",
+        )
+        .bind(channel_id)
+        .bind(bob.id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(rows, 1);
+    }
+
+    #[sqlx::test]
+    async fn invitable_users_leave_out_members_and_pending_invitees(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+        let member = create_user(&pool, "team-member").await;
+        add_member(&pool, channel_id, &member, Role::Member).await;
+        let invited = create_user(&pool, "team-invited").await;
+        insert_invitation(&pool, channel_id, &alice, &invited, "pending").await;
+        let rejected = create_user(&pool, "team-rejected").await;
+        insert_invitation(&pool, channel_id, &alice, &rejected, "rejected").await;
+        create_user(&pool, "team-new").await;
+        create_user(&pool, "team-zed").await;
+        create_user(&pool, "outsider").await;
+
+        let search = |query: &str| {
+            test::TestRequest::get()
+                .uri(&format!("/api/channels/{}/invitable?{}", channel_id, query))
+                .insert_header(alice.bearer())
+                .to_request()
+        };
+        let names = |body: Value| -> Vec<String> {
+            body.as_array()
+                .unwrap()
+                .iter()
+                .map(|user| user["username"].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        let body = test::call_and_read_body_json(&app, search("query=team")).await;
+        assert_eq!(names(body), ["team-new", "team-rejected", "team-zed"]);
+        let body = test::call_and_read_body_json(&app, search("query=team&limit=2")).await;
+        assert_eq!(names(body), ["team-new", "team-rejected"]);
+    }
+
+    #[sqlx::test]
+    async fn member_cap_holds_on_join_and_accept_until_raised(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+        let carol = create_user(&pool, "carol").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+        add_member(&pool, channel_id, &bob, Role::Member).await;
+        let invitation_id = insert_invitation(&pool, channel_id, &alice, &carol, "pending").await;
+
+        let update = |changes: Value| {
+            test::TestRequest::patch()
+                .uri(&format!("/api/channels/{}", channel_id))
+                .insert_header(alice.bearer())
+                .set_json(changes)
+                .to_request()
+        };
+        let join = || {
+            test::TestRequest::post()
+                .uri(&format!("/api/channels/{}/join", channel_id))
+                .insert_header(carol.bearer())
+                .to_request()
+        };
+        let accept = || {
+            test::TestRequest::post()
+                .uri(&format!("/api/invitations/{}/respond", invitation_id))
+                .insert_header(carol.bearer())
+                .set_json(json!({ "accept": true }))
+                .to_request()
+        };
+
+        let resp =
+            test::call_service(&app, update(json!({ "max_members": 2, "is_public": true }))).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            test::call_service(&app, join()).await.status(),
+            StatusCode::CONFLICT
+        );
+        assert_eq!(
+            test::call_service(&app, accept()).await.status(),
+            StatusCode::CONFLICT
+        );
+
+        let resp = test::call_service(&app, update(json!({ "max_members": 3 }))).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            test::call_service(&app, accept()).await.status(),
+            StatusCode::OK
+        );
+    }
+
+    #[sqlx::test]
+    async fn resend_reopens_a_rejected_invite_but_not_an_accepted_one(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+        let general = create_channel(&pool, &alice, "general").await;
+        let random = create_channel(&pool, &alice, "random").await;
+        let rejected = insert_invitation(&pool, general, &alice, &bob, "rejected").await;
+        let accepted = insert_invitation(&pool, random, &alice, &bob, "accepted").await;
+        add_member(&pool, random, &bob, Role::Member).await;
+        let (_, mut bob_rx) = open_session(&server, bob.id, "bob", random, Role::Member);
+
+        let resend = |invitation_id: Uuid| {
+            test::TestRequest::post()
+                .uri(&format!("/api/invitations/{}/resend", invitation_id))
+                .insert_header(alice.bearer())
+                .to_request()
+        };
+
+        let body: Value = test::call_and_read_body_json(&app, resend(rejected)).await;
+        assert_eq!(body["status"], "pending");
+        let frame = next_frame(&mut bob_rx, "invitation_received").await;
+        assert_eq!(frame["invitation"]["id"], rejected.to_string());
+
+        assert_eq!(
+            test::call_service(&app, resend(accepted)).await.status(),
+            StatusCode::CONFLICT
+        );
+        // a reopened invite is pending again, so a second resend conflicts too
+        assert_eq!(
+            test::call_service(&app, resend(rejected)).await.status(),
+            StatusCode::CONFLICT
+        );
+    }
+
+    #[sqlx::test]
+    async fn count_follows_new_responded_and_seen_invitations(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+        let count = || async {
+            let req = test::TestRequest::get()
+                .uri("/api/invitations/count")
+                .insert_header(bob.bearer())
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            (
+                body["pending"].as_i64().unwrap(),
+                body["unseen"].as_i64().unwrap(),
+            )
+        };
+        assert_eq!(count().await, (0, 0));
+
+        let mut invitations = Vec::new();
+        for name in ["general", "random", "offtopic"] {
+            let channel_id = create_channel(&pool, &alice, name).await;
+            let req = test::TestRequest::post()
+                .uri(&format!("/api/channels/{}/invite", channel_id))
+                .insert_header(alice.bearer())
+                .set_json(json!({ "email": "bob@example.com" }))
+                .to_request();
+            let invitation: Value = test::call_and_read_body_json(&app, req).await;
+            invitations.push(invitation["id"].as_str().unwrap().to_string());
+        }
+        assert_eq!(count().await, (3, 3));
+
+        for (id, accept) in [(&invitations[0], true), (&invitations[1], false)] {
+            let req = test::TestRequest::post()
+                .uri(&format!("/api/invitations/{}/respond", id))
+                .insert_header(bob.bearer())
+                .set_json(json!({ "accept": accept }))
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+        }
+        assert_eq!(count().await, (1, 1));
+
+        let req = test::TestRequest::post()
+            .uri("/api/invitations/read-all")
+            .insert_header(bob.bearer())
+            .to_request();
+        test::call_service(&app, req).await;
+        assert_eq!(count().await, (1, 0));
+    }
+
+    #[sqlx::test]
+    async fn channel_invitations_are_listed_for_admins_only(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+        let carol = create_user(&pool, "carol").await;
+        let dave = create_user(&pool, "dave").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+        let other = create_channel(&pool, &alice, "random").await;
+        add_member(&pool, channel_id, &bob, Role::Member).await;
+        insert_invitation(&pool, channel_id, &alice, &carol, "pending").await;
+        insert_invitation(&pool, channel_id, &alice, &dave, "rejected").await;
+        insert_invitation(&pool, other, &alice, &dave, "pending").await;
+
+        let list = |user: &TestUser, query: &str| {
+            test::TestRequest::get()
+                .uri(&format!(
+                    "/api/channels/{}/invitations{}",
+                    channel_id, query
+                ))
+                .insert_header(user.bearer())
+                .to_request()
+        };
+        let invitees = |body: Value| -> Vec<(String, String)> {
+            body.as_array()
+                .unwrap()
+                .iter()
+                .map(|invitation| {
+                    (
+                        invitation["invitee_username"].as_str().unwrap().to_string(),
+                        invitation["status"].as_str().unwrap().to_string(),
+                    )
+                })
+                .collect()
+        };
+
+        let body = test::call_and_read_body_json(&app, list(&alice, "")).await;
+        assert_eq!(
+            invitees(body),
+            [("carol".to_string(), "pending".to_string())]
+        );
+        let body = test::call_and_read_body_json(&app, list(&alice, "?status=all")).await;
+        let mut all = invitees(body);
+        all.sort();
+        assert_eq!(
+            all,
+            [
+                ("carol".to_string(), "pending".to_string()),
+                ("dave".to_string(), "rejected".to_string()),
+            ]
+        );
+
+        assert_eq!(
+            test::call_service(&app, list(&bob, "")).await.status(),
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[sqlx::test]
+    async fn sent_summary_counts_only_the_callers_invitations(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+        let carol = create_user(&pool, "carol").await;
+        let dave = create_user(&pool, "dave").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+        add_member(&pool, channel_id, &bob, Role::Member).await;
+
+        let mut sent = Vec::new();
+        for invitee in [&carol, &dave] {
+            sent.push(insert_invitation(&pool, channel_id, &alice, invitee, "pending").await);
+        }
+        let other = create_channel(&pool, &alice, "random").await;
+        insert_invitation(&pool, other, &alice, &bob, "pending").await;
+        // bob's own invitation isn't part of alice's summary
+        let bobs = create_channel(&pool, &bob, "bobs").await;
+        insert_invitation(&pool, bobs, &bob, &carol, "pending").await;
+
+        for (invitation, invitee, accept) in [(sent[0], &carol, true), (sent[1], &dave, false)] {
+            let req = test::TestRequest::post()
+                .uri(&format!("/api/invitations/{}/respond", invitation))
+                .insert_header(invitee.bearer())
+                .set_json(json!({ "accept": accept }))
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+        }
+
+        let req = test::TestRequest::get()
+            .uri("/api/invitations/sent/summary")
+            .insert_header(alice.bearer())
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(
+            body,
+            json!({ "total": 3, "pending": 1, "accepted": 1, "rejected": 1 })
+        );
+    }
+
+    #[sqlx::test]
+    async fn members_invite_only_when_the_policy_allows(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+        let viewer = create_user(&pool, "viewer").await;
+        create_user(&pool, "carol").await;
+        create_user(&pool, "dave").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+        add_member(&pool, channel_id, &bob, Role::Member).await;
+        add_member(&pool, channel_id, &viewer, Role::Viewer).await;
+
+        let invite = |user: &TestUser, email: &str| {
+            test::TestRequest::post()
+                .uri(&format!("/api/channels/{}/invite", channel_id))
+                .insert_header(user.bearer())
+                .set_json(json!({ "email": email }))
+                .to_request()
+        };
+        let set_policy = |user: &TestUser, policy: &str| {
+            test::TestRequest::patch()
+                .uri(&format!("/api/channels/{}", channel_id))
+                .insert_header(user.bearer())
+                .set_json(json!({ "invite_policy": policy }))
+                .to_request()
+        };
+
+        assert_eq!(
+            test::call_service(&app, invite(&bob, "carol@example.com"))
+                .await
+                .status(),
+            StatusCode::FORBIDDEN
+        );
+        // only admins choose the policy
+        assert_eq!(
+            test::call_service(&app, set_policy(&bob, "members"))
+                .await
+                .status(),
+            StatusCode::FORBIDDEN
+        );
+
+        let channel: Value =
+            test::call_and_read_body_json(&app, set_policy(&alice, "members")).await;
+        assert_eq!(channel["invite_policy"], "members");
+        assert_eq!(
+            test::call_service(&app, invite(&bob, "carol@example.com"))
+                .await
+                .status(),
+            StatusCode::CREATED
+        );
+        assert_eq!(
+            test::call_service(&app, invite(&viewer, "dave@example.com"))
+                .await
+                .status(),
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[sqlx::test]
+    async fn a_double_accept_joins_once(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+        let carol = create_user(&pool, "carol").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+        let bob_invite = insert_invitation(&pool, channel_id, &alice, &bob, "pending").await;
+
+        let accept = |user: &TestUser, invitation_id: Uuid| {
+            test::TestRequest::post()
+                .uri(&format!("/api/invitations/{}/respond", invitation_id))
+                .insert_header(user.bearer())
+                .set_json(json!({ "accept": true }))
+                .to_request()
+        };
+        let memberships = |user: &TestUser| {
+            sqlx::query_scalar::<_, i64>(
+                "SELECT COUNT(*) FROM channel_members WHERE channel_id = $1 AND user_id = $2",
+            )
+            .bind(channel_id)
+            .bind(user.id)
+            .fetch_one(&pool)
+        };
+
+        let (first, second) = tokio::join!(
+            test::call_service(&app, accept(&bob, bob_invite)),
+            test::call_service(&app, accept(&bob, bob_invite)),
+        );
+        let mut statuses = [first.status(), second.status()];
+        statuses.sort();
+        assert_eq!(statuses, [StatusCode::OK, StatusCode::CONFLICT]);
+        assert_eq!(memberships(&bob).await.unwrap(), 1);
+
+        // already in the channel by another route: accepted, not a 500
+        add_member(&pool, channel_id, &carol, Role::Member).await;
+        let carol_invite = insert_invitation(&pool, channel_id, &alice, &carol, "pending").await;
+        let resp = test::call_service(&app, accept(&carol, carol_invite)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(
+            body,
+            "Invitation accepted, already a member of this channel"
+        );
+        assert_eq!(memberships(&carol).await.unwrap(), 1);
+        let status =
+            sqlx::query_scalar::<_, String>("SELECT status FROM invitations WHERE id = $1")
+                .bind(carol_invite)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(status, "accepted");
+    }
 }
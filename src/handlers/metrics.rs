@@ -0,0 +1,57 @@
+use crate::handlers::websocket::ChatServerHandle;
+use actix_web::{web, HttpResponse};
+use std::fmt::Write;
+
+/// Exposes live chat server counters in the Prometheus text format.
+pub async fn metrics(
+    server: web::Data<ChatServerHandle>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let metrics = server
+        .metrics()
+        .await
+        .ok_or_else(|| actix_web::error::ErrorServiceUnavailable("Chat server unavailable"))?;
+
+    let series = [
+        (
+            "chat_active_connections",
+            "gauge",
+            "Open WebSocket connections",
+            metrics.active_connections as u64,
+        ),
+        (
+            "chat_active_channels",
+            "gauge",
+            "Channels with at least one open connection",
+            metrics.active_channels as u64,
+        ),
+        (
+            "chat_online_users",
+            "gauge",
+            "Users with at least one open connection",
+            metrics.online_users as u64,
+        ),
+        (
+            "chat_messages_relayed_total",
+            "counter",
+            "Chat messages relayed since startup",
+            metrics.messages_relayed,
+        ),
+        (
+            "chat_dropped_clients_total",
+            "counter",
+            "Clients disconnected for falling behind",
+            metrics.dropped_clients,
+        ),
+    ];
+
+    let mut body = String::new();
+    for (name, kind, help, value) in series {
+        let _ = writeln!(body, "# HELP {} {}", name, help);
+        let _ = writeln!(body, "# TYPE {} {}", name, kind);
+        let _ = writeln!(body, "{} {}", name, value);
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(body))
+}
@@ -1,4 +1,6 @@
 pub mod auth;
 pub mod channel;
 pub mod invitation;
+pub mod metrics;
+pub mod user;
 pub mod websocket;
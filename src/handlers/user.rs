@@ -0,0 +1,686 @@
+use crate::{
+    db::transaction,
+    handlers::websocket::ChatServerHandle,
+    middleware::auth::AuthUser,
+    models::{
+        error::ErrorResponse,
+        pagination::clamp_limit,
+        user::{
+            AuthResponse, LeaveAllResponse, PresenceResponse, UpdateProfileRequest,
+            UpdateUsernameRequest, User, UserResponse, UserSearchQuery, UserSearchResult,
+        },
+    },
+    utils::{
+        jwt::create_jwt,
+        validation::{escape_like, validate_http_url, validate_username},
+    },
+};
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+const DEFAULT_USER_SEARCH_LIMIT: i64 = 20;
+const MAX_USER_SEARCH_LIMIT: i64 = 50;
+const MIN_USER_SEARCH_QUERY_LENGTH: usize = 2;
+
+pub async fn update_me(
+    pool: web::Data<PgPool>,
+    user: AuthUser,
+    body: web::Json<UpdateProfileRequest>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user.id;
+
+    if let Some(avatar_url) = body.avatar_url.as_deref().filter(|url| !url.is_empty()) {
+        validate_http_url(avatar_url).map_err(actix_web::error::ErrorBadRequest)?;
+    }
+
+    let user = sqlx::query_as::<_, User>(
+        r#"
+        UPDATE users
+        SET avatar_url = CASE WHEN $1::text IS NULL THEN avatar_url ELSE NULLIF($1, '') END,
+            discoverable = COALESCE($2, discoverable)
+        WHERE id = $3
+        RETURNING id, username, email, password_hash, avatar_url, created_at, token_version
+        "#,
+    )
+    .bind(&body.avatar_url)
+    .bind(body.discoverable)
+    .bind(user_id)
+    .fetch_optional(pool.get_ref())
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?
+    .ok_or_else(|| actix_web::error::ErrorNotFound("User not found"))?;
+
+    Ok(HttpResponse::Ok().json(UserResponse::from(user)))
+}
+
+pub async fn update_username(
+    pool: web::Data<PgPool>,
+    server: web::Data<ChatServerHandle>,
+    user: AuthUser,
+    body: web::Json<UpdateUsernameRequest>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let username = body.username.trim();
+
+    if let Err(reason) = validate_username(username) {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse::for_field("username", reason)));
+    }
+
+    let updated = sqlx::query_as::<_, User>(
+        r#"
+        UPDATE users
+        SET username = $1
+        WHERE id = $2 AND deleted_at IS NULL
+        RETURNING id, username, email, password_hash, avatar_url, created_at, token_version
+        "#,
+    )
+    .bind(username)
+    .bind(user.id)
+    .fetch_optional(pool.get_ref())
+    .await;
+
+    let updated = match updated {
+        Ok(Some(updated)) => updated,
+        Ok(None) => return Err(actix_web::error::ErrorNotFound("User not found")),
+        Err(sqlx::Error::Database(db_err)) if db_err.constraint() == Some("users_username_key") => {
+            return Ok(HttpResponse::Conflict().json(ErrorResponse::for_field(
+                "username",
+                "Username already exists",
+            )));
+        }
+        Err(_) => return Err(actix_web::error::ErrorInternalServerError("Database error")),
+    };
+
+    server.rename_user(updated.id, updated.username.clone());
+
+    // the old token still carries the previous username
+    let token = create_jwt(updated.id, &updated.username, updated.token_version)
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to create token"))?;
+
+    Ok(HttpResponse::Ok().json(AuthResponse {
+        token,
+        user: updated.into(),
+    }))
+}
+
+/// Removes the user from every channel, returning how many they left and which were deleted.
+///
+/// Where the user is the only admin, the longest-standing remaining member is promoted;
+/// channels with no other members are deleted outright.
+async fn leave_all_channels(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+) -> Result<(u64, Vec<Uuid>), sqlx::Error> {
+    let deleted_channels = sqlx::query_scalar::<_, Uuid>(
+        r#"
+        DELETE FROM channels c
+        WHERE EXISTS (
+                SELECT 1 FROM channel_members cm
+                WHERE cm.channel_id = c.id AND cm.user_id = $1
+            )
+            AND NOT EXISTS (
+                SELECT 1 FROM channel_members cm
+                WHERE cm.channel_id = c.id AND cm.user_id <> $1
+            )
+        RETURNING c.id
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(&mut **tx)
+    .await?;
+
+    sqlx::query(
+        r#"
+        UPDATE channel_members cm
+        SET role = 'admin'
+        FROM (
+            SELECT DISTINCT ON (other.channel_id) other.channel_id, other.user_id
+            FROM channel_members me
+            INNER JOIN channel_members other
+                ON other.channel_id = me.channel_id AND other.user_id <> me.user_id
+            WHERE me.user_id = $1 AND me.role = 'admin'
+                AND NOT EXISTS (
+                    SELECT 1 FROM channel_members a
+                    WHERE a.channel_id = me.channel_id AND a.role = 'admin' AND a.user_id <> $1
+                )
+            ORDER BY other.channel_id, other.joined_at, other.user_id
+        ) heir
+        WHERE cm.channel_id = heir.channel_id AND cm.user_id = heir.user_id
+        "#,
+    )
+    .bind(user_id)
+    .execute(&mut **tx)
+    .await?;
+
+    let left = sqlx::query(
+        r#"
+        DELETE FROM channel_members
+        WHERE user_id = $1
+        "#,
+    )
+    .bind(user_id)
+    .execute(&mut **tx)
+    .await?
+    .rows_affected();
+
+    Ok((left + deleted_channels.len() as u64, deleted_channels))
+}
+
+pub async fn leave_all(
+    pool: web::Data<PgPool>,
+    server: web::Data<ChatServerHandle>,
+    user: AuthUser,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut tx = transaction::begin(pool.get_ref()).await?;
+
+    let (left, deleted_channels) = leave_all_channels(&mut tx, user.id)
+        .await
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to leave channels"))?;
+
+    transaction::commit(tx).await?;
+
+    server.disconnect_user(user.id);
+    for channel_id in &deleted_channels {
+        server.close_channel(*channel_id);
+    }
+
+    Ok(HttpResponse::Ok().json(LeaveAllResponse {
+        left,
+        deleted_channels,
+    }))
+}
+
+pub async fn delete_me(
+    pool: web::Data<PgPool>,
+    server: web::Data<ChatServerHandle>,
+    user: AuthUser,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user.id;
+
+    let mut tx = transaction::begin(pool.get_ref()).await?;
+
+    // keep the row so messages stay attributed, but scrub identifying data
+    let deleted = sqlx::query(
+        r#"
+        UPDATE users
+        SET deleted_at = NOW(),
+            username = 'deleted-' || id::text,
+            email = 'deleted-' || id::text || '@deleted.invalid',
+            avatar_url = NULL
+        WHERE id = $1 AND deleted_at IS NULL
+        "#,
+    )
+    .bind(user_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to delete account"))?;
+
+    if deleted.rows_affected() == 0 {
+        return Err(actix_web::error::ErrorNotFound("User not found"));
+    }
+
+    let (_, deleted_channels) = leave_all_channels(&mut tx, user_id)
+        .await
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to leave channels"))?;
+
+    sqlx::query(
+        r#"
+        DELETE FROM invitations
+        WHERE invitee_id = $1 AND status = 'pending'
+        "#,
+    )
+    .bind(user_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to remove invitations"))?;
+
+    transaction::commit(tx).await?;
+
+    server.disconnect_user(user_id);
+    for channel_id in &deleted_channels {
+        server.close_channel(*channel_id);
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn search_users(
+    pool: web::Data<PgPool>,
+    user: AuthUser,
+    query: web::Query<UserSearchQuery>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let term = query.q.trim();
+    if term.chars().count() < MIN_USER_SEARCH_QUERY_LENGTH {
+        return Err(actix_web::error::ErrorBadRequest(
+            "Search query is too short",
+        ));
+    }
+
+    let pattern = format!("{}%", escape_like(term));
+    let limit = clamp_limit(
+        query.limit,
+        DEFAULT_USER_SEARCH_LIMIT,
+        MAX_USER_SEARCH_LIMIT,
+    );
+
+    // emails only match exactly, a prefix match would let anyone guess them letter by letter
+    let users = sqlx::query_as::<_, UserSearchResult>(
+        r#"
+        SELECT id, username, avatar_url
+        FROM users
+        WHERE deleted_at IS NULL AND discoverable AND id <> $1
+            AND (username ILIKE $2 OR LOWER(email) = LOWER($3))
+        ORDER BY username
+        LIMIT $4
+        "#,
+    )
+    .bind(user.id)
+    .bind(&pattern)
+    .bind(term)
+    .bind(limit)
+    .fetch_all(pool.get_ref())
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to search users"))?;
+
+    Ok(HttpResponse::Ok().json(users))
+}
+
+/// Presence of a user the caller can find in search or shares a channel with.
+///
+/// Anyone else is reported as not found, the same as a user that doesn't exist.
+pub async fn get_presence(
+    pool: web::Data<PgPool>,
+    server: web::Data<ChatServerHandle>,
+    user: AuthUser,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = path.into_inner();
+
+    let last_seen_at = sqlx::query_scalar::<_, Option<DateTime<Utc>>>(
+        r#"
+        SELECT u.last_seen_at FROM users u
+        WHERE u.id = $1 AND u.deleted_at IS NULL
+            AND (u.id = $2 OR u.discoverable OR EXISTS (
+                SELECT 1 FROM channel_members mine
+                INNER JOIN channel_members theirs ON theirs.channel_id = mine.channel_id
+                WHERE mine.user_id = $2 AND theirs.user_id = u.id
+            ))
+        "#,
+    )
+    .bind(user_id)
+    .bind(user.id)
+    .fetch_optional(pool.get_ref())
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?
+    .ok_or_else(|| actix_web::error::ErrorNotFound("User not found"))?;
+
+    let is_online = server.is_online(user_id).await;
+
+    Ok(HttpResponse::Ok().json(PresenceResponse {
+        user_id,
+        is_online,
+        last_seen_at,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        handlers::websocket::open_session,
+        models::role::Role,
+        test_support::{
+            add_member, app, create_channel, create_user, start_server, TestUser, PASSWORD,
+        },
+    };
+    use actix_web::{http::StatusCode, test};
+    use serde_json::{json, Value};
+    use sqlx::PgPool;
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    #[sqlx::test]
+    async fn avatar_url_can_be_set_and_cleared(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+
+        let req = test::TestRequest::patch()
+            .uri("/api/me")
+            .insert_header(alice.bearer())
+            .set_json(json!({ "avatar_url": "https://cdn.example.com/alice.png" }))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["avatar_url"], "https://cdn.example.com/alice.png");
+
+        // leaving the field out keeps the avatar, an empty string clears it
+        let req = test::TestRequest::patch()
+            .uri("/api/me")
+            .insert_header(alice.bearer())
+            .set_json(json!({}))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["avatar_url"], "https://cdn.example.com/alice.png");
+
+        let req = test::TestRequest::patch()
+            .uri("/api/me")
+            .insert_header(alice.bearer())
+            .set_json(json!({ "avatar_url": "" }))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["avatar_url"], Value::Null);
+    }
+
+    #[sqlx::test]
+    async fn avatar_url_must_be_an_http_url(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+
+        for avatar_url in [
+            "not a url",
+            "javascript:alert(1)",
+            "ftp://example.com/a.png",
+        ] {
+            let req = test::TestRequest::patch()
+                .uri("/api/me")
+                .insert_header(alice.bearer())
+                .set_json(json!({ "avatar_url": avatar_url }))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST, "{}", avatar_url);
+        }
+
+        let stored =
+            sqlx::query_scalar::<_, Option<String>>("SELECT avatar_url FROM users WHERE id = $1")
+                .bind(alice.id)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(stored, None);
+    }
+
+    #[sqlx::test]
+    async fn username_can_be_changed_unless_taken(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        create_user(&pool, "bob").await;
+
+        let req = test::TestRequest::patch()
+            .uri("/api/me/username")
+            .insert_header(alice.bearer())
+            .set_json(json!({ "username": "alicia" }))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["user"]["username"], "alicia");
+        assert!(body["token"].is_string());
+
+        let req = test::TestRequest::patch()
+            .uri("/api/me/username")
+            .insert_header(alice.bearer())
+            .set_json(json!({ "username": "bob" }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["field"], "username");
+        assert_eq!(body["error"], "Username already exists");
+    }
+
+    #[sqlx::test]
+    async fn token_from_before_a_rename_acts_under_the_new_name(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+        let channel_id = create_channel(&pool, &bob, "general").await;
+        sqlx::query("UPDATE channels SET is_public = TRUE WHERE id = $1")
+            .bind(channel_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let req = test::TestRequest::patch()
+            .uri("/api/me/username")
+            .insert_header(alice.bearer())
+            .set_json(json!({ "username": "alicia" }))
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+
+        // still the token that says "alice"
+        let req = test::TestRequest::post()
+            .uri(&format!("/api/channels/{}/join", channel_id))
+            .insert_header(alice.bearer())
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+
+        let joined = sqlx::query_scalar::<_, String>(
+            "SELECT content FROM messages WHERE channel_id = $1 AND kind = 'system'",
+        )
+        .bind(channel_id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(joined, "alicia joined the channel");
+    }
+
+    #[sqlx::test]
+    async fn presence_follows_the_users_sockets(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+        let presence = |bob: &TestUser| {
+            test::TestRequest::get()
+                .uri(&format!("/api/users/{}/presence", bob.id))
+                .insert_header(alice.bearer())
+                .to_request()
+        };
+
+        let body: Value = test::call_and_read_body_json(&app, presence(&bob)).await;
+        assert_eq!(body["is_online"], false);
+        assert_eq!(body["last_seen_at"], Value::Null);
+
+        let (conn, _rx) = open_session(&server, bob.id, "bob", Uuid::new_v4(), Role::Member);
+        let body: Value = test::call_and_read_body_json(&app, presence(&bob)).await;
+        assert_eq!(body["is_online"], true);
+
+        // last seen is written in the background once the last socket is gone
+        server.disconnect(conn);
+        let mut body = Value::Null;
+        for _ in 0..50 {
+            body = test::call_and_read_body_json(&app, presence(&bob)).await;
+            if !body["last_seen_at"].is_null() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(body["is_online"], false);
+        assert!(body["last_seen_at"].is_string());
+    }
+
+    #[sqlx::test]
+    async fn presence_of_a_hidden_user_needs_a_shared_channel(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+        sqlx::query("UPDATE users SET discoverable = FALSE WHERE id = $1")
+            .bind(bob.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        let presence = || {
+            test::TestRequest::get()
+                .uri(&format!("/api/users/{}/presence", bob.id))
+                .insert_header(alice.bearer())
+                .to_request()
+        };
+
+        let resp = test::call_service(&app, presence()).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        let channel_id = create_channel(&pool, &alice, "general").await;
+        add_member(&pool, channel_id, &bob, Role::Member).await;
+        let resp = test::call_service(&app, presence()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[sqlx::test]
+    async fn deleted_account_cannot_authenticate_and_leaves_its_channels(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+        let channel_id = create_channel(&pool, &bob, "general").await;
+        add_member(&pool, channel_id, &alice, Role::Member).await;
+        sqlx::query("INSERT INTO messages (channel_id, user_id, content) VALUES ($1, $2, 'hi')")
+            .bind(channel_id)
+            .bind(alice.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let req = test::TestRequest::delete()
+            .uri("/api/me")
+            .insert_header(alice.bearer())
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::NO_CONTENT
+        );
+
+        let req = test::TestRequest::post()
+            .uri("/api/auth/login")
+            .set_json(json!({ "email": "alice@example.com", "password": PASSWORD }))
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::UNAUTHORIZED
+        );
+        let req = test::TestRequest::patch()
+            .uri("/api/me")
+            .insert_header(alice.bearer())
+            .set_json(json!({}))
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::UNAUTHORIZED
+        );
+
+        let memberships =
+            sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM channel_members WHERE user_id = $1")
+                .bind(alice.id)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(memberships, 0);
+
+        // the message stays, under the placeholder name
+        let req = test::TestRequest::get()
+            .uri(&format!("/api/channels/{}/messages", channel_id))
+            .insert_header(bob.bearer())
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body[0]["content"], "hi");
+        assert_eq!(body[0]["username"], format!("deleted-{}", alice.id));
+    }
+
+    #[sqlx::test]
+    async fn leaving_everything_hands_over_sole_admin_channels(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+        let carol = create_user(&pool, "carol").await;
+        let dave = create_user(&pool, "dave").await;
+
+        let solo = create_channel(&pool, &alice, "solo").await;
+        let shared = create_channel(&pool, &alice, "shared").await;
+        add_member(&pool, shared, &bob, Role::Member).await;
+        add_member(&pool, shared, &carol, Role::Member).await;
+        let elsewhere = create_channel(&pool, &dave, "elsewhere").await;
+        add_member(&pool, elsewhere, &alice, Role::Member).await;
+        let (_, mut alice_rx) = open_session(&server, alice.id, "alice", shared, Role::Admin);
+
+        let req = test::TestRequest::post()
+            .uri("/api/me/leave-all")
+            .insert_header(alice.bearer())
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["left"], 3);
+        assert_eq!(body["deleted_channels"], json!([solo]));
+
+        // the longest-standing member inherits the admin seat
+        let roles = sqlx::query_as::<_, (String, Uuid, Role)>(
+            r#"
+            SELECT c.name, cm.user_id, cm.role
+            FROM channel_members cm
+            INNER JOIN channels c ON c.id = cm.channel_id
+            ORDER BY c.name, cm.joined_at
+            "#,
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            roles,
+            [
+                ("elsewhere".to_string(), dave.id, Role::Admin),
+                ("shared".to_string(), bob.id, Role::Admin),
+                ("shared".to_string(), carol.id, Role::Member),
+            ]
+        );
+
+        // and the live session is closed
+        while alice_rx.recv().await.is_some() {}
+    }
+
+    #[sqlx::test]
+    async fn search_matches_prefixes_and_leaves_out_the_caller(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        for name in ["alan", "albert", "bob"] {
+            create_user(&pool, name).await;
+        }
+        let alma = create_user(&pool, "alma").await;
+        sqlx::query("UPDATE users SET discoverable = FALSE WHERE id = $1")
+            .bind(alma.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let search = |query: &str| {
+            test::TestRequest::get()
+                .uri(&format!("/api/users/search?{}", query))
+                .insert_header(alice.bearer())
+                .to_request()
+        };
+        let names = |body: Value| -> Vec<String> {
+            body.as_array()
+                .unwrap()
+                .iter()
+                .map(|user| user["username"].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        let body: Value = test::call_and_read_body_json(&app, search("q=AL")).await;
+        assert!(body[0].get("email").is_none());
+        assert_eq!(names(body), ["alan", "albert"]);
+        let body = test::call_and_read_body_json(&app, search("q=al&limit=1")).await;
+        assert_eq!(names(body), ["alan"]);
+
+        // emails have to be typed out in full
+        let body = test::call_and_read_body_json(&app, search("q=bob@example.com")).await;
+        assert_eq!(names(body), ["bob"]);
+        let body = test::call_and_read_body_json(&app, search("q=bob@exam")).await;
+        assert!(names(body).is_empty());
+
+        assert_eq!(
+            test::call_service(&app, search("q=a")).await.status(),
+            StatusCode::BAD_REQUEST
+        );
+    }
+}
@@ -1,16 +1,35 @@
-use crate::models::WsMessage;
-use crate::models::{ClientMessage, Message as DbMessage};
-use actix_web::{web, HttpRequest, HttpResponse};
-use actix_ws::Message as WsFrameMessage;
+use crate::config::persist_system_messages;
+use crate::models::role::Role;
+use crate::models::{
+    Attachment, ClientMessage, Message as DbMessage, MessageFormat, MessageKind, MessageResponse,
+    OnlineUser, TypingUser, WsErrorCode, WsMessage,
+};
+use crate::utils::{
+    access::member_role,
+    sanitize::sanitize_content,
+    task_tracker::TaskTracker,
+    validation::{validate_attachments, validate_message_content, ContentError},
+    word_filter::word_filter,
+};
+use actix_web::{
+    http::header::{HeaderValue, SEC_WEBSOCKET_PROTOCOL},
+    web, HttpRequest, HttpResponse,
+};
+use actix_ws::{CloseCode, CloseReason, Message as WsFrameMessage, ProtocolError};
+use chrono::{DateTime, Utc};
 use futures_util::StreamExt;
 use sqlx::PgPool;
+use std::future::Future;
 use std::time::Duration;
 use std::time::Instant;
 use std::{
     collections::{HashMap, HashSet},
     env,
 };
-use tokio::sync::mpsc;
+use tokio::sync::{
+    mpsc::{self, error::TrySendError},
+    oneshot, watch,
+};
 use uuid::Uuid;
 
 static CON_ID_COUNTER: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(1);
@@ -21,6 +40,146 @@ fn next_conn_id() -> ConnId {
 
 const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
 const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Repeated `is_typing: true` within this window are not rebroadcast.
+const TYPING_DEDUP_WINDOW: Duration = Duration::from_secs(3);
+/// A typer who hasn't repeated `is_typing: true` for this long counts as stopped, so a
+/// client that goes quiet without sending `false` doesn't linger.
+const TYPING_TTL: Duration = Duration::from_secs(10);
+/// How long a socket opened without a token has to send `ClientMessage::Auth`.
+const AUTH_TIMEOUT: Duration = Duration::from_secs(5);
+const AUTH_SUBPROTOCOL: &str = "bearer";
+/// How long a resume token stays usable once its connection has dropped.
+const RESUME_TTL: Duration = Duration::from_secs(60);
+/// Read marks are flushed at most this often per connection; only the latest one is kept.
+const READ_RECEIPT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Tries per chat message before it is written to `failed_messages`.
+const MAX_INSERT_ATTEMPTS: u32 = 3;
+/// Doubled after every failed attempt.
+const INSERT_RETRY_BACKOFF: Duration = Duration::from_millis(100);
+
+const DEFAULT_SEND_BUFFER: usize = 256;
+const DEFAULT_DUPLICATE_WINDOW_MS: u64 = 2000;
+const DEFAULT_MAX_FRAME_SIZE: usize = 64 * 1024;
+const DEFAULT_HISTORY_LIMIT: i64 = 50;
+const MAX_HISTORY_LIMIT: i64 = 200;
+/// Users per `online_snapshot` frame; the rest are fetched with `load_online`.
+const ONLINE_SNAPSHOT_PAGE: usize = 100;
+const DEFAULT_MAX_CONNECTIONS_PER_USER: usize = 20;
+const DEFAULT_MAX_CONNECTIONS_PER_CHANNEL: usize = 5;
+
+/// Outgoing frames buffered per session before the client is considered too slow.
+fn send_buffer_capacity() -> usize {
+    env::var("WS_SEND_BUFFER")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .filter(|capacity| *capacity > 0)
+        .unwrap_or(DEFAULT_SEND_BUFFER)
+}
+
+/// How long an identical message from the same connection is treated as a double submit.
+///
+/// Read from `WS_DUPLICATE_WINDOW_MS`; 0 turns the check off.
+fn duplicate_window() -> Duration {
+    let millis = env::var("WS_DUPLICATE_WINDOW_MS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_DUPLICATE_WINDOW_MS);
+    Duration::from_millis(millis)
+}
+
+/// Largest frame accepted from a client, from `WS_MAX_FRAME_SIZE` in bytes or 64 KiB.
+///
+/// Bigger frames close the connection with `1009`; 0 or an invalid value uses the default.
+fn max_frame_size() -> usize {
+    env::var("WS_MAX_FRAME_SIZE")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .filter(|size| *size > 0)
+        .unwrap_or(DEFAULT_MAX_FRAME_SIZE)
+}
+
+/// Messages replayed on connect or per `load_more`, from the request, `WS_HISTORY_LIMIT`, or 50.
+///
+/// Capped at `MAX_HISTORY_LIMIT`; 0 disables the replay on connect.
+fn history_limit(requested: Option<i64>) -> i64 {
+    requested
+        .or_else(|| {
+            env::var("WS_HISTORY_LIMIT")
+                .ok()
+                .and_then(|value| value.parse().ok())
+        })
+        .unwrap_or(DEFAULT_HISTORY_LIMIT)
+        .clamp(0, MAX_HISTORY_LIMIT)
+}
+
+/// What happens to a connect that would put a user over their connection limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LimitPolicy {
+    CloseOldest,
+    RejectNewest,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ConnectionLimits {
+    per_user: usize,
+    per_user_channel: usize,
+    policy: LimitPolicy,
+}
+
+impl ConnectionLimits {
+    /// Reads `WS_MAX_CONNECTIONS_PER_USER`, `WS_MAX_CONNECTIONS_PER_CHANNEL` and
+    /// `WS_CONNECTION_LIMIT_POLICY` (`close_oldest` by default, or `reject_newest`).
+    fn from_env() -> Self {
+        let limit = |name: &str, default: usize| {
+            env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .filter(|limit| *limit > 0)
+                .unwrap_or(default)
+        };
+
+        let policy = match env::var("WS_CONNECTION_LIMIT_POLICY").as_deref() {
+            Ok("reject_newest") => LimitPolicy::RejectNewest,
+            _ => LimitPolicy::CloseOldest,
+        };
+
+        Self {
+            per_user: limit(
+                "WS_MAX_CONNECTIONS_PER_USER",
+                DEFAULT_MAX_CONNECTIONS_PER_USER,
+            ),
+            per_user_channel: limit(
+                "WS_MAX_CONNECTIONS_PER_CHANNEL",
+                DEFAULT_MAX_CONNECTIONS_PER_CHANNEL,
+            ),
+            policy,
+        }
+    }
+}
+
+fn too_many_connections() -> WsMessage {
+    WsMessage::Error {
+        code: WsErrorCode::TooManyConnections,
+        message: "Too many open connections for this user".to_string(),
+        retry_after: None,
+    }
+}
+
+/// Rounds a wait up to whole seconds, so a client waiting exactly this long isn't
+/// rejected again.
+pub fn retry_after_secs(remaining: Duration) -> u64 {
+    remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0)
+}
+
+fn slow_mode_error(remaining: Duration) -> WsMessage {
+    let seconds = retry_after_secs(remaining);
+    WsMessage::Error {
+        code: WsErrorCode::SlowMode,
+        message: format!("Slow mode is on, wait {} seconds", seconds),
+        retry_after: Some(seconds),
+    }
+}
 
 type ConnId = u64;
 type Msg = String;
@@ -31,26 +190,142 @@ enum Command {
         conn_id: ConnId,
         user_id: Uuid,
         username: String,
+        avatar_url: Option<String>,
+        role: Role,
         channel_id: Uuid,
-        tx: mpsc::UnboundedSender<Msg>,
+        tx: mpsc::Sender<Msg>,
+        role_tx: watch::Sender<Role>,
     },
     Disconnect {
         conn_id: ConnId,
     },
+    Resume {
+        token: String,
+        channel_id: Uuid,
+        respond_to: oneshot::Sender<Option<WsIdentity>>,
+    },
     Message {
         conn_id: ConnId,
         channel_id: Uuid,
         message: WsMessage,
     },
+    Broadcast {
+        channel_id: Uuid,
+        message: WsMessage,
+    },
+    Typing {
+        conn_id: ConnId,
+        is_typing: bool,
+        parent_id: Option<Uuid>,
+    },
+    GetTyping {
+        conn_id: ConnId,
+    },
+    GetOnline {
+        conn_id: ConnId,
+        offset: usize,
+    },
+    RenameUser {
+        user_id: Uuid,
+        username: String,
+    },
+    ChangeRole {
+        channel_id: Uuid,
+        user_id: Uuid,
+        role: Role,
+    },
+    DisconnectUser {
+        user_id: Uuid,
+    },
+    SendToUser {
+        user_id: Uuid,
+        message: WsMessage,
+    },
+    Presence {
+        contacts: Vec<Uuid>,
+        message: WsMessage,
+    },
+    CloseChannel {
+        channel_id: Uuid,
+    },
+    IsOnline {
+        user_id: Uuid,
+        respond_to: oneshot::Sender<bool>,
+    },
+    CheckDuplicate {
+        user_id: Uuid,
+        channel_id: Uuid,
+        content: String,
+        attachments: Vec<Attachment>,
+        respond_to: oneshot::Sender<bool>,
+    },
+    Metrics {
+        respond_to: oneshot::Sender<ServerMetrics>,
+    },
+}
+
+/// Snapshot of the chat server's live load.
+#[derive(Debug, Clone, Copy)]
+pub struct ServerMetrics {
+    pub active_connections: usize,
+    pub active_channels: usize,
+    pub online_users: usize,
+    pub messages_relayed: u64,
+    pub dropped_clients: u64,
+}
+
+#[derive(Debug)]
+struct SessionInfo {
+    user_id: Uuid,
+    username: String,
+    avatar_url: Option<String>,
+    role: Role,
+    channel_id: Uuid,
+    resume_token: String,
+    /// Pushes role changes to the session task, which checks permissions locally.
+    role_tx: watch::Sender<Role>,
+}
+
+/// What a resume token restores, so a reconnect can skip the token and membership checks.
+#[derive(Debug)]
+struct ResumeTicket {
+    user_id: Uuid,
+    username: String,
+    avatar_url: Option<String>,
+    role: Role,
+    channel_id: Uuid,
+    /// Unset while the issuing connection is still open.
+    expires_at: Option<Instant>,
+}
+
+impl ResumeTicket {
+    fn is_expired(&self) -> bool {
+        self.expires_at.is_some_and(|at| at <= Instant::now())
+    }
 }
 
 pub struct ChatServer {
-    sessions: HashMap<ConnId, mpsc::UnboundedSender<Msg>>,
-    session_info: HashMap<ConnId, (Uuid, String, Uuid)>,
+    sessions: HashMap<ConnId, mpsc::Sender<Msg>>,
+    session_info: HashMap<ConnId, SessionInfo>,
     channels: HashMap<Uuid, HashSet<ConnId>>,
-    #[allow(dead_code)]
+    users: HashMap<Uuid, HashSet<ConnId>>,
+    /// Who is typing per channel, with when they started and the thread they're replying in.
+    typing: HashMap<Uuid, HashMap<Uuid, (Instant, Option<Uuid>)>>,
+    /// Single-use tokens handed out on connect, keyed by token.
+    resume_tickets: HashMap<String, ResumeTicket>,
+    /// Each user's last message per channel, so a double submit is caught whichever of
+    /// their sockets it comes from.
+    recent_messages: HashMap<(Uuid, Uuid), (String, Vec<Attachment>, Instant)>,
+    duplicate_window: Duration,
+    /// Sessions whose send buffer overflowed, disconnected after the current command.
+    slow_sessions: Vec<ConnId>,
+    dropped_clients: u64,
+    messages_relayed: u64,
+    limits: ConnectionLimits,
     db_pool: PgPool,
     cmd_rx: mpsc::UnboundedReceiver<Command>,
+    /// Lets background lookups report back; weak so it never keeps the server alive.
+    cmd_tx: mpsc::WeakUnboundedSender<Command>,
 }
 
 impl ChatServer {
@@ -61,11 +336,24 @@ impl ChatServer {
             sessions: HashMap::new(),
             session_info: HashMap::new(),
             channels: HashMap::new(),
+            users: HashMap::new(),
+            typing: HashMap::new(),
+            resume_tickets: HashMap::new(),
+            recent_messages: HashMap::new(),
+            duplicate_window: duplicate_window(),
+            slow_sessions: Vec::new(),
+            dropped_clients: 0,
+            messages_relayed: 0,
+            limits: ConnectionLimits::from_env(),
             db_pool,
             cmd_rx,
+            cmd_tx: cmd_tx.downgrade(),
         };
 
-        let handle = ChatServerHandle { cmd_tx };
+        let handle = ChatServerHandle {
+            cmd_tx,
+            inserts: TaskTracker::on_current_runtime(),
+        };
 
         (server, handle)
     }
@@ -77,44 +365,637 @@ impl ChatServer {
                     conn_id,
                     user_id,
                     username,
+                    avatar_url,
+                    role,
                     channel_id,
                     tx,
+                    role_tx,
                 } => {
+                    if !self.make_room(user_id, channel_id) {
+                        // dropping tx right after the error closes the new socket
+                        let error = serde_json::to_string(&too_many_connections()).unwrap();
+                        let _ = tx.try_send(error);
+                        continue;
+                    }
+
+                    self.resume_tickets.retain(|_, ticket| !ticket.is_expired());
+                    let window = self.duplicate_window;
+                    self.recent_messages
+                        .retain(|_, (_, _, sent_at)| sent_at.elapsed() < window);
+                    let resume_token =
+                        format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+                    self.resume_tickets.insert(
+                        resume_token.clone(),
+                        ResumeTicket {
+                            user_id,
+                            username: username.clone(),
+                            avatar_url: avatar_url.clone(),
+                            role,
+                            channel_id,
+                            expires_at: None,
+                        },
+                    );
+
                     self.sessions.insert(conn_id, tx);
-                    self.session_info
-                        .insert(conn_id, (user_id, username.clone(), channel_id));
+                    self.session_info.insert(
+                        conn_id,
+                        SessionInfo {
+                            user_id,
+                            username: username.clone(),
+                            avatar_url: avatar_url.clone(),
+                            role,
+                            channel_id,
+                            resume_token: resume_token.clone(),
+                            role_tx,
+                        },
+                    );
                     self.channels.entry(channel_id).or_default().insert(conn_id);
-                    let join_message = WsMessage::UserJoined { user_id, username };
+                    let came_online = !self.users.contains_key(&user_id);
+                    self.users.entry(user_id).or_default().insert(conn_id);
+                    if came_online {
+                        self.announce_presence(user_id, username.clone(), avatar_url.clone(), true);
+                    }
+                    let join_message = WsMessage::UserJoined {
+                        user_id,
+                        username,
+                        avatar_url,
+                    };
                     self.send_to_channel(&channel_id, join_message, Some(conn_id));
+                    let resume = WsMessage::ResumeToken {
+                        token: resume_token,
+                        ttl_secs: RESUME_TTL.as_secs(),
+                    };
+                    self.send_to_session(conn_id, &resume);
+                    self.send_online_snapshot(conn_id, channel_id, 0);
                 }
                 Command::Disconnect { conn_id } => {
-                    self.sessions.remove(&conn_id);
-                    if let Some((user_id, username, channel_id)) =
-                        self.session_info.remove(&conn_id)
-                    {
-                        if let Some(sessions) = self.channels.get_mut(&channel_id) {
-                            sessions.remove(&conn_id);
-                            if sessions.is_empty() {
-                                self.channels.remove(&channel_id);
-                            }
+                    self.disconnect(conn_id);
+                }
+                Command::Resume {
+                    token,
+                    channel_id,
+                    respond_to,
+                } => {
+                    let _ = respond_to.send(self.take_resume_ticket(&token, channel_id));
+                }
+                Command::Message {
+                    conn_id,
+                    channel_id,
+                    mut message,
+                } => {
+                    // the session's name is authoritative, it may have changed since connecting
+                    if let WsMessage::ChatMessage { username, .. } = &mut message {
+                        if let Some(info) = self.session_info.get(&conn_id) {
+                            username.clone_from(&info.username);
                         }
+                    }
 
-                        let leave_msg = WsMessage::UserLeft { user_id, username };
-                        self.send_to_channel(&channel_id, leave_msg, None);
+                    // never relay chat from a read-only session, whatever the handler did; a
+                    // session that closed while its insert ran was checked when it sent
+                    let is_chat = matches!(message, WsMessage::ChatMessage { .. });
+                    let read_only = self
+                        .session_info
+                        .get(&conn_id)
+                        .is_some_and(|info| !info.role.can_post());
+
+                    if is_chat && read_only {
+                        log::warn!("Ignoring chat message from read-only session {}", conn_id);
+                    } else {
+                        if is_chat {
+                            self.messages_relayed += 1;
+                        }
+                        self.send_to_channel(&channel_id, message, Some(conn_id));
                     }
                 }
-                Command::Message {
-                    conn_id,
+                Command::Broadcast {
                     channel_id,
                     message,
                 } => {
-                    self.send_to_channel(&channel_id, message, Some(conn_id));
+                    self.send_to_channel(&channel_id, message, None);
+                }
+                Command::Typing {
+                    conn_id,
+                    is_typing,
+                    parent_id,
+                } => {
+                    self.handle_typing(conn_id, is_typing, parent_id);
+                }
+                Command::GetTyping { conn_id } => {
+                    self.send_typing_users(conn_id);
+                }
+                Command::GetOnline { conn_id, offset } => {
+                    if let Some(info) = self.session_info.get(&conn_id) {
+                        let channel_id = info.channel_id;
+                        self.send_online_snapshot(conn_id, channel_id, offset);
+                    }
+                }
+                Command::RenameUser { user_id, username } => {
+                    for conn_id in self.users.get(&user_id).into_iter().flatten() {
+                        if let Some(info) = self.session_info.get_mut(conn_id) {
+                            info.username = username.clone();
+                        }
+                    }
+                    for ticket in self.resume_tickets.values_mut() {
+                        if ticket.user_id == user_id {
+                            ticket.username = username.clone();
+                        }
+                    }
+                }
+                Command::ChangeRole {
+                    channel_id,
+                    user_id,
+                    role,
+                } => {
+                    for conn_id in self.users.get(&user_id).into_iter().flatten() {
+                        if let Some(info) = self.session_info.get_mut(conn_id) {
+                            if info.channel_id == channel_id {
+                                info.role = role;
+                                info.role_tx.send_replace(role);
+                            }
+                        }
+                    }
+                    for ticket in self.resume_tickets.values_mut() {
+                        if ticket.user_id == user_id && ticket.channel_id == channel_id {
+                            ticket.role = role;
+                        }
+                    }
+
+                    let changed = WsMessage::RoleChanged {
+                        channel_id,
+                        user_id,
+                        role,
+                    };
+                    self.send_to_channel(&channel_id, changed, None);
+                }
+                Command::DisconnectUser { user_id } => {
+                    let conns: Vec<ConnId> = self
+                        .users
+                        .get(&user_id)
+                        .into_iter()
+                        .flatten()
+                        .copied()
+                        .collect();
+                    for conn_id in conns {
+                        self.disconnect(conn_id);
+                    }
+                    self.resume_tickets
+                        .retain(|_, ticket| ticket.user_id != user_id);
+                }
+                Command::SendToUser { user_id, message } => {
+                    let conns: Vec<ConnId> = self
+                        .users
+                        .get(&user_id)
+                        .into_iter()
+                        .flatten()
+                        .copied()
+                        .collect();
+                    for conn_id in conns {
+                        self.send_to_session(conn_id, &message);
+                    }
+                }
+                Command::Presence { contacts, message } => {
+                    self.deliver_presence(&contacts, &message);
+                }
+                Command::CloseChannel { channel_id } => {
+                    self.close_channel(channel_id);
+                    self.resume_tickets
+                        .retain(|_, ticket| ticket.channel_id != channel_id);
+                }
+                Command::IsOnline {
+                    user_id,
+                    respond_to,
+                } => {
+                    let _ = respond_to.send(self.users.contains_key(&user_id));
+                }
+                Command::CheckDuplicate {
+                    user_id,
+                    channel_id,
+                    content,
+                    attachments,
+                    respond_to,
+                } => {
+                    let is_duplicate =
+                        self.check_duplicate(user_id, channel_id, content, attachments);
+                    let _ = respond_to.send(is_duplicate);
+                }
+                Command::Metrics { respond_to } => {
+                    let _ = respond_to.send(ServerMetrics {
+                        active_connections: self.sessions.len(),
+                        active_channels: self.channels.len(),
+                        online_users: self.users.len(),
+                        messages_relayed: self.messages_relayed,
+                        dropped_clients: self.dropped_clients,
+                    });
+                }
+            }
+
+            // evicting a slow session broadcasts a leave, which may overflow another one
+            while let Some(conn_id) = self.slow_sessions.pop() {
+                if self.session_info.contains_key(&conn_id) {
+                    self.dropped_clients += 1;
+                    log::warn!(
+                        "Dropping slow WebSocket client {} ({} dropped so far)",
+                        conn_id,
+                        self.dropped_clients
+                    );
+                    self.disconnect(conn_id);
+                }
+            }
+        }
+    }
+
+    fn disconnect(&mut self, conn_id: ConnId) {
+        if let Some(SessionInfo {
+            user_id,
+            username,
+            channel_id,
+            ..
+        }) = self.remove_session(conn_id)
+        {
+            // typing is tracked per user, so another open tab may still be typing
+            if !self.has_session_in(user_id, channel_id) {
+                if let Some(typing) = self.typing.get_mut(&channel_id) {
+                    typing.remove(&user_id);
+                    if typing.is_empty() {
+                        self.typing.remove(&channel_id);
+                    }
+                }
+            }
+
+            let leave_msg = WsMessage::UserLeft { user_id, username };
+            self.send_to_channel(&channel_id, leave_msg, None);
+        }
+    }
+
+    /// Whether the message repeats the user's last one in the channel within the duplicate
+    /// window; otherwise it becomes the one later sends are compared against.
+    fn check_duplicate(
+        &mut self,
+        user_id: Uuid,
+        channel_id: Uuid,
+        content: String,
+        attachments: Vec<Attachment>,
+    ) -> bool {
+        let key = (user_id, channel_id);
+        let is_duplicate = self.recent_messages.get(&key).is_some_and(
+            |(last_content, last_attachments, sent_at)| {
+                *last_content == content
+                    && *last_attachments == attachments
+                    && sent_at.elapsed() < self.duplicate_window
+            },
+        );
+        if !is_duplicate {
+            self.recent_messages
+                .insert(key, (content, attachments, Instant::now()));
+        }
+        is_duplicate
+    }
+
+    fn has_session_in(&self, user_id: Uuid, channel_id: Uuid) -> bool {
+        self.users.get(&user_id).is_some_and(|conns| {
+            conns.iter().any(|id| {
+                self.session_info
+                    .get(id)
+                    .is_some_and(|info| info.channel_id == channel_id)
+            })
+        })
+    }
+
+    /// Enforces the per-user connection limits ahead of a connect.
+    ///
+    /// Under `CloseOldest` the user's oldest sessions are evicted until there's room;
+    /// under `RejectNewest` this returns `false` and the caller turns the connect away.
+    fn make_room(&mut self, user_id: Uuid, channel_id: Uuid) -> bool {
+        loop {
+            let conns = self.users.get(&user_id);
+            let in_channel: Vec<ConnId> = conns
+                .into_iter()
+                .flatten()
+                .copied()
+                .filter(|id| {
+                    self.session_info
+                        .get(id)
+                        .is_some_and(|info| info.channel_id == channel_id)
+                })
+                .collect();
+
+            // connection ids only grow, so the smallest is the oldest
+            let oldest = if in_channel.len() >= self.limits.per_user_channel {
+                in_channel.into_iter().min()
+            } else if conns.map_or(0, HashSet::len) >= self.limits.per_user {
+                conns.into_iter().flatten().copied().min()
+            } else {
+                return true;
+            };
+
+            if self.limits.policy == LimitPolicy::RejectNewest {
+                return false;
+            }
+
+            let Some(oldest) = oldest else {
+                return true;
+            };
+
+            log::info!(
+                "Closing connection {} of {}: limit reached",
+                oldest,
+                user_id
+            );
+            self.send_to_session(oldest, &too_many_connections());
+            self.disconnect(oldest);
+        }
+    }
+
+    /// Redeems a resume token for the channel it was issued on; each token works once.
+    ///
+    /// Only a dropped connection can be resumed, so a leaked token can't open a second
+    /// session next to the live one.
+    fn take_resume_ticket(&mut self, token: &str, channel_id: Uuid) -> Option<WsIdentity> {
+        let ticket = self.resume_tickets.get(token)?;
+        if ticket.channel_id != channel_id || ticket.expires_at.is_none() || ticket.is_expired() {
+            return None;
+        }
+
+        let ticket = self.resume_tickets.remove(token)?;
+        Some(WsIdentity {
+            user_id: ticket.user_id,
+            username: ticket.username,
+            avatar_url: ticket.avatar_url,
+            role: ticket.role,
+        })
+    }
+
+    /// Drops a session from every index, returning its info if it was still registered.
+    fn remove_session(&mut self, conn_id: ConnId) -> Option<SessionInfo> {
+        self.sessions.remove(&conn_id);
+        let info = self.session_info.remove(&conn_id)?;
+
+        // the resume window starts once the socket is gone
+        if let Some(ticket) = self.resume_tickets.get_mut(&info.resume_token) {
+            ticket.expires_at = Some(Instant::now() + RESUME_TTL);
+        }
+
+        if let Some(sessions) = self.channels.get_mut(&info.channel_id) {
+            sessions.remove(&conn_id);
+            if sessions.is_empty() {
+                self.channels.remove(&info.channel_id);
+            }
+        }
+
+        if let Some(conns) = self.users.get_mut(&info.user_id) {
+            conns.remove(&conn_id);
+            if conns.is_empty() {
+                self.users.remove(&info.user_id);
+                self.touch_last_seen(info.user_id);
+                self.announce_presence(
+                    info.user_id,
+                    info.username.clone(),
+                    info.avatar_url.clone(),
+                    false,
+                );
+            }
+        }
+
+        Some(info)
+    }
+
+    /// Looks up who shares a channel with `user_id` off the server loop, then hands the
+    /// list back as a `Presence` command.
+    fn announce_presence(
+        &self,
+        user_id: Uuid,
+        username: String,
+        avatar_url: Option<String>,
+        online: bool,
+    ) {
+        // nobody else is connected, so there is nobody to tell
+        if self.users.keys().all(|id| *id == user_id) {
+            return;
+        }
+
+        let pool = self.db_pool.clone();
+        let cmd_tx = self.cmd_tx.clone();
+        tokio::spawn(async move {
+            let contacts = sqlx::query_scalar::<_, Uuid>(
+                r#"
+                SELECT DISTINCT other.user_id
+                FROM channel_members mine
+                INNER JOIN channel_members other
+                    ON other.channel_id = mine.channel_id AND other.user_id <> mine.user_id
+                WHERE mine.user_id = $1
+                "#,
+            )
+            .bind(user_id)
+            .fetch_all(&pool)
+            .await;
+
+            match contacts {
+                Ok(contacts) => {
+                    if let Some(cmd_tx) = cmd_tx.upgrade() {
+                        let message = WsMessage::PresenceUpdate {
+                            user_id,
+                            username,
+                            avatar_url,
+                            online,
+                        };
+                        let _ = cmd_tx.send(Command::Presence { contacts, message });
+                    }
+                }
+                Err(e) => log::error!("Failed to load contacts of {}: {}", user_id, e),
+            }
+        });
+    }
+
+    /// Sends a presence change to every connected contact.
+    ///
+    /// Lookups can finish out of order, so a change the user has since reverted, such as
+    /// going offline and straight back online, is dropped.
+    fn deliver_presence(&mut self, contacts: &[Uuid], message: &WsMessage) {
+        let WsMessage::PresenceUpdate {
+            user_id, online, ..
+        } = message
+        else {
+            return;
+        };
+        if self.users.contains_key(user_id) != *online {
+            return;
+        }
+
+        let conns: Vec<ConnId> = contacts
+            .iter()
+            .filter_map(|contact| self.users.get(contact))
+            .flatten()
+            .copied()
+            .collect();
+        for conn_id in conns {
+            self.send_to_session(conn_id, message);
+        }
+    }
+
+    fn touch_last_seen(&self, user_id: Uuid) {
+        let pool = self.db_pool.clone();
+        tokio::spawn(async move {
+            let updated = sqlx::query(
+                r#"
+                UPDATE users
+                SET last_seen_at = NOW()
+                WHERE id = $1
+                "#,
+            )
+            .bind(user_id)
+            .execute(&pool)
+            .await;
+
+            if let Err(e) = updated {
+                log::error!("Failed to update last seen for {}: {}", user_id, e);
+            }
+        });
+    }
+
+    /// Notifies every session in the channel, then drops them so their sockets close.
+    fn close_channel(&mut self, channel_id: Uuid) {
+        let notice = WsMessage::ChannelDeleted { channel_id };
+        self.send_to_channel(&channel_id, notice, None);
+
+        self.typing.remove(&channel_id);
+        if let Some(sessions) = self.channels.remove(&channel_id) {
+            for conn_id in sessions {
+                self.remove_session(conn_id);
+            }
+        }
+    }
+
+    /// Broadcasts typing state transitions only, collapsing repeats within the dedup window.
+    ///
+    /// Moving between the channel and a thread counts as a transition, and stopping
+    /// reports the place the user was typing in.
+    fn handle_typing(&mut self, conn_id: ConnId, is_typing: bool, parent_id: Option<Uuid>) {
+        let Some(info) = self.session_info.get(&conn_id) else {
+            return;
+        };
+        let (user_id, username, channel_id) =
+            (info.user_id, info.username.clone(), info.channel_id);
+
+        let typing = self.typing.entry(channel_id).or_default();
+        let changed = if is_typing {
+            match typing.get(&user_id) {
+                Some((since, parent))
+                    if *parent == parent_id && since.elapsed() < TYPING_DEDUP_WINDOW =>
+                {
+                    None
+                }
+                _ => {
+                    typing.insert(user_id, (Instant::now(), parent_id));
+                    Some(parent_id)
                 }
             }
+        } else {
+            typing.remove(&user_id).map(|(_, parent)| parent)
+        };
+
+        if typing.is_empty() {
+            self.typing.remove(&channel_id);
+        }
+
+        if let Some(parent_id) = changed {
+            let typing_msg = WsMessage::TypingIndicator {
+                user_id,
+                username,
+                is_typing,
+                parent_id,
+            };
+            self.send_to_channel(&channel_id, typing_msg, Some(conn_id));
+        }
+    }
+
+    /// Sends one page of who is in the channel, one entry per user.
+    ///
+    /// Pages are sorted by username so `offset` stays meaningful between requests,
+    /// though users coming and going in between can shift entries across pages.
+    fn send_online_snapshot(&mut self, conn_id: ConnId, channel_id: Uuid, offset: usize) {
+        let mut seen = HashSet::new();
+        let mut users: Vec<OnlineUser> = self
+            .channels
+            .get(&channel_id)
+            .into_iter()
+            .flatten()
+            .filter_map(|id| self.session_info.get(id))
+            .filter(|info| seen.insert(info.user_id))
+            .map(|info| OnlineUser {
+                user_id: info.user_id,
+                username: info.username.clone(),
+            })
+            .collect();
+        users.sort_by(|a, b| (&a.username, a.user_id).cmp(&(&b.username, b.user_id)));
+
+        let total = users.len();
+        let users: Vec<OnlineUser> = users
+            .into_iter()
+            .skip(offset)
+            .take(ONLINE_SNAPSHOT_PAGE)
+            .collect();
+        let has_more = offset.saturating_add(users.len()) < total;
+
+        let snapshot = WsMessage::OnlineSnapshot {
+            users,
+            total,
+            offset,
+            has_more,
+        };
+        self.send_to_session(conn_id, &snapshot);
+    }
+
+    /// Replies to a single session with everyone else currently typing in its channel.
+    fn send_typing_users(&mut self, conn_id: ConnId) {
+        let Some(info) = self.session_info.get(&conn_id) else {
+            return;
+        };
+        let (own_id, channel_id) = (info.user_id, info.channel_id);
+
+        if let Some(typing) = self.typing.get_mut(&channel_id) {
+            typing.retain(|_, (since, _)| since.elapsed() < TYPING_TTL);
+            if typing.is_empty() {
+                self.typing.remove(&channel_id);
+            }
+        }
+
+        let users = self
+            .typing
+            .get(&channel_id)
+            .into_iter()
+            .flat_map(|typing| typing.iter())
+            .filter(|(&user_id, _)| user_id != own_id)
+            .filter_map(|(&user_id, &(_, parent_id))| {
+                // any live connection of the user carries their username
+                let username = self
+                    .users
+                    .get(&user_id)?
+                    .iter()
+                    .find_map(|id| self.session_info.get(id))?
+                    .username
+                    .clone();
+                Some(TypingUser {
+                    user_id,
+                    username,
+                    parent_id,
+                })
+            })
+            .collect();
+
+        let message = WsMessage::TypingUsers { channel_id, users };
+        self.send_to_session(conn_id, &message);
+    }
+
+    fn send_to_session(&mut self, conn_id: ConnId, message: &WsMessage) {
+        if let Some(tx) = self.sessions.get(&conn_id) {
+            let msg_text = serde_json::to_string(message).unwrap();
+            if let Err(TrySendError::Full(_)) = tx.try_send(msg_text) {
+                self.slow_sessions.push(conn_id);
+            }
         }
     }
 
-    fn send_to_channel(&self, channel_id: &Uuid, message: WsMessage, skip: Option<ConnId>) {
+    fn send_to_channel(&mut self, channel_id: &Uuid, message: WsMessage, skip: Option<ConnId>) {
         if let Some(sessions) = self.channels.get(channel_id) {
             let msg_text = serde_json::to_string(&message).unwrap();
             for &conn_id in sessions {
@@ -124,7 +1005,9 @@ impl ChatServer {
                     }
                 }
                 if let Some(tx) = self.sessions.get(&conn_id) {
-                    let _ = tx.send(msg_text.clone());
+                    if let Err(TrySendError::Full(_)) = tx.try_send(msg_text.clone()) {
+                        self.slow_sessions.push(conn_id);
+                    }
                 }
             }
         }
@@ -134,23 +1017,41 @@ impl ChatServer {
 #[derive(Clone)]
 pub struct ChatServerHandle {
     cmd_tx: mpsc::UnboundedSender<Command>,
+    /// Message inserts detached from their socket, awaited on shutdown.
+    inserts: TaskTracker,
 }
 
 impl ChatServerHandle {
+    /// Runs a message insert in the background, tracked so shutdown can wait for it.
+    pub fn spawn_insert<F>(&self, insert: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.inserts.spawn(insert);
+    }
+
+    /// Waits up to `timeout` for background inserts, returning how many didn't finish.
+    pub async fn drain_inserts(&self, timeout: Duration) -> usize {
+        self.inserts.drain(timeout).await
+    }
+
     pub fn connect(
         &self,
         conn_id: ConnId,
-        user_id: Uuid,
-        username: String,
+        identity: &WsIdentity,
         channel_id: Uuid,
-        tx: mpsc::UnboundedSender<Msg>,
+        tx: mpsc::Sender<Msg>,
+        role_tx: watch::Sender<Role>,
     ) {
         let _ = self.cmd_tx.send(Command::Connect {
             conn_id,
-            user_id,
-            username,
+            user_id: identity.user_id,
+            username: identity.username.clone(),
+            avatar_url: identity.avatar_url.clone(),
+            role: identity.role,
             channel_id,
             tx,
+            role_tx,
         });
     }
 
@@ -158,6 +1059,17 @@ impl ChatServerHandle {
         let _ = self.cmd_tx.send(Command::Disconnect { conn_id });
     }
 
+    /// Redeems a resume token, returning the identity it was issued to.
+    pub async fn resume(&self, token: &str, channel_id: Uuid) -> Option<WsIdentity> {
+        let (respond_to, response) = oneshot::channel();
+        let _ = self.cmd_tx.send(Command::Resume {
+            token: token.to_string(),
+            channel_id,
+            respond_to,
+        });
+        response.await.ok().flatten()
+    }
+
     pub fn send_message(&self, conn_id: ConnId, channel_id: Uuid, message: WsMessage) {
         println!("{:?}", message);
         let _ = self.cmd_tx.send(Command::Message {
@@ -166,6 +1078,658 @@ impl ChatServerHandle {
             message,
         });
     }
+
+    pub fn typing(&self, conn_id: ConnId, is_typing: bool, parent_id: Option<Uuid>) {
+        let _ = self.cmd_tx.send(Command::Typing {
+            conn_id,
+            is_typing,
+            parent_id,
+        });
+    }
+
+    pub fn rename_user(&self, user_id: Uuid, username: String) {
+        let _ = self.cmd_tx.send(Command::RenameUser { user_id, username });
+    }
+
+    /// Applies a member's new role to their live sessions in the channel and tells everyone.
+    pub fn change_role(&self, channel_id: Uuid, user_id: Uuid, role: Role) {
+        let _ = self.cmd_tx.send(Command::ChangeRole {
+            channel_id,
+            user_id,
+            role,
+        });
+    }
+
+    /// Drops every live session of the user; their sockets close once the queue drains.
+    pub fn disconnect_user(&self, user_id: Uuid) {
+        let _ = self.cmd_tx.send(Command::DisconnectUser { user_id });
+    }
+
+    /// Delivers a message to all of the user's sockets, whichever channels they're on.
+    pub fn send_to_user(&self, user_id: Uuid, message: WsMessage) {
+        let _ = self.cmd_tx.send(Command::SendToUser { user_id, message });
+    }
+
+    pub fn get_typing(&self, conn_id: ConnId) {
+        let _ = self.cmd_tx.send(Command::GetTyping { conn_id });
+    }
+
+    pub fn get_online(&self, conn_id: ConnId, offset: usize) {
+        let _ = self.cmd_tx.send(Command::GetOnline { conn_id, offset });
+    }
+
+    pub fn close_channel(&self, channel_id: Uuid) {
+        let _ = self.cmd_tx.send(Command::CloseChannel { channel_id });
+    }
+
+    pub async fn is_online(&self, user_id: Uuid) -> bool {
+        let (respond_to, response) = oneshot::channel();
+        let _ = self.cmd_tx.send(Command::IsOnline {
+            user_id,
+            respond_to,
+        });
+        response.await.unwrap_or(false)
+    }
+
+    /// Whether the user just sent the same message to the channel, from any socket.
+    pub async fn is_duplicate(
+        &self,
+        user_id: Uuid,
+        channel_id: Uuid,
+        content: &str,
+        attachments: &[Attachment],
+    ) -> bool {
+        let (respond_to, response) = oneshot::channel();
+        let _ = self.cmd_tx.send(Command::CheckDuplicate {
+            user_id,
+            channel_id,
+            content: content.to_string(),
+            attachments: attachments.to_vec(),
+            respond_to,
+        });
+        response.await.unwrap_or(false)
+    }
+
+    pub async fn metrics(&self) -> Option<ServerMetrics> {
+        let (respond_to, response) = oneshot::channel();
+        let _ = self.cmd_tx.send(Command::Metrics { respond_to });
+        response.await.ok()
+    }
+
+    pub fn broadcast(&self, channel_id: Uuid, message: WsMessage) {
+        let _ = self.cmd_tx.send(Command::Broadcast {
+            channel_id,
+            message,
+        });
+    }
+}
+
+async fn insert_message(
+    pool: &PgPool,
+    channel_id: Uuid,
+    user_id: Uuid,
+    content: &str,
+    attachments: &[Attachment],
+    client_sent_at: Option<DateTime<Utc>>,
+    format: MessageFormat,
+) -> Result<DbMessage, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let msg = sqlx::query_as::<_, DbMessage>(
+        r#"
+        INSERT INTO messages (channel_id, user_id, content, client_sent_at, format)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id, channel_id, user_id, content, created_at, client_sent_at, edited_at,
+            version, kind, format
+        "#,
+    )
+    .bind(channel_id)
+    .bind(user_id)
+    .bind(content)
+    .bind(client_sent_at)
+    .bind(format)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    for attachment in attachments {
+        sqlx::query(
+            r#"
+            INSERT INTO attachments (message_id, url, mime_type, size)
+            VALUES ($1, $2, $3, $4)
+            "#,
+        )
+        .bind(msg.id)
+        .bind(&attachment.url)
+        .bind(&attachment.mime_type)
+        .bind(attachment.size)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(msg)
+}
+
+/// Errors worth another attempt: lost connections, pool exhaustion, deadlocks and
+/// serialization failures. Constraint violations and the like will fail the same way again.
+fn is_transient(e: &sqlx::Error) -> bool {
+    match e {
+        sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut => true,
+        sqlx::Error::Database(db_err) => db_err.code().is_some_and(|code| {
+            // serialization_failure, deadlock_detected and the connection_exception class
+            code == "40001" || code == "40P01" || code.starts_with("08")
+        }),
+        _ => false,
+    }
+}
+
+/// Inserts a chat message, retrying transient errors with exponential backoff.
+///
+/// A message that still can't be stored is recorded in `failed_messages`, or logged in
+/// full when even that fails, so it is never dropped silently.
+pub async fn insert_message_with_retry(
+    pool: &PgPool,
+    channel_id: Uuid,
+    user_id: Uuid,
+    content: &str,
+    attachments: &[Attachment],
+    client_sent_at: Option<DateTime<Utc>>,
+    format: MessageFormat,
+) -> Result<DbMessage, sqlx::Error> {
+    let mut backoff = INSERT_RETRY_BACKOFF;
+    let mut attempt = 1;
+
+    let error = loop {
+        match insert_message(
+            pool,
+            channel_id,
+            user_id,
+            content,
+            attachments,
+            client_sent_at,
+            format,
+        )
+        .await
+        {
+            Ok(msg) => return Ok(msg),
+            Err(e) if attempt < MAX_INSERT_ATTEMPTS && is_transient(&e) => {
+                log::warn!(
+                    "Message insert attempt {} failed, retrying in {:?}: {}",
+                    attempt,
+                    backoff,
+                    e
+                );
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+                attempt += 1;
+            }
+            Err(e) => break e,
+        }
+    };
+
+    let attachments_json = serde_json::to_string(attachments).unwrap_or_else(|_| "[]".into());
+    let recorded = sqlx::query(
+        r#"
+        INSERT INTO failed_messages
+            (channel_id, user_id, content, attachments, client_sent_at, format, error, attempts)
+        VALUES ($1, $2, $3, $4::jsonb, $5, $6, $7, $8)
+        "#,
+    )
+    .bind(channel_id)
+    .bind(user_id)
+    .bind(content)
+    .bind(&attachments_json)
+    .bind(client_sent_at)
+    .bind(format)
+    .bind(error.to_string())
+    .bind(attempt as i32)
+    .execute(pool)
+    .await;
+
+    if let Err(record_error) = recorded {
+        log::error!(
+            "Lost message from {} in {} after {} attempts ({}), and recording it failed ({}): \
+             content={:?} attachments={} client_sent_at={:?} format={}",
+            user_id,
+            channel_id,
+            attempt,
+            error,
+            record_error,
+            content,
+            attachments_json,
+            client_sent_at,
+            format.as_str()
+        );
+    }
+
+    Err(error)
+}
+
+/// Stores a server-generated event in the channel's history and relays it like chat.
+///
+/// A no-op unless `PERSIST_SYSTEM_MESSAGES` is on; failures are logged, never surfaced,
+/// since the event itself already happened.
+pub async fn post_system_message(
+    pool: &PgPool,
+    server: &ChatServerHandle,
+    channel_id: Uuid,
+    actor_id: Uuid,
+    actor_username: &str,
+    content: String,
+) {
+    if !persist_system_messages() {
+        return;
+    }
+
+    // channel names are free text and end up in the content
+    let content = sanitize_content(&content);
+
+    let inserted = sqlx::query_as::<_, DbMessage>(
+        r#"
+        INSERT INTO messages (channel_id, user_id, content, kind)
+        VALUES ($1, $2, $3, $4)
+        RETURNING id, channel_id, user_id, content, created_at, client_sent_at, edited_at,
+            version, kind, format
+        "#,
+    )
+    .bind(channel_id)
+    .bind(actor_id)
+    .bind(content)
+    .bind(MessageKind::System)
+    .fetch_one(pool)
+    .await;
+
+    match inserted {
+        Ok(msg) => server.broadcast(
+            channel_id,
+            WsMessage::ChatMessage {
+                id: msg.id,
+                user_id: msg.user_id,
+                username: actor_username.to_string(),
+                content: msg.content,
+                created_at: msg.created_at,
+                client_sent_at: None,
+                attachments: Vec::new(),
+                kind: msg.kind,
+                format: msg.format,
+            },
+        ),
+        Err(e) => log::error!("Failed to store system message: {}", e),
+    }
+}
+
+pub async fn is_archived(pool: &PgPool, channel_id: Uuid) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar::<_, bool>(
+        r#"
+        SELECT EXISTS(
+            SELECT 1 FROM channels
+            WHERE id = $1 AND archived_at IS NOT NULL
+        )
+        "#,
+    )
+    .bind(channel_id)
+    .fetch_one(pool)
+    .await
+}
+
+/// The channel's slow mode interval and when `user_id` last posted there, or `None`
+/// when slow mode is off.
+async fn slow_mode_state(
+    pool: &PgPool,
+    channel_id: Uuid,
+    user_id: Uuid,
+) -> Result<Option<(i32, Option<DateTime<Utc>>)>, sqlx::Error> {
+    sqlx::query_as::<_, (i32, Option<DateTime<Utc>>)>(
+        r#"
+        SELECT c.slow_mode_seconds, (
+            SELECT MAX(m.created_at) FROM messages m
+            WHERE m.channel_id = c.id AND m.user_id = $2 AND m.kind = 'user'
+        )
+        FROM channels c
+        WHERE c.id = $1 AND c.slow_mode_seconds IS NOT NULL
+        "#,
+    )
+    .bind(channel_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await
+}
+
+/// How long the user still has to wait, given when their last message was stored and
+/// how long ago this connection sent one, whichever is more recent.
+fn slow_mode_remaining(
+    interval_secs: i32,
+    last_stored_at: Option<DateTime<Utc>>,
+    last_sent: Option<Duration>,
+) -> Option<Duration> {
+    let interval = Duration::from_secs(u64::try_from(interval_secs).unwrap_or_default());
+    // a timestamp from the future (clock skew) counts as just now
+    let since_stored = last_stored_at.map(|at| (Utc::now() - at).to_std().unwrap_or_default());
+
+    [since_stored, last_sent]
+        .into_iter()
+        .flatten()
+        .min()
+        .and_then(|elapsed| interval.checked_sub(elapsed))
+        .filter(|remaining| !remaining.is_zero())
+}
+
+/// How long `user_id` must wait before posting in the channel again, `None` when they
+/// can post now or slow mode is off.
+///
+/// `last_sent` is how long ago the caller's own connection sent a message, which may not
+/// have been stored yet.
+pub async fn slow_mode_wait(
+    pool: &PgPool,
+    channel_id: Uuid,
+    user_id: Uuid,
+    last_sent: Option<Duration>,
+) -> Result<Option<Duration>, sqlx::Error> {
+    let state = slow_mode_state(pool, channel_id, user_id).await?;
+    Ok(state.and_then(|(interval, last_stored_at)| {
+        slow_mode_remaining(interval, last_stored_at, last_sent)
+    }))
+}
+
+/// Loads up to `limit` messages older than `before` (or the newest ones), oldest first.
+async fn fetch_history(
+    pool: &PgPool,
+    channel_id: Uuid,
+    before: Option<Uuid>,
+    limit: i64,
+) -> Result<WsMessage, sqlx::Error> {
+    let mut messages = sqlx::query_as::<_, MessageResponse>(
+        r#"
+        SELECT m.id, m.channel_id, m.user_id, u.username, m.content, m.created_at, m.client_sent_at,
+            m.edited_at, m.version, m.kind, m.format,
+            COALESCE(
+                (
+                    SELECT json_agg(
+                        json_build_object('url', a.url, 'mime_type', a.mime_type, 'size', a.size)
+                        ORDER BY a.created_at
+                    )
+                    FROM attachments a
+                    WHERE a.message_id = m.id
+                ),
+                '[]'::json
+            ) AS attachments
+        FROM messages m
+        INNER JOIN users u ON m.user_id = u.id
+        WHERE m.channel_id = $1 AND m.deleted_at IS NULL
+            AND ($2::uuid IS NULL OR (m.created_at, m.id) < (
+                SELECT created_at, id FROM messages WHERE id = $2 AND channel_id = $1
+            ))
+        ORDER BY m.created_at DESC, m.id DESC
+        LIMIT $3
+        "#,
+    )
+    .bind(channel_id)
+    .bind(before)
+    .bind(limit + 1)
+    .fetch_all(pool)
+    .await?;
+
+    let has_more = messages.len() as i64 > limit;
+    messages.truncate(limit as usize);
+    messages.reverse();
+
+    Ok(WsMessage::History { messages, has_more })
+}
+
+/// Loads up to `limit` messages newer than `after`, oldest first, for a resumed connection.
+///
+/// `has_more` means the gap was larger than `limit`; the rest is on the REST history.
+async fn fetch_missed(
+    pool: &PgPool,
+    channel_id: Uuid,
+    after: Uuid,
+    limit: i64,
+) -> Result<WsMessage, sqlx::Error> {
+    let mut messages = sqlx::query_as::<_, MessageResponse>(
+        r#"
+        SELECT m.id, m.channel_id, m.user_id, u.username, m.content, m.created_at, m.client_sent_at,
+            m.edited_at, m.version, m.kind, m.format,
+            COALESCE(
+                (
+                    SELECT json_agg(
+                        json_build_object('url', a.url, 'mime_type', a.mime_type, 'size', a.size)
+                        ORDER BY a.created_at
+                    )
+                    FROM attachments a
+                    WHERE a.message_id = m.id
+                ),
+                '[]'::json
+            ) AS attachments
+        FROM messages m
+        INNER JOIN users u ON m.user_id = u.id
+        WHERE m.channel_id = $1 AND m.deleted_at IS NULL
+            AND (m.created_at, m.id) > (
+                SELECT created_at, id FROM messages WHERE id = $2 AND channel_id = $1
+            )
+        ORDER BY m.created_at, m.id
+        LIMIT $3
+        "#,
+    )
+    .bind(channel_id)
+    .bind(after)
+    .bind(limit + 1)
+    .fetch_all(pool)
+    .await?;
+
+    let has_more = messages.len() as i64 > limit;
+    messages.truncate(limit as usize);
+
+    Ok(WsMessage::History { messages, has_more })
+}
+
+async fn send_history(
+    session: &mut actix_ws::Session,
+    history: Result<WsMessage, sqlx::Error>,
+) -> Result<(), actix_ws::Closed> {
+    match history {
+        Ok(history) => {
+            session
+                .text(serde_json::to_string(&history).unwrap_or_default())
+                .await
+        }
+        Err(e) => {
+            log::error!("Failed to load history: {}", e);
+            send_error(session, WsErrorCode::Internal, "Failed to load history").await
+        }
+    }
+}
+
+/// Whether `message_id` is a live message in the channel, i.e. a thread a reply can go in.
+async fn is_channel_message(
+    pool: &PgPool,
+    channel_id: Uuid,
+    message_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar::<_, bool>(
+        r#"
+        SELECT EXISTS(
+            SELECT 1 FROM messages
+            WHERE id = $1 AND channel_id = $2 AND deleted_at IS NULL
+        )
+        "#,
+    )
+    .bind(message_id)
+    .bind(channel_id)
+    .fetch_one(pool)
+    .await
+}
+
+/// Moves the user's read position forward to the given message.
+///
+/// Returns `false` when the message isn't in the channel or is older than the current position.
+async fn mark_read(
+    pool: &PgPool,
+    channel_id: Uuid,
+    user_id: Uuid,
+    up_to_message_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let advanced = sqlx::query_scalar::<_, Uuid>(
+        r#"
+        INSERT INTO channel_reads (channel_id, user_id, last_read_message_id, last_read_message_at)
+        SELECT m.channel_id, $2, m.id, m.created_at
+        FROM messages m
+        WHERE m.id = $3 AND m.channel_id = $1
+        ON CONFLICT (channel_id, user_id) DO UPDATE
+        SET last_read_message_id = EXCLUDED.last_read_message_id,
+            last_read_message_at = EXCLUDED.last_read_message_at,
+            updated_at = NOW()
+        WHERE channel_reads.last_read_message_at < EXCLUDED.last_read_message_at
+        RETURNING last_read_message_id
+        "#,
+    )
+    .bind(channel_id)
+    .bind(user_id)
+    .bind(up_to_message_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(advanced.is_some())
+}
+
+async fn send_error(
+    session: &mut actix_ws::Session,
+    code: WsErrorCode,
+    message: &str,
+) -> Result<(), actix_ws::Closed> {
+    let error = WsMessage::Error {
+        code,
+        message: message.to_string(),
+        retry_after: None,
+    };
+    session
+        .text(serde_json::to_string(&error).unwrap_or_default())
+        .await
+}
+
+/// Identity of a socket once its token has been verified.
+#[derive(Debug)]
+pub struct WsIdentity {
+    user_id: Uuid,
+    username: String,
+    avatar_url: Option<String>,
+    role: Role,
+}
+
+async fn authenticate(
+    pool: &PgPool,
+    token: &str,
+    channel_id: Uuid,
+) -> Result<WsIdentity, actix_web::Error> {
+    let claims = crate::utils::jwt::decode_jwt(token)
+        .map_err(|e| actix_web::error::ErrorUnauthorized(e.to_string()))?;
+
+    let user_id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Invalid user ID"))?;
+
+    let role = member_role(pool, channel_id, user_id)
+        .await
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?
+        .ok_or_else(|| actix_web::error::ErrorForbidden("Not a member of this channel"))?;
+
+    // the username comes from the row, a token issued before a rename still carries the old one
+    let (username, avatar_url, token_version) = sqlx::query_as::<_, (String, Option<String>, i32)>(
+        r#"
+        SELECT username, avatar_url, token_version FROM users
+        WHERE id = $1 AND deleted_at IS NULL
+        "#,
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?
+    .ok_or_else(|| actix_web::error::ErrorUnauthorized("Invalid token"))?;
+
+    if token_version != claims.ver {
+        return Err(actix_web::error::ErrorUnauthorized("Token revoked"));
+    }
+
+    Ok(WsIdentity {
+        user_id,
+        username,
+        avatar_url,
+        role,
+    })
+}
+
+/// Reads a token offered as `Sec-WebSocket-Protocol: bearer, <token>`.
+fn subprotocol_token(req: &HttpRequest) -> Option<String> {
+    let header = req.headers().get(SEC_WEBSOCKET_PROTOCOL)?.to_str().ok()?;
+    let mut protocols = header.split(',').map(str::trim);
+
+    protocols.find(|protocol| *protocol == AUTH_SUBPROTOCOL)?;
+    protocols
+        .next()
+        .filter(|token| !token.is_empty())
+        .map(str::to_string)
+}
+
+/// Waits for a `ClientMessage::Auth` or `ClientMessage::Resume` frame on a socket that
+/// connected without a token.
+///
+/// A failed resume is answered with `resume_expired` and the client may still send `auth`
+/// before the timeout. A successful resume also returns the message id to replay from.
+async fn wait_for_auth(
+    session: &mut actix_ws::Session,
+    msg_stream: &mut actix_ws::MessageStream,
+    server: &ChatServerHandle,
+    pool: &PgPool,
+    channel_id: Uuid,
+) -> Result<(WsIdentity, Option<Uuid>), &'static str> {
+    tokio::time::timeout(AUTH_TIMEOUT, async {
+        while let Some(Ok(msg)) = msg_stream.next().await {
+            match msg {
+                WsFrameMessage::Text(text) => match serde_json::from_str::<ClientMessage>(&text) {
+                    Ok(ClientMessage::Auth { token }) => {
+                        return authenticate(pool, &token, channel_id)
+                            .await
+                            .map(|identity| (identity, None))
+                            .map_err(|_| "Authentication failed");
+                    }
+                    Ok(ClientMessage::Resume {
+                        token,
+                        last_message_id,
+                    }) => {
+                        if let Some(identity) = server.resume(&token, channel_id).await {
+                            return Ok((identity, last_message_id));
+                        }
+
+                        let sent = send_error(
+                            session,
+                            WsErrorCode::ResumeExpired,
+                            "Resume token is invalid or expired, send an auth message",
+                        )
+                        .await;
+                        if sent.is_err() {
+                            return Err("Expected auth message");
+                        }
+                    }
+                    _ => return Err("Expected auth message"),
+                },
+                WsFrameMessage::Ping(bytes) => {
+                    let _ = session.pong(&bytes).await;
+                }
+                WsFrameMessage::Close(_) => return Err("Expected auth message"),
+                _ => {}
+            }
+        }
+        Err("Expected auth message")
+    })
+    .await
+    .map_err(|_| "Authentication timed out")?
+}
+
+/// What a socket is sent right after it connects.
+enum Replay {
+    /// The newest messages, up to the limit; 0 sends nothing.
+    Latest(i64),
+    /// Everything after the last message a resumed connection saw.
+    Missed(Uuid),
 }
 
 pub async fn websocket_handler(
@@ -179,47 +1743,63 @@ pub async fn websocket_handler(
     // /ws/{channel_id}
     let channel_id = path.into_inner();
 
-    // ?token=<token>
-    let token = query
-        .get("token")
-        .ok_or_else(|| actix_web::error::ErrorUnauthorized("No token provided"))?;
-
-    let secret = env::var("JWT_SECRET").unwrap_or_else(|_| "secret".to_string());
-    let claims = crate::utils::jwt::decode_jwt(token, secret)
-        .map_err(|_| actix_web::error::ErrorUnauthorized("Invalid token"))?;
-
-    let user_id = Uuid::parse_str(&claims.sub)
-        .map_err(|_| actix_web::error::ErrorInternalServerError("Invalid user ID"))?;
-
-    let is_member = sqlx::query_scalar::<_, bool>(
-        r#"
-        SELECT EXISTS(
-            SELECT 1 FROM channel_members
-            WHERE channel_id = $1 AND user_id = $2
-        )"#,
-    )
-    .bind(channel_id)
-    .bind(user_id)
-    .fetch_one(pool.get_ref())
-    .await
-    .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+    // ?token=<token> is kept for older clients; prefer the subprotocol or an auth frame
+    let header_token = subprotocol_token(&req);
+    let token = query.get("token").cloned().or_else(|| header_token.clone());
+    let history = history_limit(query.get("history").and_then(|value| value.parse().ok()));
 
-    if !is_member {
-        return Err(actix_web::error::ErrorForbidden(
-            "Not a member for this channel",
-        ));
+    let identity = match &token {
+        Some(token) => Some(authenticate(pool.get_ref(), token, channel_id).await?),
+        None => None,
     };
 
-    let (response, session, msg_stream) = actix_ws::handle(&req, stream)?;
+    let (mut response, mut session, msg_stream) = actix_ws::handle(&req, stream)?;
+    let mut msg_stream = msg_stream.max_frame_size(max_frame_size());
+
+    // browsers drop the connection unless the offered subprotocol is echoed back
+    if header_token.is_some() {
+        response.headers_mut().insert(
+            SEC_WEBSOCKET_PROTOCOL,
+            HeaderValue::from_static(AUTH_SUBPROTOCOL),
+        );
+    }
 
-    let conn_id = next_conn_id();
-    let username = claims.username;
     let server = server.get_ref().clone();
     let db_pool = pool.get_ref().clone();
 
-    tokio::task::spawn_local(chat_ws_handler(
-        session, msg_stream, server, conn_id, user_id, username, channel_id, db_pool,
-    ));
+    tokio::task::spawn_local(async move {
+        let (identity, resume_after) = match identity {
+            Some(identity) => (identity, None),
+            None => {
+                let auth =
+                    wait_for_auth(&mut session, &mut msg_stream, &server, &db_pool, channel_id)
+                        .await;
+                match auth {
+                    Ok(auth) => auth,
+                    Err(reason) => {
+                        let _ = session
+                            .close(Some(CloseReason {
+                                code: CloseCode::Policy,
+                                description: Some(reason.to_string()),
+                            }))
+                            .await;
+                        return;
+                    }
+                }
+            }
+        };
+
+        // a resume without a last message id gets the usual replay
+        let replay = match resume_after {
+            Some(after) => Replay::Missed(after),
+            None => Replay::Latest(history),
+        };
+
+        chat_ws_handler(
+            session, msg_stream, server, identity, channel_id, replay, db_pool,
+        )
+        .await;
+    });
 
     Ok(response)
 }
@@ -228,74 +1808,325 @@ async fn chat_ws_handler(
     mut session: actix_ws::Session,
     mut msg_stream: actix_ws::MessageStream,
     server: ChatServerHandle,
-    conn_id: ConnId,
-    user_id: Uuid,
-    username: String,
+    identity: WsIdentity,
     channel_id: Uuid,
+    replay: Replay,
     db_pool: PgPool,
 ) {
-    let (tx, mut rx) = mpsc::unbounded_channel();
+    let conn_id = next_conn_id();
+    let (tx, mut rx) = mpsc::channel(send_buffer_capacity());
+    let (role_tx, role_rx) = watch::channel(identity.role);
+
+    server.connect(conn_id, &identity, channel_id, tx, role_tx);
+
+    let WsIdentity {
+        user_id, username, ..
+    } = identity;
 
-    server.connect(conn_id, user_id, username.clone(), channel_id, tx);
+    // replay after connecting so nothing sent in between is missed; clients dedupe by id
+    let history = match replay {
+        Replay::Missed(after) => {
+            Some(fetch_missed(&db_pool, channel_id, after, MAX_HISTORY_LIMIT).await)
+        }
+        Replay::Latest(limit) if limit > 0 => {
+            Some(fetch_history(&db_pool, channel_id, None, limit).await)
+        }
+        Replay::Latest(_) => None,
+    };
+    if let Some(history) = history {
+        if send_history(&mut session, history).await.is_err() {
+            server.disconnect(conn_id);
+            return;
+        }
+    }
 
     let mut last_heartbeat = Instant::now();
     let mut interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+    let mut read_interval = tokio::time::interval(READ_RECEIPT_INTERVAL);
+    let mut pending_read: Option<Uuid> = None;
+    let mut last_sent: Option<Instant> = None;
+    // the last thread `typing` was validated against, so repeats skip the lookup
+    let mut known_thread: Option<Uuid> = None;
+    let mut close_reason: Option<CloseReason> = None;
 
     loop {
         tokio::select! {
-            Some(msg) = rx.recv() => {
+            msg = rx.recv() => {
+                // the server dropped this session, e.g. because the channel was deleted
+                let Some(msg) = msg else {
+                    break;
+                };
+
                 if session.text(msg).await.is_err() {
                     break;
                 }
             }
-            Some(Ok(msg)) = msg_stream.next() => {
+            Some(frame) = msg_stream.next() => {
+                let msg = match frame {
+                    Ok(msg) => msg,
+                    // an oversized frame is refused by the codec before it is buffered
+                    Err(ProtocolError::Overflow) => {
+                        log::warn!("Closing connection {}: frame over the size limit", conn_id);
+                        close_reason = Some(CloseReason {
+                            code: CloseCode::Size,
+                            description: Some("Frame too large".to_string()),
+                        });
+                        break;
+                    }
+                    Err(e) => {
+                        log::debug!("Closing connection {}: {}", conn_id, e);
+                        close_reason = Some(CloseCode::Protocol.into());
+                        break;
+                    }
+                };
+
                 match msg {
                     WsFrameMessage::Text(text) => {
                         last_heartbeat = Instant::now();
 
-                        if let Ok(client_msg) = serde_json::from_str::<ClientMessage>(&text) {
-                            match client_msg {
-                                ClientMessage::SendMessage { content } => {
-                                    let channel_id_clone = channel_id;
-                                    let user_id_clone = user_id;
-                                    let username_clone = username.clone();
-                                    let db_pool_clone = db_pool.clone();
-                                    let server_clone = server.clone();
-
-                                    tokio::spawn(async move {
-                                        if let Ok(msg) = sqlx::query_as::<_, DbMessage>(r#"
-                                        INSERT INTO messages (channel_id, user_id, content)
-                                        VALUES ($1, $2, $3)
-                                        RETURNING id, channel_id, user_id, content, created_at
-                                            "#,)
-                                            .bind(channel_id_clone)
-                                            .bind(user_id_clone)
-                                            .bind(&content)
-                                            .fetch_one(&db_pool_clone)
-                                            .await {
-                                                println!("{:?}", msg);
-                                                let ws_msg = WsMessage::ChatMessage {
-                                                    id: msg.id,
-                                                    user_id: user_id_clone,
-                                                    username: username_clone,
-                                                    content: msg.content,
-                                                    created_at: msg.created_at
-                                                };
-
-                                                server_clone.send_message(conn_id, channel_id, ws_msg);
+                        let client_msg = match serde_json::from_str::<ClientMessage>(&text) {
+                            Ok(client_msg) => client_msg,
+                            Err(e) => {
+                                log::debug!("Unparseable frame from {}: {}", user_id, e);
+                                // serde names the unknown type or missing field, e.g. `unknown variant`
+                                let sent = send_error(
+                                    &mut session,
+                                    WsErrorCode::BadPayload,
+                                    &format!("Unrecognized message: {}", e),
+                                )
+                                .await;
+                                if sent.is_err() {
+                                    break;
+                                }
+                                continue;
+                            }
+                        };
+
+                        match client_msg {
+                            ClientMessage::SendMessage {
+                                content,
+                                attachments,
+                                client_sent_at,
+                                format,
+                            } => {
+                                if !role_rx.borrow().can_post() {
+                                    let sent = send_error(
+                                        &mut session,
+                                        WsErrorCode::Forbidden,
+                                        "You don't have permission to post in this channel",
+                                    )
+                                    .await;
+                                    if sent.is_err() {
+                                        break;
+                                    }
+                                    continue;
+                                }
+
+                                if let Err(e) = validate_message_content(&content, !attachments.is_empty()) {
+                                    let code = match e {
+                                        ContentError::Empty => WsErrorCode::BadPayload,
+                                        ContentError::TooLong => WsErrorCode::TooLong,
+                                    };
+                                    let sent = send_error(&mut session, code, &e.to_string()).await;
+                                    if sent.is_err() {
+                                        break;
+                                    }
+                                    continue;
+                                }
+
+                                let rejection = match is_archived(&db_pool, channel_id).await {
+                                    Ok(false) => None,
+                                    Ok(true) => Some((
+                                        WsErrorCode::ChannelArchived,
+                                        "This channel is archived",
+                                    )),
+                                    // fail closed, an archived channel must never take messages
+                                    Err(e) => {
+                                        log::error!("Failed to check channel state: {}", e);
+                                        Some((
+                                            WsErrorCode::Internal,
+                                            "Failed to send message, try again",
+                                        ))
+                                    }
+                                };
+                                if let Some((code, message)) = rejection {
+                                    let sent = send_error(&mut session, code, message).await;
+                                    if sent.is_err() {
+                                        break;
+                                    }
+                                    continue;
+                                }
+
+                                if !role_rx.borrow().is_admin() {
+                                    // the insert runs in the background, so this connection's
+                                    // own last send covers a message that isn't stored yet
+                                    let last_sent_ago = last_sent.map(|sent_at| sent_at.elapsed());
+                                    let remaining =
+                                        slow_mode_wait(&db_pool, channel_id, user_id, last_sent_ago)
+                                            .await
+                                            .unwrap_or_else(|e| {
+                                                log::error!("Failed to check slow mode: {}", e);
+                                                None
+                                            });
+                                    if let Some(remaining) = remaining {
+                                        let error = slow_mode_error(remaining);
+                                        let sent = session
+                                            .text(serde_json::to_string(&error).unwrap_or_default())
+                                            .await;
+                                        if sent.is_err() {
+                                            break;
+                                        }
+                                        continue;
+                                    }
+                                }
+
+                                if let Err(reason) = validate_attachments(&attachments) {
+                                    log::warn!("Rejected message from {}: {}", user_id, reason);
+                                    let sent =
+                                        send_error(&mut session, WsErrorCode::BadPayload, reason).await;
+                                    if sent.is_err() {
+                                        break;
+                                    }
+                                    continue;
+                                }
+
+                                let content = match word_filter().apply(&content) {
+                                    Ok(content) => sanitize_content(&content),
+                                    Err(_) => {
+                                        let sent = send_error(
+                                            &mut session,
+                                            WsErrorCode::BlockedContent,
+                                            "Message contains blocked words",
+                                        )
+                                        .await;
+                                        if sent.is_err() {
+                                            break;
+                                        }
+                                        continue;
+                                    }
+                                };
+
+                                // a double submit is dropped quietly, the first copy is already on its way
+                                if server.is_duplicate(user_id, channel_id, &content, &attachments).await {
+                                    log::debug!("Dropped duplicate message from {}", user_id);
+                                    continue;
+                                }
+                                last_sent = Some(Instant::now());
+
+                                let channel_id_clone = channel_id;
+                                let user_id_clone = user_id;
+                                let username_clone = username.clone();
+                                let db_pool_clone = db_pool.clone();
+                                let server_clone = server.clone();
+
+                                server.spawn_insert(async move {
+                                    let inserted = insert_message_with_retry(
+                                        &db_pool_clone,
+                                        channel_id_clone,
+                                        user_id_clone,
+                                        &content,
+                                        &attachments,
+                                        client_sent_at,
+                                        format,
+                                    )
+                                    .await;
+
+                                    match inserted {
+                                        Ok(msg) => {
+                                            let ws_msg = WsMessage::ChatMessage {
+                                                id: msg.id,
+                                                user_id: user_id_clone,
+                                                username: username_clone,
+                                                content: msg.content,
+                                                created_at: msg.created_at,
+                                                client_sent_at: msg.client_sent_at,
+                                                attachments,
+                                                kind: msg.kind,
+                                                format: msg.format,
+                                            };
+
+                                            server_clone.send_message(conn_id, channel_id, ws_msg);
                                         }
+                                        Err(e) => log::error!("Failed to insert message: {}", e),
+                                    }
+                                });
+                            }
+                            ClientMessage::Typing {
+                                is_typing,
+                                parent_id,
+                            } => {
+                                // only relay a thread that exists here; stopping needs no check,
+                                // the server reports the place it recorded
+                                let unknown_thread = match parent_id {
+                                    Some(parent_id) if is_typing && known_thread != Some(parent_id) => {
+                                        match is_channel_message(&db_pool, channel_id, parent_id).await {
+                                            Ok(true) => {
+                                                known_thread = Some(parent_id);
+                                                false
+                                            }
+                                            Ok(false) => true,
+                                            Err(e) => {
+                                                log::error!("Failed to check typing thread: {}", e);
+                                                true
+                                            }
+                                        }
+                                    }
+                                    _ => false,
+                                };
+                                if unknown_thread {
+                                    let sent = send_error(
+                                        &mut session,
+                                        WsErrorCode::BadPayload,
+                                        "parent_id is not a message in this channel",
+                                    )
+                                    .await;
+                                    if sent.is_err() {
+                                        break;
+                                    }
+                                    continue;
+                                }
+                                server.typing(conn_id, is_typing, parent_id);
+                            }
+                            ClientMessage::MarkRead { up_to_message_id } => {
+                                pending_read = Some(up_to_message_id);
+                            }
+                            ClientMessage::GetTyping => {
+                                server.get_typing(conn_id);
+                            }
+                            ClientMessage::LoadOnline { offset } => {
+                                server.get_online(conn_id, offset);
+                            }
+                            ClientMessage::LoadMore { before, limit } => {
+                                // the socket outlives a membership, e.g. after leaving the channel
+                                let is_member = member_role(&db_pool, channel_id, user_id)
+                                    .await
+                                    .map(|role| role.is_some())
+                                    .unwrap_or_else(|e| {
+                                        log::error!("Failed to check membership: {}", e);
+                                        false
                                     });
+                                if !is_member {
+                                    let sent = send_error(
+                                        &mut session,
+                                        WsErrorCode::NotMember,
+                                        "You are no longer a member of this channel",
+                                    )
+                                    .await;
+                                    if sent.is_err() {
+                                        break;
+                                    }
+                                    continue;
                                 }
-                                ClientMessage::Typing { is_typing } => {
-                                    let typing_msg = WsMessage::TypingIndicator {
-                                        user_id,
-                                        username: username.clone(),
-                                        is_typing,
-                                    };
 
-                                   server.send_message(conn_id, channel_id, typing_msg);
+                                let limit = history_limit(limit);
+                                let history =
+                                    fetch_history(&db_pool, channel_id, Some(before), limit).await;
+                                let sent = send_history(&mut session, history).await;
+                                if sent.is_err() {
+                                    break;
                                 }
                             }
+                            ClientMessage::Auth { .. } | ClientMessage::Resume { .. } => {}
                         }
                     }
                     WsFrameMessage::Ping(bytes) => {
@@ -311,6 +2142,26 @@ async fn chat_ws_handler(
                     _ => {}
                 }
             }
+            _ = read_interval.tick() => {
+                if let Some(up_to_message_id) = pending_read.take() {
+                    let db_pool_clone = db_pool.clone();
+                    let server_clone = server.clone();
+
+                    tokio::spawn(async move {
+                        match mark_read(&db_pool_clone, channel_id, user_id, up_to_message_id).await {
+                            Ok(true) => {
+                                let receipt = WsMessage::ReadReceipt {
+                                    user_id,
+                                    up_to_message_id,
+                                };
+                                server_clone.send_message(conn_id, channel_id, receipt);
+                            }
+                            Ok(false) => {}
+                            Err(e) => log::error!("Failed to update read state: {}", e),
+                        }
+                    });
+                }
+            }
             _ = interval.tick() => {
                 if Instant::now().duration_since(last_heartbeat) > CLIENT_TIMEOUT {
                     break;
@@ -325,5 +2176,1336 @@ async fn chat_ws_handler(
     }
 
     server.disconnect(conn_id);
-    let _ = session.close(None).await;
+    let _ = session.close(close_reason).await;
+}
+
+/// Registers a session the way the handshake does, with the receiver standing in for the socket.
+#[cfg(test)]
+pub(crate) fn open_session(
+    server: &ChatServerHandle,
+    user_id: Uuid,
+    username: &str,
+    channel_id: Uuid,
+    role: Role,
+) -> (ConnId, mpsc::Receiver<Msg>) {
+    let conn_id = next_conn_id();
+    let (tx, rx) = mpsc::channel(64);
+    let (role_tx, _) = watch::channel(role);
+    let identity = WsIdentity {
+        user_id,
+        username: username.to_string(),
+        avatar_url: None,
+        role,
+    };
+    server.connect(conn_id, &identity, channel_id, tx, role_tx);
+    (conn_id, rx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{
+        add_member, app, create_channel, create_user, insert_message, next_frame, serve,
+        start_server as start_db_server, TestUser, WsClient,
+    };
+    use sqlx::{error::DatabaseError, postgres::PgPoolOptions};
+    use std::net::SocketAddr;
+    use std::{borrow::Cow, error::Error as StdError, fmt};
+
+    /// A database error carrying only a SQLSTATE code.
+    #[derive(Debug)]
+    struct SqlState(&'static str);
+
+    impl fmt::Display for SqlState {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "SQLSTATE {}", self.0)
+        }
+    }
+
+    impl StdError for SqlState {}
+
+    impl DatabaseError for SqlState {
+        fn message(&self) -> &str {
+            self.0
+        }
+
+        fn code(&self) -> Option<Cow<'_, str>> {
+            Some(Cow::Borrowed(self.0))
+        }
+
+        fn as_error(&self) -> &(dyn StdError + Send + Sync + 'static) {
+            self
+        }
+
+        fn as_error_mut(&mut self) -> &mut (dyn StdError + Send + Sync + 'static) {
+            self
+        }
+
+        fn into_error(self: Box<Self>) -> Box<dyn StdError + Send + Sync + 'static> {
+            self
+        }
+
+        fn kind(&self) -> sqlx::error::ErrorKind {
+            sqlx::error::ErrorKind::Other
+        }
+    }
+
+    fn db_error(code: &'static str) -> sqlx::Error {
+        sqlx::Error::Database(Box::new(SqlState(code)))
+    }
+
+    /// A server whose pool never connects; presence and last-seen lookups just log errors.
+    fn start_server() -> ChatServerHandle {
+        start_server_with(|_| {})
+    }
+
+    /// A server without a database, adjusted before it starts.
+    fn start_server_with(configure: impl FnOnce(&mut ChatServer)) -> ChatServerHandle {
+        let pool = PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/chat_test")
+            .unwrap();
+        let (mut server, handle) = ChatServer::new(pool);
+        configure(&mut server);
+        tokio::spawn(server.run());
+        handle
+    }
+
+    fn connect(
+        server: &ChatServerHandle,
+        user_id: Uuid,
+        username: &str,
+        channel_id: Uuid,
+    ) -> (ConnId, mpsc::Receiver<Msg>) {
+        open_session(server, user_id, username, channel_id, Role::Member)
+    }
+
+    /// Opens an authenticated socket and waits until the server has registered it.
+    async fn open_socket(addr: SocketAddr, channel_id: Uuid, user: &TestUser) -> WsClient {
+        let protocol = format!("bearer, {}", user.token);
+        let (head, mut ws) = WsClient::connect(
+            addr,
+            &format!("/ws/{}", channel_id),
+            &[("Sec-WebSocket-Protocol", &protocol)],
+        )
+        .await;
+        assert!(head.starts_with("http/1.1 101"), "{}", head);
+        ws.next_json("resume_token").await;
+        ws
+    }
+
+    fn chat_message(user_id: Uuid, content: &str) -> WsMessage {
+        WsMessage::ChatMessage {
+            id: Uuid::new_v4(),
+            user_id,
+            username: String::new(),
+            content: content.to_string(),
+            created_at: Utc::now(),
+            client_sent_at: None,
+            attachments: Vec::new(),
+            kind: MessageKind::User,
+            format: MessageFormat::Plain,
+        }
+    }
+
+    #[test]
+    fn slow_mode_waits_out_the_most_recent_message() {
+        let ten_secs_ago = Some(Utc::now() - chrono::Duration::seconds(10));
+
+        let remaining = slow_mode_remaining(30, ten_secs_ago, None).unwrap();
+        assert!(remaining > Duration::from_secs(19) && remaining <= Duration::from_secs(20));
+
+        // an unstored send on this connection is more recent than the stored message
+        let remaining = slow_mode_remaining(30, ten_secs_ago, Some(Duration::from_secs(25)));
+        assert!(remaining.unwrap() > Duration::from_secs(19));
+        let remaining = slow_mode_remaining(30, ten_secs_ago, Some(Duration::from_secs(2)));
+        assert_eq!(remaining, Some(Duration::from_secs(28)));
+    }
+
+    #[test]
+    fn slow_mode_lets_through_once_the_interval_passed() {
+        assert_eq!(slow_mode_remaining(30, None, None), None);
+        let long_ago = Some(Utc::now() - chrono::Duration::seconds(60));
+        assert_eq!(slow_mode_remaining(30, long_ago, None), None);
+        assert_eq!(
+            slow_mode_remaining(30, None, Some(Duration::from_secs(30))),
+            None
+        );
+    }
+
+    #[test]
+    fn slow_mode_treats_a_future_timestamp_as_just_now() {
+        let future = Some(Utc::now() + chrono::Duration::seconds(60));
+        assert_eq!(
+            slow_mode_remaining(30, future, None),
+            Some(Duration::from_secs(30))
+        );
+    }
+
+    #[test]
+    fn retry_after_rounds_up_to_whole_seconds() {
+        assert_eq!(retry_after_secs(Duration::from_secs(5)), 5);
+        assert_eq!(retry_after_secs(Duration::from_millis(4_001)), 5);
+        assert_eq!(retry_after_secs(Duration::from_millis(200)), 1);
+    }
+
+    #[test]
+    fn only_transient_errors_are_retried() {
+        let reset = std::io::Error::from(std::io::ErrorKind::ConnectionReset);
+        assert!(is_transient(&sqlx::Error::Io(reset)));
+        assert!(is_transient(&sqlx::Error::PoolTimedOut));
+        assert!(is_transient(&db_error("40001")));
+        assert!(is_transient(&db_error("40P01")));
+        assert!(is_transient(&db_error("08006")));
+
+        // unique and foreign key violations fail the same way every time
+        assert!(!is_transient(&db_error("23505")));
+        assert!(!is_transient(&db_error("23503")));
+        assert!(!is_transient(&sqlx::Error::RowNotFound));
+    }
+
+    /// Makes message inserts fail with a serialization failure, the first `times` of them
+    /// or every one when `times` is `None`.
+    async fn fail_inserts(pool: &PgPool, times: Option<i64>) {
+        sqlx::query("CREATE SEQUENCE insert_attempts")
+            .execute(pool)
+            .await
+            .unwrap();
+        sqlx::query(&format!(
+            r#"
+            CREATE FUNCTION flaky_insert() RETURNS trigger AS $$
+            BEGIN
+                IF nextval('insert_attempts') <= {} THEN
+                    RAISE EXCEPTION 'could not serialize access' USING ERRCODE = '40001';
+                END IF;
+                RETURN NEW;
+            END;
+            $$ LANGUAGE plpgsql
+            "#,
+            times.unwrap_or(i64::MAX)
+        ))
+        .execute(pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            "CREATE TRIGGER flaky_insert BEFORE INSERT ON messages \
+             FOR EACH ROW EXECUTE FUNCTION flaky_insert()",
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    async fn failed_attempts(pool: &PgPool) -> Vec<(String, i32)> {
+        sqlx::query_as("SELECT content, attempts FROM failed_messages ORDER BY created_at")
+            .fetch_all(pool)
+            .await
+            .unwrap()
+    }
+
+    #[sqlx::test]
+    async fn a_transient_failure_is_retried(pool: PgPool) {
+        let alice = create_user(&pool, "alice").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+        fail_inserts(&pool, Some(1)).await;
+
+        let msg = insert_message_with_retry(
+            &pool,
+            channel_id,
+            alice.id,
+            "second time lucky",
+            &[],
+            None,
+            MessageFormat::Plain,
+        )
+        .await
+        .unwrap();
+        assert_eq!(msg.content, "second time lucky");
+        assert!(failed_attempts(&pool).await.is_empty());
+    }
+
+    #[sqlx::test]
+    async fn a_message_that_cannot_be_stored_is_recorded(pool: PgPool) {
+        let alice = create_user(&pool, "alice").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+
+        // a missing channel fails the same way every time, so it isn't retried
+        let missing = Uuid::new_v4();
+        let stored = insert_message_with_retry(
+            &pool,
+            missing,
+            alice.id,
+            "nowhere",
+            &[],
+            None,
+            MessageFormat::Plain,
+        )
+        .await;
+        assert!(stored.is_err());
+        assert_eq!(failed_attempts(&pool).await, [("nowhere".to_string(), 1)]);
+
+        fail_inserts(&pool, None).await;
+        let stored = insert_message_with_retry(
+            &pool,
+            channel_id,
+            alice.id,
+            "never lucky",
+            &[],
+            None,
+            MessageFormat::Plain,
+        )
+        .await;
+        assert!(stored.is_err());
+        assert_eq!(
+            failed_attempts(&pool).await[1],
+            ("never lucky".to_string(), MAX_INSERT_ATTEMPTS as i32)
+        );
+    }
+
+    #[tokio::test]
+    async fn resume_token_only_works_once_the_socket_is_gone() {
+        let server = start_server();
+        let channel_id = Uuid::new_v4();
+        let alice = Uuid::new_v4();
+
+        let (conn, mut rx) = connect(&server, alice, "alice", channel_id);
+        let frame = next_frame(&mut rx, "resume_token").await;
+        let token = frame["token"].as_str().unwrap().to_string();
+
+        assert!(server.resume(&token, channel_id).await.is_none());
+
+        server.disconnect(conn);
+        let identity = server.resume(&token, channel_id).await.unwrap();
+        assert_eq!(identity.user_id, alice);
+
+        assert!(server.resume(&token, channel_id).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn double_submit_is_caught_per_user_and_channel() {
+        let server = start_server();
+        let channel_id = Uuid::new_v4();
+        let alice = Uuid::new_v4();
+
+        assert!(!server.is_duplicate(alice, channel_id, "hi", &[]).await);
+        assert!(server.is_duplicate(alice, channel_id, "hi", &[]).await);
+
+        // other content, another channel or another user is not a repeat
+        assert!(
+            !server
+                .is_duplicate(alice, channel_id, "hi there", &[])
+                .await
+        );
+        assert!(
+            !server
+                .is_duplicate(alice, Uuid::new_v4(), "hi there", &[])
+                .await
+        );
+        assert!(
+            !server
+                .is_duplicate(Uuid::new_v4(), channel_id, "hi there", &[])
+                .await
+        );
+    }
+
+    #[tokio::test]
+    async fn same_message_after_the_window_is_not_a_duplicate() {
+        let server = start_server_with(|server| {
+            server.duplicate_window = Duration::from_millis(50);
+        });
+        let (alice, channel_id) = (Uuid::new_v4(), Uuid::new_v4());
+
+        assert!(!server.is_duplicate(alice, channel_id, "hi", &[]).await);
+        assert!(server.is_duplicate(alice, channel_id, "hi", &[]).await);
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(!server.is_duplicate(alice, channel_id, "hi", &[]).await);
+    }
+
+    #[tokio::test]
+    async fn late_joiner_sees_who_is_typing() {
+        let server = start_server();
+        let channel_id = Uuid::new_v4();
+        let alice = Uuid::new_v4();
+
+        let (alice_conn, _alice_rx) = connect(&server, alice, "alice", channel_id);
+        server.typing(alice_conn, true, None);
+
+        let (bob, mut bob_rx) = connect(&server, Uuid::new_v4(), "bob", channel_id);
+        server.get_typing(bob);
+        let frame = next_frame(&mut bob_rx, "typing_users").await;
+        assert_eq!(frame["users"][0]["user_id"], alice.to_string());
+        assert_eq!(frame["users"][0]["username"], "alice");
+    }
+
+    #[tokio::test]
+    async fn typing_survives_closing_another_tab() {
+        let server = start_server();
+        let channel_id = Uuid::new_v4();
+        let alice = Uuid::new_v4();
+
+        let (typing_tab, _typing_rx) = connect(&server, alice, "alice", channel_id);
+        let (other_tab, _other_rx) = connect(&server, alice, "alice", channel_id);
+        server.typing(typing_tab, true, None);
+        server.disconnect(other_tab);
+
+        let (bob, mut bob_rx) = connect(&server, Uuid::new_v4(), "bob", channel_id);
+        server.get_typing(bob);
+        let frame = next_frame(&mut bob_rx, "typing_users").await;
+        assert_eq!(frame["users"][0]["user_id"], alice.to_string());
+
+        server.disconnect(typing_tab);
+        server.get_typing(bob);
+        let frame = next_frame(&mut bob_rx, "typing_users").await;
+        assert_eq!(frame["users"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn history_limit_defaults_and_caps() {
+        assert_eq!(history_limit(None), DEFAULT_HISTORY_LIMIT);
+        assert_eq!(history_limit(Some(10)), 10);
+        assert_eq!(history_limit(Some(10_000)), MAX_HISTORY_LIMIT);
+        assert_eq!(history_limit(Some(-5)), 0);
+    }
+
+    #[tokio::test]
+    async fn metrics_follow_connects_and_disconnects() {
+        let server = start_server();
+        let (general, random) = (Uuid::new_v4(), Uuid::new_v4());
+        let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+
+        let (alice_tab, _alice_rx) = connect(&server, alice, "alice", general);
+        let (_, _alice_other_rx) = connect(&server, alice, "alice", general);
+        let (bob_conn, _bob_rx) = connect(&server, bob, "bob", random);
+        server.send_message(alice_tab, general, chat_message(alice, "hi"));
+
+        let metrics = server.metrics().await.unwrap();
+        assert_eq!(
+            (
+                metrics.active_connections,
+                metrics.active_channels,
+                metrics.online_users,
+                metrics.messages_relayed,
+            ),
+            (3, 2, 2, 1)
+        );
+
+        server.disconnect(bob_conn);
+        server.disconnect(alice_tab);
+        let metrics = server.metrics().await.unwrap();
+        assert_eq!(
+            (
+                metrics.active_connections,
+                metrics.active_channels,
+                metrics.online_users,
+            ),
+            (1, 1, 1)
+        );
+    }
+
+    #[tokio::test]
+    async fn joining_sends_one_entry_per_online_user() {
+        let server = start_server();
+        let (general, random) = (Uuid::new_v4(), Uuid::new_v4());
+        let (alice, bob, carol) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+
+        // alice has two tabs open, dave is only elsewhere
+        let _alice_tab = connect(&server, alice, "alice", general);
+        let _alice_other_tab = connect(&server, alice, "alice", general);
+        let _bob = connect(&server, bob, "bob", general);
+        let _dave = connect(&server, Uuid::new_v4(), "dave", random);
+
+        let (_, mut carol_rx) = connect(&server, carol, "carol", general);
+        let snapshot = next_frame(&mut carol_rx, "online_snapshot").await;
+        let users: Vec<(&str, &str)> = snapshot["users"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|user| {
+                (
+                    user["username"].as_str().unwrap(),
+                    user["user_id"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        let (alice, bob, carol) = (alice.to_string(), bob.to_string(), carol.to_string());
+        assert_eq!(
+            users,
+            [
+                ("alice", alice.as_str()),
+                ("bob", bob.as_str()),
+                ("carol", carol.as_str())
+            ]
+        );
+        assert_eq!(snapshot["total"], 3);
+        assert_eq!(snapshot["has_more"], false);
+    }
+
+    #[tokio::test]
+    async fn large_snapshots_are_capped_and_paged_on_request() {
+        let server = start_server();
+        let channel_id = Uuid::new_v4();
+        let crowd = ONLINE_SNAPSHOT_PAGE + 20;
+
+        // the crowd's receivers are drained so none of them is dropped as a slow consumer
+        for i in 0..crowd {
+            let (_, mut rx) = connect(
+                &server,
+                Uuid::new_v4(),
+                &format!("user{:03}", i),
+                channel_id,
+            );
+            tokio::spawn(async move { while rx.recv().await.is_some() {} });
+            server.metrics().await.unwrap();
+        }
+        let (zed, mut zed_rx) = connect(&server, Uuid::new_v4(), "zed", channel_id);
+
+        let first = next_frame(&mut zed_rx, "online_snapshot").await;
+        assert_eq!(
+            first["users"].as_array().unwrap().len(),
+            ONLINE_SNAPSHOT_PAGE
+        );
+        assert_eq!(first["total"], crowd + 1);
+        assert_eq!(first["offset"], 0);
+        assert_eq!(first["has_more"], true);
+        assert_eq!(first["users"][0]["username"], "user000");
+
+        server.get_online(zed, ONLINE_SNAPSHOT_PAGE);
+        let rest = next_frame(&mut zed_rx, "online_snapshot").await;
+        let names: Vec<&str> = rest["users"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|user| user["username"].as_str().unwrap())
+            .collect();
+        assert_eq!(names.len(), 21);
+        assert_eq!(names.first(), Some(&"user100"));
+        assert_eq!(names.last(), Some(&"zed"));
+        assert_eq!(rest["offset"], ONLINE_SNAPSHOT_PAGE);
+        assert_eq!(rest["has_more"], false);
+
+        server.get_online(zed, crowd + 1);
+        let past_the_end = next_frame(&mut zed_rx, "online_snapshot").await;
+        assert!(past_the_end["users"].as_array().unwrap().is_empty());
+        assert_eq!(past_the_end["has_more"], false);
+    }
+
+    #[tokio::test]
+    async fn over_the_limit_the_oldest_session_is_closed() {
+        let server = start_server_with(|server| {
+            server.limits = ConnectionLimits {
+                per_user: 20,
+                per_user_channel: 2,
+                policy: LimitPolicy::CloseOldest,
+            };
+        });
+        let channel_id = Uuid::new_v4();
+        let alice = Uuid::new_v4();
+
+        let (_, mut oldest_rx) = connect(&server, alice, "alice", channel_id);
+        let (_, _second_rx) = connect(&server, alice, "alice", channel_id);
+        let (_, _newest_rx) = connect(&server, alice, "alice", channel_id);
+
+        let error = next_frame(&mut oldest_rx, "error").await;
+        assert_eq!(error["code"], "too_many_connections");
+        while oldest_rx.recv().await.is_some() {}
+        assert_eq!(server.metrics().await.unwrap().active_connections, 2);
+    }
+
+    #[tokio::test]
+    async fn over_the_limit_the_newest_session_can_be_rejected() {
+        let server = start_server_with(|server| {
+            server.limits = ConnectionLimits {
+                per_user: 2,
+                per_user_channel: 5,
+                policy: LimitPolicy::RejectNewest,
+            };
+        });
+        let alice = Uuid::new_v4();
+
+        // the per-user limit counts sessions across channels
+        let (_, mut first_rx) = connect(&server, alice, "alice", Uuid::new_v4());
+        let (_, _second_rx) = connect(&server, alice, "alice", Uuid::new_v4());
+        let (_, mut rejected_rx) = connect(&server, alice, "alice", Uuid::new_v4());
+
+        let error = next_frame(&mut rejected_rx, "error").await;
+        assert_eq!(error["code"], "too_many_connections");
+        assert!(rejected_rx.recv().await.is_none());
+        assert_eq!(server.metrics().await.unwrap().active_connections, 2);
+        // the first session is untouched: drained, but not closed
+        let still_open = loop {
+            match first_rx.try_recv() {
+                Ok(_) => continue,
+                Err(e) => break e == mpsc::error::TryRecvError::Empty,
+            }
+        };
+        assert!(still_open);
+    }
+
+    #[tokio::test]
+    async fn chat_from_a_viewer_session_is_not_relayed() {
+        let server = start_server();
+        let channel_id = Uuid::new_v4();
+        let viewer = Uuid::new_v4();
+        let member = Uuid::new_v4();
+
+        let (viewer_conn, _viewer_rx) =
+            open_session(&server, viewer, "viewer", channel_id, Role::Viewer);
+        let (member_conn, _member_rx) = connect(&server, member, "member", channel_id);
+        let (_, mut bob_rx) = connect(&server, Uuid::new_v4(), "bob", channel_id);
+
+        server.send_message(viewer_conn, channel_id, chat_message(viewer, "from viewer"));
+        server.send_message(member_conn, channel_id, chat_message(member, "from member"));
+
+        // relays keep their order, so the viewer's would have arrived first
+        let frame = next_frame(&mut bob_rx, "chat").await;
+        assert_eq!(frame["content"], "from member");
+        assert_eq!(frame["username"], "member");
+    }
+
+    #[tokio::test]
+    async fn chat_stored_after_its_socket_closed_is_still_relayed() {
+        let server = start_server();
+        let channel_id = Uuid::new_v4();
+        let alice = Uuid::new_v4();
+
+        let (alice_conn, _alice_rx) = connect(&server, alice, "alice", channel_id);
+        let (_, mut bob_rx) = connect(&server, Uuid::new_v4(), "bob", channel_id);
+        server.disconnect(alice_conn);
+
+        server.send_message(
+            alice_conn,
+            channel_id,
+            chat_message(alice, "sent just before closing"),
+        );
+        let frame = next_frame(&mut bob_rx, "chat").await;
+        assert_eq!(frame["content"], "sent just before closing");
+    }
+
+    #[tokio::test]
+    async fn rename_applies_to_open_sessions() {
+        let server = start_server();
+        let channel_id = Uuid::new_v4();
+        let alice = Uuid::new_v4();
+
+        let (alice_conn, _alice_rx) = connect(&server, alice, "alice", channel_id);
+        let (_, mut bob_rx) = connect(&server, Uuid::new_v4(), "bob", channel_id);
+
+        server.rename_user(alice, "alicia".to_string());
+        server.send_message(alice_conn, channel_id, chat_message(alice, "hi"));
+        let frame = next_frame(&mut bob_rx, "chat").await;
+        assert_eq!(frame["username"], "alicia");
+    }
+
+    #[tokio::test]
+    async fn rapid_typing_only_broadcasts_state_changes() {
+        let server = start_server();
+        let channel_id = Uuid::new_v4();
+        let alice = Uuid::new_v4();
+
+        let (alice_conn, _alice_rx) = connect(&server, alice, "alice", channel_id);
+        let (_, mut bob_rx) = connect(&server, Uuid::new_v4(), "bob", channel_id);
+
+        for is_typing in [true, true, true, false, false, true] {
+            server.typing(alice_conn, is_typing, None);
+        }
+        // a relayed chat marks the end of what the typing events produced
+        server.send_message(alice_conn, channel_id, chat_message(alice, "done"));
+
+        let mut broadcast = Vec::new();
+        loop {
+            let text = tokio::time::timeout(Duration::from_secs(1), bob_rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            let frame: serde_json::Value = serde_json::from_str(&text).unwrap();
+            match frame["type"].as_str() {
+                Some("typing") => broadcast.push(frame["is_typing"].as_bool().unwrap()),
+                Some("chat") => break,
+                _ => {}
+            }
+        }
+        assert_eq!(broadcast, [true, false, true]);
+    }
+
+    #[tokio::test]
+    async fn typing_reports_the_thread_or_the_channel() {
+        let server = start_server();
+        let channel_id = Uuid::new_v4();
+        let thread = Uuid::new_v4();
+
+        let (alice_conn, _alice_rx) = connect(&server, Uuid::new_v4(), "alice", channel_id);
+        let (_, mut bob_rx) = connect(&server, Uuid::new_v4(), "bob", channel_id);
+
+        server.typing(alice_conn, true, None);
+        let frame = next_frame(&mut bob_rx, "typing").await;
+        assert_eq!(frame["is_typing"], true);
+        assert!(frame.get("parent_id").is_none());
+
+        // moving into a thread is a change even while still typing
+        server.typing(alice_conn, true, Some(thread));
+        let frame = next_frame(&mut bob_rx, "typing").await;
+        assert_eq!(frame["is_typing"], true);
+        assert_eq!(frame["parent_id"], thread.to_string());
+
+        server.typing(alice_conn, false, None);
+        let frame = next_frame(&mut bob_rx, "typing").await;
+        assert_eq!(frame["is_typing"], false);
+        assert_eq!(frame["parent_id"], thread.to_string());
+    }
+
+    #[tokio::test]
+    async fn closing_a_channel_notifies_and_drops_its_sessions() {
+        let server = start_server();
+        let channel_id = Uuid::new_v4();
+        let (_, mut alice_rx) = connect(&server, Uuid::new_v4(), "alice", channel_id);
+        let (_, mut bob_rx) = connect(&server, Uuid::new_v4(), "bob", channel_id);
+        let (_, mut elsewhere_rx) = connect(&server, Uuid::new_v4(), "carol", Uuid::new_v4());
+
+        server.close_channel(channel_id);
+
+        for rx in [&mut alice_rx, &mut bob_rx] {
+            let frame = next_frame(rx, "channel_deleted").await;
+            assert_eq!(frame["channel_id"], channel_id.to_string());
+            // the server drops its end, which ends the socket
+            while rx.recv().await.is_some() {}
+        }
+
+        let metrics = server.metrics().await.unwrap();
+        assert_eq!(metrics.active_connections, 1);
+        while let Ok(text) = elsewhere_rx.try_recv() {
+            assert!(!text.contains("channel_deleted"), "{}", text);
+        }
+    }
+
+    #[tokio::test]
+    async fn client_that_stops_reading_is_dropped_once_its_buffer_fills() {
+        let server = start_server();
+        let channel_id = Uuid::new_v4();
+        let (_, mut slow_rx) = connect(&server, Uuid::new_v4(), "slow", channel_id);
+        let (_, _elsewhere_rx) = connect(&server, Uuid::new_v4(), "other", Uuid::new_v4());
+
+        // well past the 64 frames a test session buffers
+        for i in 0..100 {
+            server.broadcast(channel_id, chat_message(Uuid::new_v4(), &i.to_string()));
+        }
+
+        let metrics = server.metrics().await.unwrap();
+        assert_eq!(metrics.dropped_clients, 1);
+        assert_eq!(metrics.active_connections, 1);
+
+        // what was buffered is still delivered, then the session ends
+        let mut buffered = 0;
+        while slow_rx.recv().await.is_some() {
+            buffered += 1;
+        }
+        assert_eq!(buffered, 64);
+    }
+
+    #[sqlx::test]
+    async fn token_in_the_subprotocol_header_authenticates(pool: PgPool) {
+        let server = start_db_server(&pool);
+        let addr = serve(&pool, &server);
+        let alice = create_user(&pool, "alice").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+
+        let protocol = format!("bearer, {}", alice.token);
+        let (head, mut ws) = WsClient::connect(
+            addr,
+            &format!("/ws/{}", channel_id),
+            &[("Sec-WebSocket-Protocol", &protocol)],
+        )
+        .await;
+        assert!(head.starts_with("http/1.1 101"), "{}", head);
+        assert!(
+            head.contains("sec-websocket-protocol: bearer\r\n"),
+            "{}",
+            head
+        );
+        ws.next_json("resume_token").await;
+    }
+
+    #[sqlx::test]
+    async fn token_in_a_first_auth_frame_authenticates(pool: PgPool) {
+        let server = start_db_server(&pool);
+        let addr = serve(&pool, &server);
+        let alice = create_user(&pool, "alice").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+
+        let (head, mut ws) = WsClient::connect(addr, &format!("/ws/{}", channel_id), &[]).await;
+        assert!(head.starts_with("http/1.1 101"), "{}", head);
+        ws.send_json(serde_json::json!({ "type": "auth", "token": alice.token }))
+            .await;
+        ws.next_json("resume_token").await;
+    }
+
+    #[sqlx::test]
+    async fn unauthenticated_socket_is_closed_after_the_grace_period(pool: PgPool) {
+        let server = start_db_server(&pool);
+        let addr = serve(&pool, &server);
+        let alice = create_user(&pool, "alice").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+
+        let started = Instant::now();
+        let (_, mut ws) = WsClient::connect(addr, &format!("/ws/{}", channel_id), &[]).await;
+        assert_eq!(ws.close_code().await, 1008);
+        assert!(started.elapsed() >= AUTH_TIMEOUT);
+    }
+
+    #[sqlx::test]
+    async fn mark_read_is_relayed_as_a_read_receipt(pool: PgPool) {
+        let server = start_db_server(&pool);
+        let addr = serve(&pool, &server);
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+        add_member(&pool, channel_id, &bob, Role::Member).await;
+        let message_id = insert_message(&pool, channel_id, &bob, "hi").await;
+
+        let mut alice_ws = open_socket(addr, channel_id, &alice).await;
+        let mut bob_ws = open_socket(addr, channel_id, &bob).await;
+
+        alice_ws
+            .send_json(serde_json::json!({ "type": "mark_read", "up_to_message_id": message_id }))
+            .await;
+        let receipt = bob_ws.next_json("read_receipt").await;
+        assert_eq!(receipt["user_id"], alice.id.to_string());
+        assert_eq!(receipt["up_to_message_id"], message_id.to_string());
+    }
+
+    #[sqlx::test]
+    async fn read_position_only_moves_forward(pool: PgPool) {
+        let alice = create_user(&pool, "alice").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+        let older = insert_message(&pool, channel_id, &alice, "first").await;
+        sqlx::query("UPDATE messages SET created_at = NOW() - INTERVAL '1 minute' WHERE id = $1")
+            .bind(older)
+            .execute(&pool)
+            .await
+            .unwrap();
+        let newer = insert_message(&pool, channel_id, &alice, "second").await;
+
+        assert!(mark_read(&pool, channel_id, alice.id, newer).await.unwrap());
+        assert!(!mark_read(&pool, channel_id, alice.id, older).await.unwrap());
+        assert!(!mark_read(&pool, channel_id, alice.id, newer).await.unwrap());
+
+        let position = sqlx::query_scalar::<_, Uuid>(
+            "SELECT last_read_message_id FROM channel_reads WHERE channel_id = $1 AND user_id = $2",
+        )
+        .bind(channel_id)
+        .bind(alice.id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(position, newer);
+    }
+
+    #[sqlx::test]
+    async fn viewer_socket_is_refused_where_a_member_is_relayed(pool: PgPool) {
+        let server = start_db_server(&pool);
+        let addr = serve(&pool, &server);
+        let alice = create_user(&pool, "alice").await;
+        let viewer = create_user(&pool, "viewer").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+        add_member(&pool, channel_id, &viewer, Role::Viewer).await;
+
+        let mut alice_ws = open_socket(addr, channel_id, &alice).await;
+        let mut viewer_ws = open_socket(addr, channel_id, &viewer).await;
+
+        viewer_ws
+            .send_json(serde_json::json!({ "type": "send_message", "content": "from viewer" }))
+            .await;
+        let error = viewer_ws.next_json("error").await;
+        assert_eq!(error["code"], "forbidden");
+
+        alice_ws
+            .send_json(serde_json::json!({ "type": "send_message", "content": "from member" }))
+            .await;
+        // relays keep their order, so the viewer's would have arrived first
+        let frame = viewer_ws.next_json("chat").await;
+        assert_eq!(frame["content"], "from member");
+
+        let stored =
+            sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM messages WHERE channel_id = $1")
+                .bind(channel_id)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(stored, 1);
+    }
+
+    #[sqlx::test]
+    async fn demotion_is_announced_and_applies_to_the_open_socket(pool: PgPool) {
+        let server = start_db_server(&pool);
+        let addr = serve(&pool, &server);
+        let app = actix_web::test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+        add_member(&pool, channel_id, &bob, Role::Member).await;
+
+        let mut alice_ws = open_socket(addr, channel_id, &alice).await;
+        let mut bob_ws = open_socket(addr, channel_id, &bob).await;
+
+        bob_ws
+            .send_json(serde_json::json!({ "type": "send_message", "content": "as a member" }))
+            .await;
+        assert_eq!(alice_ws.next_json("chat").await["content"], "as a member");
+
+        let req = actix_web::test::TestRequest::patch()
+            .uri(&format!("/api/channels/{}/members/{}", channel_id, bob.id))
+            .insert_header(alice.bearer())
+            .set_json(serde_json::json!({ "role": "viewer" }))
+            .to_request();
+        assert!(actix_web::test::call_service(&app, req)
+            .await
+            .status()
+            .is_success());
+
+        for ws in [&mut alice_ws, &mut bob_ws] {
+            let frame = ws.next_json("role_changed").await;
+            assert_eq!(frame["user_id"], bob.id.to_string());
+            assert_eq!(frame["role"], "viewer");
+        }
+
+        // same socket, no reconnect
+        bob_ws
+            .send_json(serde_json::json!({ "type": "send_message", "content": "as a viewer" }))
+            .await;
+        assert_eq!(bob_ws.next_json("error").await["code"], "forbidden");
+    }
+
+    #[sqlx::test]
+    async fn script_tags_are_escaped_before_storing_and_relaying(pool: PgPool) {
+        let server = start_db_server(&pool);
+        let addr = serve(&pool, &server);
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+        add_member(&pool, channel_id, &bob, Role::Member).await;
+
+        let mut alice_ws = open_socket(addr, channel_id, &alice).await;
+        let mut bob_ws = open_socket(addr, channel_id, &bob).await;
+        alice_ws
+            .send_json(serde_json::json!({
+                "type": "send_message",
+                "content": "<script>alert(1)</script>",
+            }))
+            .await;
+
+        let escaped = "&lt;script&gt;alert(1)&lt;/script&gt;";
+        assert_eq!(bob_ws.next_json("chat").await["content"], escaped);
+        let stored: String =
+            sqlx::query_scalar("SELECT content FROM messages WHERE channel_id = $1")
+                .bind(channel_id)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(stored, escaped);
+    }
+
+    #[sqlx::test]
+    async fn slow_mode_holds_back_members_but_not_admins(pool: PgPool) {
+        let server = start_db_server(&pool);
+        let addr = serve(&pool, &server);
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+        let channel_id = create_channel(&pool, &alice, "announcements").await;
+        add_member(&pool, channel_id, &bob, Role::Member).await;
+        sqlx::query("UPDATE channels SET slow_mode_seconds = 30 WHERE id = $1")
+            .bind(channel_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let mut alice_ws = open_socket(addr, channel_id, &alice).await;
+        let mut bob_ws = open_socket(addr, channel_id, &bob).await;
+        let send =
+            |content: &str| serde_json::json!({ "type": "send_message", "content": content });
+
+        bob_ws.send_json(send("first")).await;
+        assert_eq!(alice_ws.next_json("chat").await["content"], "first");
+        bob_ws.send_json(send("too soon")).await;
+        let error = bob_ws.next_json("error").await;
+        assert_eq!(error["code"], "slow_mode");
+        let retry_after = error["retry_after"].as_u64().unwrap();
+        assert!((1..=30).contains(&retry_after), "{}", retry_after);
+
+        for content in ["one", "two"] {
+            alice_ws.send_json(send(content)).await;
+            assert_eq!(bob_ws.next_json("chat").await["content"], content);
+        }
+
+        let from_bob: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM messages WHERE user_id = $1")
+            .bind(bob.id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(from_bob, 1);
+    }
+
+    #[sqlx::test]
+    async fn presence_reaches_channel_peers_only(pool: PgPool) {
+        let server = start_db_server(&pool);
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+        let carol = create_user(&pool, "carol").await;
+        let general = create_channel(&pool, &alice, "general").await;
+        add_member(&pool, general, &bob, Role::Member).await;
+        let notes = create_channel(&pool, &alice, "notes").await;
+        let solo = create_channel(&pool, &carol, "solo").await;
+
+        let (_, mut bob_rx) = open_session(&server, bob.id, "bob", general, Role::Member);
+        let (_, mut carol_rx) = open_session(&server, carol.id, "carol", solo, Role::Admin);
+
+        // a channel bob isn't in, so only presence can tell him
+        let (alice_conn, _alice_rx) = open_session(&server, alice.id, "alice", notes, Role::Admin);
+        let frame = next_frame(&mut bob_rx, "presence").await;
+        assert_eq!(frame["user_id"], alice.id.to_string());
+        assert_eq!(frame["online"], true);
+
+        server.disconnect(alice_conn);
+        let frame = next_frame(&mut bob_rx, "presence").await;
+        assert_eq!(frame["online"], false);
+
+        while let Ok(text) = carol_rx.try_recv() {
+            let frame: serde_json::Value = serde_json::from_str(&text).unwrap();
+            assert_ne!(frame["type"], "presence", "{}", frame);
+        }
+    }
+
+    #[sqlx::test]
+    async fn connect_replays_the_requested_amount_of_history(pool: PgPool) {
+        let server = start_db_server(&pool);
+        let addr = serve(&pool, &server);
+        let alice = create_user(&pool, "alice").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+        for content in ["first", "second", "third"] {
+            insert_message(&pool, channel_id, &alice, content).await;
+        }
+
+        let protocol = format!("bearer, {}", alice.token);
+        for (query, expected, has_more) in [
+            ("", &["first", "second", "third"][..], false),
+            ("?history=2", &["second", "third"][..], true),
+        ] {
+            let (_, mut ws) = WsClient::connect(
+                addr,
+                &format!("/ws/{}{}", channel_id, query),
+                &[("Sec-WebSocket-Protocol", &protocol)],
+            )
+            .await;
+            let history = ws.next_json("history").await;
+            let contents: Vec<&str> = history["messages"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|message| message["content"].as_str().unwrap())
+                .collect();
+            assert_eq!(contents, expected);
+            assert_eq!(history["has_more"], has_more);
+        }
+    }
+
+    #[sqlx::test]
+    async fn resuming_replays_only_missed_messages_and_a_spent_token_falls_back_to_auth(
+        pool: PgPool,
+    ) {
+        let server = start_db_server(&pool);
+        let addr = serve(&pool, &server);
+        let alice = create_user(&pool, "alice").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+        let seen = insert_message(&pool, channel_id, &alice, "seen").await;
+
+        let protocol = format!("bearer, {}", alice.token);
+        let (_, mut ws) = WsClient::connect(
+            addr,
+            &format!("/ws/{}", channel_id),
+            &[("Sec-WebSocket-Protocol", &protocol)],
+        )
+        .await;
+        let token = ws.next_json("resume_token").await["token"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        ws.send(WsClient::CLOSE, &1000u16.to_be_bytes()).await;
+        drop(ws);
+        while server.metrics().await.unwrap().active_connections > 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        for content in ["missed one", "missed two"] {
+            insert_message(&pool, channel_id, &alice, content).await;
+        }
+        let resume = serde_json::json!({
+            "type": "resume",
+            "token": token,
+            "last_message_id": seen,
+        });
+
+        let (_, mut ws) = WsClient::connect(addr, &format!("/ws/{}", channel_id), &[]).await;
+        ws.send_json(resume.clone()).await;
+        let history = ws.next_json("history").await;
+        let contents: Vec<&str> = history["messages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|message| message["content"].as_str().unwrap())
+            .collect();
+        assert_eq!(contents, ["missed one", "missed two"]);
+
+        // the token was spent, so the client has to authenticate again
+        let (_, mut ws) = WsClient::connect(addr, &format!("/ws/{}", channel_id), &[]).await;
+        ws.send_json(resume).await;
+        let error = ws.next_json("error").await;
+        assert_eq!(error["code"], "resume_expired");
+        ws.send_json(serde_json::json!({ "type": "auth", "token": alice.token }))
+            .await;
+        let history = ws.next_json("history").await;
+        assert_eq!(history["messages"].as_array().unwrap().len(), 3);
+    }
+
+    #[sqlx::test]
+    async fn loading_more_walks_back_to_an_empty_batch(pool: PgPool) {
+        let server = start_db_server(&pool);
+        let addr = serve(&pool, &server);
+        let alice = create_user(&pool, "alice").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+        let mut ids = Vec::new();
+        for i in 1..=5 {
+            ids.push(insert_message(&pool, channel_id, &alice, &format!("m{}", i)).await);
+        }
+
+        let protocol = format!("bearer, {}", alice.token);
+        let (_, mut ws) = WsClient::connect(
+            addr,
+            &format!("/ws/{}?history=2", channel_id),
+            &[("Sec-WebSocket-Protocol", &protocol)],
+        )
+        .await;
+        ws.next_json("history").await;
+
+        let mut before = ids[3];
+        let mut batches = Vec::new();
+        loop {
+            ws.send_json(serde_json::json!({
+                "type": "load_history",
+                "before_id": before,
+                "limit": 2,
+            }))
+            .await;
+            let history = ws.next_json("history").await;
+            let messages = history["messages"].as_array().unwrap();
+            let contents: Vec<String> = messages
+                .iter()
+                .map(|message| message["content"].as_str().unwrap().to_string())
+                .collect();
+            batches.push((contents, history["has_more"].as_bool().unwrap()));
+            match messages.first() {
+                Some(oldest) => before = oldest["id"].as_str().unwrap().parse().unwrap(),
+                None => break,
+            }
+        }
+        assert_eq!(
+            batches,
+            [
+                (vec!["m2".to_string(), "m3".to_string()], true),
+                (vec!["m1".to_string()], false),
+                (vec![], false),
+            ]
+        );
+
+        sqlx::query("DELETE FROM channel_members WHERE channel_id = $1")
+            .bind(channel_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        ws.send_json(serde_json::json!({ "type": "load_more", "before": ids[1] }))
+            .await;
+        assert_eq!(ws.next_json("error").await["code"], "not_member");
+    }
+
+    #[sqlx::test]
+    async fn sending_to_an_archived_channel_gets_an_error_frame(pool: PgPool) {
+        let server = start_db_server(&pool);
+        let addr = serve(&pool, &server);
+        let alice = create_user(&pool, "alice").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+
+        let mut ws = open_socket(addr, channel_id, &alice).await;
+        sqlx::query("UPDATE channels SET archived_at = NOW() WHERE id = $1")
+            .bind(channel_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        ws.send_json(serde_json::json!({ "type": "send_message", "content": "too late" }))
+            .await;
+        let error = ws.next_json("error").await;
+        assert_eq!(error["code"], "channel_archived");
+
+        let stored =
+            sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM messages WHERE channel_id = $1")
+                .bind(channel_id)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(stored, 0);
+    }
+
+    #[sqlx::test]
+    async fn rename_over_rest_shows_on_an_open_socket(pool: PgPool) {
+        let server = start_db_server(&pool);
+        let addr = serve(&pool, &server);
+        let app = actix_web::test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+        add_member(&pool, channel_id, &bob, Role::Member).await;
+
+        let mut alice_ws = open_socket(addr, channel_id, &alice).await;
+        let mut bob_ws = open_socket(addr, channel_id, &bob).await;
+
+        let req = actix_web::test::TestRequest::patch()
+            .uri("/api/me/username")
+            .insert_header(alice.bearer())
+            .set_json(serde_json::json!({ "username": "alicia" }))
+            .to_request();
+        assert!(actix_web::test::call_service(&app, req)
+            .await
+            .status()
+            .is_success());
+
+        alice_ws
+            .send_json(serde_json::json!({ "type": "send_message", "content": "hi" }))
+            .await;
+        let frame = bob_ws.next_json("chat").await;
+        assert_eq!(frame["username"], "alicia");
+    }
+
+    #[sqlx::test]
+    async fn offered_compression_is_declined(pool: PgPool) {
+        let server = start_db_server(&pool);
+        let addr = serve(&pool, &server);
+        let alice = create_user(&pool, "alice").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+
+        // the codec can't read or write compressed frames, so the offer must not be accepted
+        let protocol = format!("bearer, {}", alice.token);
+        let (head, mut ws) = WsClient::connect(
+            addr,
+            &format!("/ws/{}", channel_id),
+            &[
+                ("Sec-WebSocket-Protocol", &protocol),
+                ("Sec-WebSocket-Extensions", "permessage-deflate"),
+            ],
+        )
+        .await;
+        assert!(head.starts_with("http/1.1 101"), "{}", head);
+        assert!(!head.contains("sec-websocket-extensions"), "{}", head);
+        ws.next_json("resume_token").await;
+    }
+
+    #[sqlx::test]
+    async fn an_oversized_frame_closes_the_socket(pool: PgPool) {
+        let server = start_db_server(&pool);
+        let addr = serve(&pool, &server);
+        let alice = create_user(&pool, "alice").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+
+        let mut ws = open_socket(addr, channel_id, &alice).await;
+        let padding = "x".repeat(DEFAULT_MAX_FRAME_SIZE);
+        ws.send_json(serde_json::json!({ "type": "typing", "is_typing": true, "pad": padding }))
+            .await;
+
+        assert_eq!(ws.close_code().await, 1009);
+    }
+
+    #[sqlx::test]
+    async fn malformed_payload_gets_an_error_frame_and_the_socket_stays_up(pool: PgPool) {
+        let server = start_db_server(&pool);
+        let addr = serve(&pool, &server);
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+        add_member(&pool, channel_id, &bob, Role::Member).await;
+        let mut ws = open_socket(addr, channel_id, &alice).await;
+        let mut bob_ws = open_socket(addr, channel_id, &bob).await;
+
+        ws.send(WsClient::TEXT, b"{not json").await;
+        let error = ws.next_json("error").await;
+        assert_eq!(error["code"], "bad_payload");
+
+        ws.send_json(serde_json::json!({ "type": "send_message" }))
+            .await;
+        let error = ws.next_json("error").await;
+        assert_eq!(error["code"], "bad_payload");
+        assert!(
+            error["message"].as_str().unwrap().contains("content"),
+            "{}",
+            error
+        );
+
+        ws.send_json(serde_json::json!({ "type": "send_message", "content": "still here" }))
+            .await;
+        assert_eq!(bob_ws.next_json("chat").await["content"], "still here");
+    }
+
+    #[sqlx::test]
+    async fn unknown_message_type_is_reported(pool: PgPool) {
+        let server = start_db_server(&pool);
+        let addr = serve(&pool, &server);
+        let alice = create_user(&pool, "alice").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+        let mut ws = open_socket(addr, channel_id, &alice).await;
+
+        ws.send_json(serde_json::json!({ "type": "nope" })).await;
+        let error = ws.next_json("error").await;
+        assert_eq!(error["code"], "bad_payload");
+        // the hint names the offending type
+        let message = error["message"].as_str().unwrap();
+        assert!(message.contains("unknown variant `nope`"), "{}", message);
+    }
+
+    #[sqlx::test]
+    async fn format_hint_round_trips_through_send_and_fetch(pool: PgPool) {
+        let server = start_db_server(&pool);
+        let addr = serve(&pool, &server);
+        let app = actix_web::test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+        add_member(&pool, channel_id, &bob, Role::Member).await;
+
+        let mut ws = open_socket(addr, channel_id, &alice).await;
+        let mut bob_ws = open_socket(addr, channel_id, &bob).await;
+        ws.send_json(serde_json::json!({
+            "type": "send_message",
+            "content": "**bold**",
+            "format": "markdown",
+        }))
+        .await;
+        assert_eq!(bob_ws.next_json("chat").await["format"], "markdown");
+        ws.send_json(serde_json::json!({ "type": "send_message", "content": "plain" }))
+            .await;
+        assert_eq!(bob_ws.next_json("chat").await["format"], "plain");
+
+        let req = actix_web::test::TestRequest::get()
+            .uri(&format!("/api/channels/{}/messages", channel_id))
+            .insert_header(alice.bearer())
+            .to_request();
+        let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, req).await;
+        let formats: HashMap<&str, &str> = body
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| {
+                (
+                    m["content"].as_str().unwrap(),
+                    m["format"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(formats["**bold**"], "markdown");
+        assert_eq!(formats["plain"], "plain");
+    }
 }
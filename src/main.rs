@@ -1,29 +1,36 @@
+mod config;
 mod db;
 mod handlers;
 mod middleware;
 mod models;
+#[cfg(test)]
+mod test_support;
 mod utils;
 
 use crate::{
-    db::pool::{create_pool, run_migrations},
+    config::bind::BindTarget,
+    db::pool::{create_pool, migration_status, run_migrations},
     handlers::websocket::ChatServer,
 };
-use actix_cors::Cors;
 use actix_web::{
-    http::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE},
+    guard,
     web::{self},
     App, HttpServer,
 };
 use actix_web_httpauth::middleware::HttpAuthentication;
 use dotenv::dotenv;
-use env_logger::Env;
 use std::env;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenv().ok();
 
-    env_logger::init_from_env(Env::default().default_filter_or("info"));
+    config::logging::init_logger();
+
+    if let Err(e) = utils::jwt::init_jwt_keys() {
+        log::error!("Invalid JWT configuration: {}", e);
+        std::process::exit(1);
+    }
 
     let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set in .env file!");
 
@@ -31,74 +38,323 @@ async fn main() -> std::io::Result<()> {
         .await
         .expect("Failed to create database pool!");
 
+    let migrate_only = env::args().any(|arg| arg == "--migrate-only");
+
+    let pending: Vec<_> = migration_status(&pool)
+        .await
+        .expect("Failed to read migration status!")
+        .into_iter()
+        .filter(|migration| !migration.applied)
+        .collect();
+
+    for migration in &pending {
+        log::info!(
+            "Applying migration {} ({})",
+            migration.version,
+            migration.description
+        );
+    }
+
     run_migrations(&pool)
         .await
         .expect("Failed to run migrations!");
 
+    log::info!("Migrations up to date ({} applied now)", pending.len());
+
+    if migrate_only {
+        return Ok(());
+    }
+
     let (chat_server, chat_server_handle) = ChatServer::new(pool.clone());
     tokio::spawn(chat_server.run());
+    let drain_handle = chat_server_handle.clone();
+    tokio::spawn(db::retention::run_retention_task(pool.clone()));
+
+    let bind_target = config::bind::bind_target();
+    let http_settings = config::http::http_settings();
+    log::info!(
+        "HTTP workers: {}, keep-alive: {}",
+        http_settings.workers,
+        http_settings.keep_alive.map_or_else(
+            || "off".to_string(),
+            |timeout| format!("{}s", timeout.as_secs())
+        )
+    );
+
+    let json_body_limit = config::json::json_body_limit();
+    let guest_read_access = config::guest_read_access();
 
-    let host = env::var("HOST").unwrap_or_else(|_| "localhost".to_string());
-    let port = env::var("PORT").unwrap_or_else(|_| "8080".to_string());
-    let address = format!("{}:{}", host, port);
+    let trusted_proxies = config::proxy::trusted_proxies();
 
-    HttpServer::new(move || {
-        let auth = HttpAuthentication::bearer(middleware::auth::jwt_validator);
+    let allowed_origins = config::cors::allowed_origins();
+    if allowed_origins.is_empty() {
+        log::warn!("CORS_ALLOWED_ORIGINS is not set, allowing any origin");
+    }
+
+    let server = HttpServer::new(move || {
         App::new()
-            .wrap(actix_web::middleware::Logger::default())
             .wrap(
-                Cors::default()
-                    .allow_any_origin()
-                    .allow_any_method()
-                    .send_wildcard()
-                    .allowed_headers(vec![AUTHORIZATION, ACCEPT])
-                    .allowed_header(CONTENT_TYPE)
-                    .max_age(3600),
+                actix_web::middleware::Logger::new(config::logging::ACCESS_LOG_FORMAT)
+                    .custom_request_replace("client_ip", |req| {
+                        config::proxy::client_ip(req.request())
+                            .map_or_else(|| "-".to_string(), |ip| ip.to_string())
+                    }),
             )
+            .wrap(config::cors::build_cors(&allowed_origins))
             .app_data(web::Data::new(pool.clone()))
             .app_data(web::Data::new(chat_server_handle.clone()))
+            .app_data(web::Data::new(trusted_proxies.clone()))
+            .app_data(config::json::json_config(json_body_limit))
+            .configure(|cfg| routes(cfg, guest_read_access))
+    });
+
+    let server = server
+        .workers(http_settings.workers)
+        .keep_alive(http_settings.keep_alive());
+
+    let server = match &bind_target {
+        BindTarget::Tcp(address) => server.bind(address)?,
+        #[cfg(unix)]
+        BindTarget::Uds(path) => {
+            // a socket left behind by an unclean shutdown would make the bind fail
+            if path.exists() {
+                log::warn!("Removing stale socket {}", path.display());
+                std::fs::remove_file(path)?;
+            }
+            server.bind_uds(path)?
+        }
+        #[cfg(not(unix))]
+        BindTarget::Uds(_) => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "BIND_UDS is only supported on Unix",
+            ));
+        }
+    };
+
+    let result = server.run().await;
+
+    // messages already accepted from a socket may still be on their way to the database
+    let unfinished = drain_handle
+        .drain_inserts(config::shutdown_drain_timeout())
+        .await;
+    if unfinished > 0 {
+        log::warn!(
+            "Shutting down with {} message inserts unfinished",
+            unfinished
+        );
+    }
+
+    if let BindTarget::Uds(path) = &bind_target {
+        if let Err(e) = std::fs::remove_file(path) {
+            log::warn!("Failed to remove socket {}: {}", path.display(), e);
+        }
+    }
+
+    result
+}
+
+/// Every route of the API; guest routes are only registered when `guest_read_access` is on.
+fn routes(cfg: &mut web::ServiceConfig, guest_read_access: bool) {
+    cfg.service(
+        // public
+        web::scope("/api/auth")
+            .route("/login", web::post().to(handlers::auth::login))
+            .route("/register", web::post().to(handlers::auth::register))
+            .route("/verify", web::get().to(handlers::auth::verify_email))
             .service(
-                // public
-                web::scope("/api/auth")
-                    .route("/login", web::post().to(handlers::auth::login))
-                    .route("/register", web::post().to(handlers::auth::register)),
+                web::resource("/send-verification")
+                    .wrap(HttpAuthentication::bearer(middleware::auth::jwt_validator))
+                    .route(web::post().to(handlers::auth::send_verification)),
             )
             .service(
-                // private
-                web::scope("/api")
-                    .wrap(auth)
-                    .route("/channels", web::get().to(handlers::channel::list_channels))
-                    .route(
-                        "/channels",
-                        web::post().to(handlers::channel::create_channel),
-                    )
-                    .route(
-                        "/channels/{id}",
-                        web::get().to(handlers::channel::get_channel),
-                    )
-                    .route(
-                        "/channels/{id}/invite",
-                        web::post().to(handlers::invitation::invite_user),
-                    )
-                    .route(
-                        "/channels/{id}/messages",
-                        web::get().to(handlers::channel::get_messages),
-                    )
-                    .route(
-                        "/invitations",
-                        web::get().to(handlers::invitation::list_invitations),
-                    )
-                    .route(
-                        "/invitations/{id}/respond",
-                        web::post().to(handlers::invitation::respond_to_invitation),
-                    ),
-            )
-            .route(
-                "/ws/{channel_id}",
-                web::get().to(handlers::websocket::websocket_handler),
+                web::resource("/revoke-all")
+                    .wrap(HttpAuthentication::bearer(middleware::auth::jwt_validator))
+                    .route(web::post().to(handlers::auth::revoke_all)),
             )
+            .service(
+                web::scope("/2fa")
+                    .wrap(HttpAuthentication::bearer(middleware::auth::jwt_validator))
+                    .route("/setup", web::post().to(handlers::auth::setup_totp))
+                    .route("/verify", web::post().to(handlers::auth::verify_totp)),
+            ),
+    )
+    .configure(|cfg| {
+        // guests, registered ahead of the private scope so tokenless reads land here;
+        // GET only, so a tokenless write still gets the private scope's 401
+        if guest_read_access {
+            cfg.service(
+                web::resource("/api/channels/public")
+                    .guard(guard::Get())
+                    .guard(guard::fn_guard(middleware::auth::is_anonymous))
+                    .route(web::get().to(handlers::channel::list_public_channels)),
+            )
+            .service(
+                web::resource("/api/channels/{id}/messages")
+                    .guard(guard::Get())
+                    .guard(guard::fn_guard(middleware::auth::is_anonymous))
+                    .route(web::get().to(handlers::channel::get_public_messages)),
+            );
+        }
     })
-    .bind(&address)?
-    .run()
-    .await
+    .service(
+        // private
+        web::scope("/api")
+            .wrap(HttpAuthentication::bearer(middleware::auth::jwt_validator))
+            .route("/channels", web::get().to(handlers::channel::list_channels))
+            .route(
+                "/channels/public",
+                web::get().to(handlers::channel::list_public_channels),
+            )
+            .route(
+                "/channels",
+                web::post().to(handlers::channel::create_channel),
+            )
+            .route(
+                "/channels/{id}",
+                web::get().to(handlers::channel::get_channel),
+            )
+            .route(
+                "/channels/{id}",
+                web::patch().to(handlers::channel::update_channel),
+            )
+            .route(
+                "/channels/{id}",
+                web::delete().to(handlers::channel::delete_channel),
+            )
+            .route(
+                "/channels/{id}/join",
+                web::post().to(handlers::channel::join_channel),
+            )
+            .route(
+                "/channels/{id}/invite",
+                web::post().to(handlers::invitation::invite_user),
+            )
+            .route(
+                "/channels/{id}/invitations",
+                web::get().to(handlers::invitation::list_channel_invitations),
+            )
+            .route(
+                "/channels/{id}/invitable",
+                web::get().to(handlers::invitation::list_invitable_users),
+            )
+            .route(
+                "/channels/{id}/invite-bulk",
+                web::post().to(handlers::invitation::invite_users_bulk),
+            )
+            .route(
+                "/channels/{id}/archive",
+                web::post().to(handlers::channel::archive_channel),
+            )
+            .route(
+                "/channels/{id}/unarchive",
+                web::post().to(handlers::channel::unarchive_channel),
+            )
+            .route(
+                "/channels/{id}/members/summary",
+                web::get().to(handlers::channel::get_members_summary),
+            )
+            .route(
+                "/channels/{id}/my-role",
+                web::get().to(handlers::channel::get_my_role),
+            )
+            .route(
+                "/channels/{id}/preferences",
+                web::put().to(handlers::channel::update_channel_preferences),
+            )
+            // before `{user_id}`, which would otherwise swallow "roles"
+            .route(
+                "/channels/{id}/members/roles",
+                web::patch().to(handlers::channel::update_member_roles),
+            )
+            .route(
+                "/channels/{id}/members/{user_id}",
+                web::patch().to(handlers::channel::update_member_role),
+            )
+            .route(
+                "/channels/{id}/export",
+                web::get().to(handlers::channel::export_messages),
+            )
+            .route(
+                "/channels/{id}/stats",
+                web::get().to(handlers::channel::get_channel_stats),
+            )
+            .route(
+                "/channels/{id}/messages",
+                web::get().to(handlers::channel::get_messages),
+            )
+            .route(
+                "/channels/{id}/messages",
+                web::post().to(handlers::channel::post_message),
+            )
+            .route(
+                "/channels/{id}/messages/{message_id}",
+                web::get().to(handlers::channel::get_message),
+            )
+            .route(
+                "/channels/{id}/messages/{message_id}",
+                web::patch().to(handlers::channel::edit_message),
+            )
+            .route(
+                "/channels/{id}/messages/{message_id}",
+                web::delete().to(handlers::channel::delete_message),
+            )
+            .route(
+                "/channels/{id}/messages/{message_id}/pin",
+                web::put().to(handlers::channel::pin_message),
+            )
+            .route(
+                "/channels/{id}/messages/{message_id}/pin",
+                web::delete().to(handlers::channel::unpin_message),
+            )
+            .route(
+                "/channels/{id}/pins",
+                web::get().to(handlers::channel::list_pins),
+            )
+            .route(
+                "/channels/{id}/messages/{message_id}/history",
+                web::get().to(handlers::channel::get_message_history),
+            )
+            .route(
+                "/invitations",
+                web::get().to(handlers::invitation::list_invitations),
+            )
+            .route(
+                "/invitations/count",
+                web::get().to(handlers::invitation::count_invitations),
+            )
+            .route(
+                "/invitations/sent/summary",
+                web::get().to(handlers::invitation::sent_invitations_summary),
+            )
+            .route(
+                "/invitations/read-all",
+                web::post().to(handlers::invitation::mark_invitations_seen),
+            )
+            .route(
+                "/invitations/{id}/resend",
+                web::post().to(handlers::invitation::resend_invitation),
+            )
+            .route(
+                "/invitations/{id}/respond",
+                web::post().to(handlers::invitation::respond_to_invitation),
+            )
+            .route("/me", web::patch().to(handlers::user::update_me))
+            .route(
+                "/me/username",
+                web::patch().to(handlers::user::update_username),
+            )
+            .route("/me", web::delete().to(handlers::user::delete_me))
+            .route("/me/leave-all", web::post().to(handlers::user::leave_all))
+            .route("/users/search", web::get().to(handlers::user::search_users))
+            .route(
+                "/users/{id}/presence",
+                web::get().to(handlers::user::get_presence),
+            ),
+    )
+    .route(
+        "/ws/{channel_id}",
+        web::get().to(handlers::websocket::websocket_handler),
+    )
+    .route("/metrics", web::get().to(handlers::metrics::metrics));
 }
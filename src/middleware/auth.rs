@@ -1,21 +1,147 @@
-use std::env;
+use std::future::{ready, Ready};
 
-use actix_web::{dev::ServiceRequest, error::ErrorUnauthorized, Error, HttpMessage};
+use actix_web::{
+    dev::{Payload, ServiceRequest},
+    error::{ErrorInternalServerError, ErrorUnauthorized},
+    guard::GuardContext,
+    http::header::AUTHORIZATION,
+    web, Error, FromRequest, HttpMessage, HttpRequest,
+};
 use actix_web_httpauth::extractors::bearer::BearerAuth;
+use sqlx::PgPool;
+use uuid::Uuid;
 
-use crate::utils;
+use crate::utils::{self, jwt::Claims};
+
+/// The user behind the bearer token, as validated by `jwt_validator`.
+///
+/// Only usable on routes wrapped by the auth middleware; elsewhere it rejects with 401.
+#[derive(Debug, Clone)]
+pub struct AuthUser {
+    pub id: Uuid,
+    pub username: String,
+}
+
+impl FromRequest for AuthUser {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let user = req
+            .extensions()
+            .get::<Claims>()
+            .ok_or_else(|| ErrorUnauthorized("No claims found"))
+            .and_then(|claims| {
+                let id = Uuid::parse_str(&claims.sub)
+                    .map_err(|_| ErrorUnauthorized("Invalid user id"))?;
+                Ok(Self {
+                    id,
+                    username: claims.username.clone(),
+                })
+            });
+
+        ready(user)
+    }
+}
+
+/// Route guard for guest endpoints: only requests without an `Authorization` header match,
+/// so anyone sending a token still goes through `jwt_validator`.
+pub fn is_anonymous(ctx: &GuardContext) -> bool {
+    !ctx.head().headers().contains_key(AUTHORIZATION)
+}
 
 pub async fn jwt_validator(
     req: ServiceRequest,
     credentials: BearerAuth,
 ) -> Result<ServiceRequest, (Error, ServiceRequest)> {
-    let secret = env::var("JWT_SECRET").unwrap_or_else(|_| "secret".to_string());
+    let claims = match utils::jwt::decode_jwt(credentials.token()) {
+        Ok(claims) => claims,
+        Err(e) => return Err((ErrorUnauthorized(e.to_string()), req)),
+    };
+
+    let Ok(user_id) = Uuid::parse_str(&claims.sub) else {
+        return Err((ErrorUnauthorized("Invalid token"), req));
+    };
+
+    let Some(pool) = req.app_data::<web::Data<PgPool>>().cloned() else {
+        return Err((ErrorInternalServerError("Database unavailable"), req));
+    };
+
+    // tokens issued before an account was deleted, or before a revoke-all, must stop working
+    let user = sqlx::query_as::<_, (i32, String)>(
+        r#"
+        SELECT token_version, username FROM users
+        WHERE id = $1 AND deleted_at IS NULL
+        "#,
+    )
+    .bind(user_id)
+    .fetch_optional(pool.get_ref())
+    .await;
 
-    match utils::jwt::decode_jwt(credentials.token(), secret) {
-        Ok(claims) => {
-            req.extensions_mut().insert(claims);
+    match user {
+        // a rename doesn't revoke tokens, so the claim is replaced by the current username
+        Ok(Some((version, username))) if version == claims.ver => {
+            req.extensions_mut().insert(Claims { username, ..claims });
             Ok(req)
         }
-        Err(_) => Err((ErrorUnauthorized("Invalid token"), req)),
+        Ok(Some(_)) => Err((ErrorUnauthorized("Token revoked"), req)),
+        Ok(None) => Err((ErrorUnauthorized("Invalid token"), req)),
+        Err(_) => Err((ErrorInternalServerError("Database error"), req)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{app, create_user, start_server};
+    use actix_web::{http::StatusCode, test};
+
+    #[actix_web::test]
+    async fn claims_yield_the_user() {
+        let id = Uuid::new_v4();
+        let req = test::TestRequest::default().to_http_request();
+        req.extensions_mut().insert(Claims {
+            sub: id.to_string(),
+            username: "alice".to_string(),
+            exp: 0,
+            ver: 0,
+        });
+
+        let user = AuthUser::extract(&req).await.unwrap();
+        assert_eq!((user.id, user.username.as_str()), (id, "alice"));
+    }
+
+    #[actix_web::test]
+    async fn missing_claims_are_unauthorized() {
+        let req = test::TestRequest::default().to_http_request();
+        let err = AuthUser::extract(&req).await.unwrap_err();
+        assert_eq!(
+            err.as_response_error().status_code(),
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[sqlx::test]
+    async fn routes_need_a_valid_bearer_token(pool: PgPool) {
+        let server = start_server(&pool);
+        let app = test::init_service(app(&pool, &server)).await;
+        let alice = create_user(&pool, "alice").await;
+
+        for header in [None, Some("Bearer not-a-token".to_string())] {
+            let mut req = test::TestRequest::get().uri("/api/channels");
+            if let Some(header) = header {
+                req = req.insert_header((AUTHORIZATION, header));
+            }
+            assert_eq!(
+                test::call_service(&app, req.to_request()).await.status(),
+                StatusCode::UNAUTHORIZED
+            );
+        }
+
+        let req = test::TestRequest::get()
+            .uri("/api/channels")
+            .insert_header(alice.bearer())
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
     }
 }
@@ -1,43 +1,300 @@
-use chrono::{DateTime, Utc};
+use crate::models::role::Role;
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
-use sqlx::prelude::FromRow;
+use sqlx::{
+    encode::IsNull,
+    error::BoxDynError,
+    postgres::{PgArgumentBuffer, PgTypeInfo, PgValueRef},
+    prelude::FromRow,
+    Decode, Encode, Postgres, Type,
+};
+use std::{fmt, str::FromStr};
 use uuid::Uuid;
 
+/// Who may invite people to a channel, stored as text in `channels.invite_policy`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InvitePolicy {
+    #[default]
+    AdminsOnly,
+    /// Admins and members; viewers never invite.
+    Members,
+}
+
+impl InvitePolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InvitePolicy::AdminsOnly => "admins_only",
+            InvitePolicy::Members => "members",
+        }
+    }
+
+    pub fn allows(&self, role: Role) -> bool {
+        match self {
+            InvitePolicy::AdminsOnly => role.is_admin(),
+            InvitePolicy::Members => role.can_post(),
+        }
+    }
+}
+
+impl fmt::Display for InvitePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownInvitePolicy(pub String);
+
+impl fmt::Display for UnknownInvitePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown invite policy: {}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownInvitePolicy {}
+
+impl FromStr for InvitePolicy {
+    type Err = UnknownInvitePolicy;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "admins_only" => Ok(InvitePolicy::AdminsOnly),
+            "members" => Ok(InvitePolicy::Members),
+            other => Err(UnknownInvitePolicy(other.to_string())),
+        }
+    }
+}
+
+impl Type<Postgres> for InvitePolicy {
+    fn type_info() -> PgTypeInfo {
+        <String as Type<Postgres>>::type_info()
+    }
+
+    fn compatible(ty: &PgTypeInfo) -> bool {
+        <String as Type<Postgres>>::compatible(ty)
+    }
+}
+
+// like roles, an unexpected value falls back to the stricter setting
+impl<'r> Decode<'r, Postgres> for InvitePolicy {
+    fn decode(value: PgValueRef<'r>) -> Result<Self, BoxDynError> {
+        let raw = <&str as Decode<Postgres>>::decode(value)?;
+        Ok(raw.parse().unwrap_or_else(|e| {
+            log::warn!("{}, treating it as admins_only", e);
+            InvitePolicy::AdminsOnly
+        }))
+    }
+}
+
+impl Encode<'_, Postgres> for InvitePolicy {
+    fn encode_by_ref(&self, buf: &mut PgArgumentBuffer) -> Result<IsNull, BoxDynError> {
+        <&str as Encode<Postgres>>::encode_by_ref(&self.as_str(), buf)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct Channel {
     pub id: Uuid,
     pub name: String,
+    pub description: Option<String>,
+    pub topic: Option<String>,
     pub created_by: Uuid,
     pub created_at: DateTime<Utc>,
+    pub archived_at: Option<DateTime<Utc>>,
+    pub retention_days: Option<i32>,
+    pub is_public: bool,
+    pub max_members: Option<i32>,
+    pub slow_mode_seconds: Option<i32>,
+    pub invite_policy: InvitePolicy,
 }
 
 #[derive(Debug, Serialize, FromRow)]
 pub struct ChannelResponse {
     pub id: Uuid,
     pub name: String,
+    pub description: Option<String>,
+    pub topic: Option<String>,
     pub created_by: Uuid,
     pub created_at: DateTime<Utc>,
-    pub role: String,
+    pub archived_at: Option<DateTime<Utc>>,
+    pub is_public: bool,
+    pub role: Role,
+    /// The caller muted notifications for this channel; messages still arrive.
+    pub muted: bool,
+    #[sqlx(json(nullable))]
+    pub last_message: Option<LastMessagePreview>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LastMessagePreview {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub username: String,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListChannelsQuery {
+    #[serde(default)]
+    pub include_archived: bool,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct CreateChannelRequest {
     pub name: String,
+    pub description: Option<String>,
+    pub topic: Option<String>,
+    #[serde(default)]
+    pub is_public: bool,
+    /// Defaults to admin.
+    pub creator_role: Option<Role>,
+    /// Made an admin alongside the creator; required when `creator_role` isn't admin.
+    pub admin_id: Option<Uuid>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateChannelRequest {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub topic: Option<String>,
+    pub retention_days: Option<i32>,
+    pub is_public: Option<bool>,
+    pub max_members: Option<i32>,
+    /// 0 turns slow mode off.
+    pub slow_mode_seconds: Option<i32>,
+    pub invite_policy: Option<InvitePolicy>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct ChannelWithMembers {
     pub id: Uuid,
     pub name: String,
+    pub description: Option<String>,
+    pub topic: Option<String>,
     pub created_by: Uuid,
     pub created_at: DateTime<Utc>,
+    pub archived_at: Option<DateTime<Utc>>,
+    pub retention_days: Option<i32>,
+    pub is_public: bool,
+    pub max_members: Option<i32>,
+    pub slow_mode_seconds: Option<i32>,
+    pub invite_policy: InvitePolicy,
     pub members: Vec<ChannelMemberInfo>,
 }
 
+/// What guests and non-members get to see of a public channel.
+#[derive(Debug, Serialize, FromRow)]
+pub struct PublicChannelResponse {
+    pub id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub topic: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateMemberRoleRequest {
+    pub role: Role,
+}
+
+/// Also one entry of the `PATCH /api/channels/{id}/members/roles` body.
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct MemberRole {
+    pub user_id: Uuid,
+    pub role: Role,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateChannelPreferencesRequest {
+    pub muted: bool,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct ChannelPreferences {
+    pub channel_id: Uuid,
+    pub muted: bool,
+}
+
 #[derive(Debug, Serialize, FromRow)]
 pub struct ChannelMemberInfo {
     pub user_id: Uuid,
     pub username: String,
-    pub role: String,
+    pub avatar_url: Option<String>,
+    pub role: Role,
     pub is_online: bool,
 }
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct RoleCount {
+    pub role: Role,
+    pub count: i64,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct MemberSummaryEntry {
+    pub user_id: Uuid,
+    pub username: String,
+    pub avatar_url: Option<String>,
+    pub role: Role,
+    pub joined_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MembersSummaryResponse {
+    pub channel_id: Uuid,
+    pub total: i64,
+    pub roles: Vec<RoleCount>,
+    pub members: Vec<MemberSummaryEntry>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct ActiveMember {
+    pub user_id: Uuid,
+    pub username: String,
+    pub message_count: i64,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct DailyMessageCount {
+    pub day: NaiveDate,
+    pub count: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChannelStatsResponse {
+    pub channel_id: Uuid,
+    pub since: DateTime<Utc>,
+    pub member_count: i64,
+    pub message_count: i64,
+    pub most_active: Vec<ActiveMember>,
+    pub messages_per_day: Vec<DailyMessageCount>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn admins_only_policy_lets_only_admins_invite() {
+        assert!(InvitePolicy::AdminsOnly.allows(Role::Admin));
+        assert!(!InvitePolicy::AdminsOnly.allows(Role::Member));
+        assert!(!InvitePolicy::AdminsOnly.allows(Role::Viewer));
+    }
+
+    #[test]
+    fn members_policy_never_lets_viewers_invite() {
+        assert!(InvitePolicy::Members.allows(Role::Admin));
+        assert!(InvitePolicy::Members.allows(Role::Member));
+        assert!(!InvitePolicy::Members.allows(Role::Viewer));
+    }
+
+    #[test]
+    fn invite_policy_parses_its_own_names() {
+        for policy in [InvitePolicy::AdminsOnly, InvitePolicy::Members] {
+            assert_eq!(policy.as_str().parse::<InvitePolicy>(), Ok(policy));
+        }
+        assert!("everyone".parse::<InvitePolicy>().is_err());
+    }
+}
@@ -0,0 +1,24 @@
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    pub error: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+}
+
+impl ErrorResponse {
+    pub fn new(error: impl Into<String>) -> Self {
+        Self {
+            error: error.into(),
+            field: None,
+        }
+    }
+
+    pub fn for_field(field: impl Into<String>, error: impl Into<String>) -> Self {
+        Self {
+            error: error.into(),
+            field: Some(field.into()),
+        }
+    }
+}
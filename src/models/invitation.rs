@@ -1,25 +1,194 @@
+use crate::models::pagination::non_negative_limit;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::prelude::FromRow;
+use std::fmt;
 use uuid::Uuid;
 
+/// Lifecycle of an invitation, stored as text in `invitations.status`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "varchar", rename_all = "lowercase")]
+pub enum InvitationStatus {
+    Pending,
+    Accepted,
+    Rejected,
+}
+
+impl InvitationStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InvitationStatus::Pending => "pending",
+            InvitationStatus::Accepted => "accepted",
+            InvitationStatus::Rejected => "rejected",
+        }
+    }
+}
+
+impl fmt::Display for InvitationStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct InviteByEmailRequest {
     pub email: String,
 }
 
-#[derive(Debug, Serialize, FromRow)]
+#[derive(Debug, Deserialize)]
+pub struct BulkInviteRequest {
+    pub emails: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkInviteStatus {
+    Invited,
+    AlreadyMember,
+    NotFound,
+    AlreadyInvited,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BulkInviteResult {
+    pub email: String,
+    pub status: BulkInviteStatus,
+    pub invitation_id: Option<Uuid>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct InvitationResponse {
     pub id: Uuid,
     pub channel_id: Uuid,
     pub channel_name: String,
     pub inviter_id: Uuid,
     pub inviter_username: String,
-    pub status: String,
+    pub invitee_id: Uuid,
+    pub invitee_username: String,
+    pub status: InvitationStatus,
     pub created_at: DateTime<Utc>,
+    pub seen_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InvitableQuery {
+    pub query: String,
+    #[serde(default, deserialize_with = "non_negative_limit")]
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct InvitableUser {
+    pub id: Uuid,
+    pub username: String,
+    pub avatar_url: Option<String>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct InvitationCountResponse {
+    pub pending: i64,
+    /// Pending invitations not yet cleared by `POST /api/invitations/read-all`.
+    pub unseen: i64,
+}
+
+/// How the invitations the caller sent have fared.
+#[derive(Debug, Serialize, FromRow)]
+pub struct SentInvitationSummary {
+    pub total: i64,
+    pub pending: i64,
+    pub accepted: i64,
+    pub rejected: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MarkInvitationsSeenResponse {
+    pub updated: u64,
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InvitationStatusFilter {
+    #[default]
+    Pending,
+    Accepted,
+    Rejected,
+    All,
+}
+
+impl InvitationStatusFilter {
+    /// The status to match, `None` for every status.
+    pub fn status(self) -> Option<InvitationStatus> {
+        match self {
+            Self::Pending => Some(InvitationStatus::Pending),
+            Self::Accepted => Some(InvitationStatus::Accepted),
+            Self::Rejected => Some(InvitationStatus::Rejected),
+            Self::All => None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InvitationDirection {
+    #[default]
+    Received,
+    Sent,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListInvitationsQuery {
+    #[serde(default)]
+    pub status: InvitationStatusFilter,
+    #[serde(default)]
+    pub direction: InvitationDirection,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChannelInvitationsQuery {
+    #[serde(default)]
+    pub status: InvitationStatusFilter,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct RespondToInvitationRequest {
     pub accept: bool,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::PgPool;
+
+    const ALL: [InvitationStatus; 3] = [
+        InvitationStatus::Pending,
+        InvitationStatus::Accepted,
+        InvitationStatus::Rejected,
+    ];
+
+    #[test]
+    fn serializes_as_lowercase_text() {
+        for status in ALL {
+            let json = serde_json::to_value(status).unwrap();
+            assert_eq!(json, status.as_str());
+            assert_eq!(
+                serde_json::from_value::<InvitationStatus>(json).unwrap(),
+                status
+            );
+        }
+        assert!(serde_json::from_str::<InvitationStatus>("\"Pending\"").is_err());
+    }
+
+    #[sqlx::test]
+    async fn round_trips_through_postgres(pool: PgPool) {
+        for status in ALL {
+            let (decoded, raw): (InvitationStatus, String) = sqlx::query_as("SELECT $1, $1::text")
+                .bind(status)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+            assert_eq!(decoded, status);
+            assert_eq!(raw, status.as_str());
+        }
+    }
+}
@@ -1,8 +1,48 @@
+use crate::models::invitation::InvitationResponse;
+use crate::models::role::Role;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::prelude::FromRow;
 use uuid::Uuid;
 
+/// Whether a message was written by a user or recorded by the server for an event.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "varchar", rename_all = "lowercase")]
+pub enum MessageKind {
+    #[default]
+    User,
+    System,
+}
+
+impl MessageKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MessageKind::User => "user",
+            MessageKind::System => "system",
+        }
+    }
+}
+
+/// How clients should render a message's content. The server only stores the hint.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "varchar", rename_all = "lowercase")]
+pub enum MessageFormat {
+    #[default]
+    Plain,
+    Markdown,
+}
+
+impl MessageFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MessageFormat::Plain => "plain",
+            MessageFormat::Markdown => "markdown",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct Message {
     pub id: Uuid,
@@ -10,9 +50,21 @@ pub struct Message {
     pub user_id: Uuid,
     pub content: String,
     pub created_at: DateTime<Utc>,
+    pub client_sent_at: Option<DateTime<Utc>>,
+    pub edited_at: Option<DateTime<Utc>>,
+    pub version: i32,
+    pub kind: MessageKind,
+    pub format: MessageFormat,
 }
 
-#[derive(Debug, Serialize, FromRow)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attachment {
+    pub url: String,
+    pub mime_type: String,
+    pub size: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct MessageResponse {
     pub id: Uuid,
     pub channel_id: Uuid,
@@ -20,6 +72,159 @@ pub struct MessageResponse {
     pub username: String,
     pub content: String,
     pub created_at: DateTime<Utc>,
+    pub client_sent_at: Option<DateTime<Utc>>,
+    pub edited_at: Option<DateTime<Utc>>,
+    pub version: i32,
+    pub kind: MessageKind,
+    pub format: MessageFormat,
+    #[sqlx(json)]
+    pub attachments: Vec<Attachment>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+/// One message in a channel export, oldest first.
+#[derive(Debug, Serialize, FromRow)]
+pub struct ExportedMessage {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub username: String,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+    pub edited_at: Option<DateTime<Utc>>,
+    pub kind: MessageKind,
+}
+
+impl ExportedMessage {
+    pub const CSV_HEADER: &'static str =
+        "id,user_id,username,content,created_at,edited_at,kind\r\n";
+
+    pub fn to_csv_row(&self) -> String {
+        let fields = [
+            self.id.to_string(),
+            self.user_id.to_string(),
+            self.username.clone(),
+            self.content.clone(),
+            self.created_at.to_rfc3339(),
+            self.edited_at
+                .map(|edited_at| edited_at.to_rfc3339())
+                .unwrap_or_default(),
+            self.kind.as_str().to_string(),
+        ];
+
+        let mut row = fields
+            .iter()
+            .map(|field| csv_field(field))
+            .collect::<Vec<_>>()
+            .join(",");
+        row.push_str("\r\n");
+        row
+    }
+}
+
+/// Quotes a CSV field when it contains a separator, quote or line break (RFC 4180).
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// A pinned message with who pinned it and when.
+#[derive(Debug, Serialize, FromRow)]
+pub struct PinnedMessage {
+    pub message_id: Uuid,
+    pub user_id: Uuid,
+    pub username: String,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+    pub pinned_by: Uuid,
+    pub pinned_at: DateTime<Utc>,
+}
+
+/// Body of `POST /api/channels/{id}/messages`, the same fields as a `send_message` frame.
+#[derive(Debug, Deserialize)]
+pub struct PostMessageRequest {
+    pub content: String,
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+    #[serde(default)]
+    pub client_sent_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub format: MessageFormat,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EditMessageRequest {
+    pub content: String,
+    /// The `version` the client last saw; a mismatch means someone else edited first.
+    pub expected_version: i32,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct MessageEditResponse {
+    pub id: Uuid,
+    pub message_id: Uuid,
+    pub editor_id: Uuid,
+    pub editor_username: String,
+    pub action: String,
+    pub old_content: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TypingUser {
+    pub user_id: Uuid,
+    pub username: String,
+    /// The thread's root message when typing a reply, `None` in the channel itself.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<Uuid>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OnlineUser {
+    pub user_id: Uuid,
+    pub username: String,
+}
+
+/// The `code` of a WebSocket `error` frame; clients can branch on it, `message` is for humans.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WsErrorCode {
+    /// The message content is over the length limit.
+    TooLong,
+    /// The session's role doesn't allow the action, e.g. a viewer posting.
+    Forbidden,
+    /// The user is no longer a member of the channel.
+    NotMember,
+    /// The frame isn't valid JSON, isn't a known `ClientMessage`, or carries invalid data
+    /// such as a disallowed attachment.
+    BadPayload,
+    /// Writes are refused while the channel is archived.
+    ChannelArchived,
+    /// The word filter rejected the message.
+    BlockedContent,
+    /// The user has too many open sockets; this one is being closed.
+    TooManyConnections,
+    /// The channel is in slow mode and the user posted too recently.
+    SlowMode,
+    /// The resume token is unknown or has expired; authenticate with a token instead.
+    ResumeExpired,
+    /// Something failed on the server; retrying may help.
+    Internal,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -32,22 +237,100 @@ pub enum WsMessage {
         username: String,
         content: String,
         created_at: DateTime<Utc>,
+        client_sent_at: Option<DateTime<Utc>>,
+        attachments: Vec<Attachment>,
+        #[serde(default)]
+        kind: MessageKind,
+        #[serde(default)]
+        format: MessageFormat,
     },
     #[serde(rename = "typing")]
     TypingIndicator {
         user_id: Uuid,
         username: String,
         is_typing: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        parent_id: Option<Uuid>,
+    },
+    #[serde(rename = "typing_users")]
+    TypingUsers {
+        channel_id: Uuid,
+        users: Vec<TypingUser>,
     },
     #[serde(rename = "user_joined")]
-    UserJoined { user_id: Uuid, username: String },
+    UserJoined {
+        user_id: Uuid,
+        username: String,
+        avatar_url: Option<String>,
+    },
+    #[serde(rename = "online_snapshot")]
+    OnlineSnapshot {
+        users: Vec<OnlineUser>,
+        /// Distinct users online in the channel, across all pages.
+        total: usize,
+        offset: usize,
+        has_more: bool,
+    },
+    /// Sent once per connection; lets the client `resume` for `ttl_secs` after a drop.
+    #[serde(rename = "resume_token")]
+    ResumeToken { token: String, ttl_secs: u64 },
     #[serde(rename = "user_left")]
     UserLeft { user_id: Uuid, username: String },
+    #[serde(rename = "member_added")]
+    MemberAdded { user_id: Uuid, username: String },
+    /// Sent to every socket of the invitee, whichever channel it's on.
+    #[serde(rename = "invitation_received")]
+    InvitationReceived { invitation: InvitationResponse },
+    #[serde(rename = "channel_updated")]
+    ChannelUpdated {
+        channel_id: Uuid,
+        name: String,
+        description: Option<String>,
+        topic: Option<String>,
+    },
+    #[serde(rename = "role_changed")]
+    RoleChanged {
+        channel_id: Uuid,
+        user_id: Uuid,
+        role: Role,
+    },
+    #[serde(rename = "channel_deleted")]
+    ChannelDeleted { channel_id: Uuid },
+    #[serde(rename = "error")]
+    Error {
+        code: WsErrorCode,
+        message: String,
+        /// Seconds until the action is allowed again, set for `slow_mode`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        retry_after: Option<u64>,
+    },
+    #[serde(rename = "read_receipt")]
+    ReadReceipt {
+        user_id: Uuid,
+        up_to_message_id: Uuid,
+    },
+    #[serde(rename = "message_edited")]
+    MessageEdited {
+        id: Uuid,
+        channel_id: Uuid,
+        content: String,
+        edited_at: DateTime<Utc>,
+        version: i32,
+    },
+    #[serde(rename = "message_deleted")]
+    MessageDeleted { id: Uuid, channel_id: Uuid },
+    #[serde(rename = "history")]
+    History {
+        messages: Vec<MessageResponse>,
+        has_more: bool,
+    },
+    /// A user who shares a channel with the recipient came online or went offline.
     #[serde(rename = "presence")]
     PresenceUpdate {
         user_id: Uuid,
         username: String,
-        is_typing: bool,
+        avatar_url: Option<String>,
+        online: bool,
     },
 }
 
@@ -55,7 +338,43 @@ pub enum WsMessage {
 #[serde(tag = "type")]
 pub enum ClientMessage {
     #[serde(rename = "send_message")]
-    SendMessage { content: String },
+    SendMessage {
+        content: String,
+        #[serde(default)]
+        attachments: Vec<Attachment>,
+        #[serde(default)]
+        client_sent_at: Option<DateTime<Utc>>,
+        #[serde(default)]
+        format: MessageFormat,
+    },
+    /// `parent_id` scopes the indicator to a thread; a user types in one place at a time.
     #[serde(rename = "typing")]
-    Typing { is_typing: bool },
+    Typing {
+        is_typing: bool,
+        #[serde(default)]
+        parent_id: Option<Uuid>,
+    },
+    #[serde(rename = "auth")]
+    Auth { token: String },
+    /// Reattaches to a recently dropped connection, replaying messages after `last_message_id`.
+    #[serde(rename = "resume")]
+    Resume {
+        token: String,
+        #[serde(default)]
+        last_message_id: Option<Uuid>,
+    },
+    #[serde(rename = "mark_read")]
+    MarkRead { up_to_message_id: Uuid },
+    #[serde(rename = "get_typing")]
+    GetTyping,
+    /// Replies with a `history` batch older than `before`; an empty batch means the start
+    /// of the channel was reached.
+    #[serde(rename = "load_more", alias = "load_history")]
+    LoadMore {
+        #[serde(alias = "before_id")]
+        before: Uuid,
+        limit: Option<i64>,
+    },
+    #[serde(rename = "load_online")]
+    LoadOnline { offset: usize },
 }
@@ -1,6 +1,9 @@
 pub mod channel;
+pub mod error;
 pub mod invitation;
 pub mod message;
+pub mod pagination;
+pub mod role;
 pub mod user;
 
 pub use message::*;
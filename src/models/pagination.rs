@@ -0,0 +1,228 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{de, Deserialize, Deserializer, Serialize};
+use uuid::Uuid;
+
+pub const DEFAULT_PAGE_LIMIT: i64 = 50;
+pub const MAX_PAGE_LIMIT: i64 = 100;
+
+/// Rejects a negative `limit` while the query string is parsed, so `web::Query` answers 400.
+///
+/// Use with `#[serde(default, deserialize_with = "non_negative_limit")]`.
+pub fn non_negative_limit<'de, D>(deserializer: D) -> Result<Option<i64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<i64>::deserialize(deserializer)? {
+        Some(limit) if limit < 0 => Err(de::Error::custom("limit must not be negative")),
+        limit => Ok(limit),
+    }
+}
+
+/// The requested `limit`, or `default`, kept within `1..=max`.
+pub fn clamp_limit(limit: Option<i64>, default: i64, max: i64) -> i64 {
+    limit.unwrap_or(default).clamp(1, max)
+}
+
+/// Opt-in paging for list endpoints; without `envelope=true` they keep returning bare arrays.
+#[derive(Debug, Deserialize)]
+pub struct ListQuery {
+    #[serde(default)]
+    pub envelope: bool,
+    #[serde(default, deserialize_with = "non_negative_limit")]
+    pub limit: Option<i64>,
+    pub cursor: Option<String>,
+}
+
+impl ListQuery {
+    pub fn page_limit(&self) -> i64 {
+        clamp_limit(self.limit, DEFAULT_PAGE_LIMIT, MAX_PAGE_LIMIT)
+    }
+
+    /// The cursor as an offset, for endpoints paged by position.
+    pub fn offset(&self) -> Result<i64, actix_web::Error> {
+        match &self.cursor {
+            Some(cursor) => cursor
+                .parse::<i64>()
+                .ok()
+                .filter(|offset| *offset >= 0)
+                .ok_or_else(|| actix_web::error::ErrorBadRequest("Invalid cursor")),
+            None => Ok(0),
+        }
+    }
+
+    /// The cursor as a `(created_at, id)` position, for endpoints paged by keyset.
+    pub fn keyset_cursor(&self) -> Result<Option<KeysetCursor>, actix_web::Error> {
+        self.cursor.as_deref().map(KeysetCursor::decode).transpose()
+    }
+}
+
+/// Which way a keyset cursor pages; `older` walks back in time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    #[default]
+    Older,
+    Newer,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KeysetQuery {
+    #[serde(default)]
+    pub direction: Direction,
+}
+
+/// Position in a `(created_at, id)` ordered list, handed to clients as opaque base64.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeysetCursor {
+    pub created_at: DateTime<Utc>,
+    pub id: Uuid,
+}
+
+impl KeysetCursor {
+    pub fn new(created_at: DateTime<Utc>, id: Uuid) -> Self {
+        Self { created_at, id }
+    }
+
+    // microseconds match Postgres' precision, so the cursor round-trips exactly
+    pub fn encode(&self) -> String {
+        let raw = format!(
+            "{}|{}",
+            self.created_at.to_rfc3339_opts(SecondsFormat::Micros, true),
+            self.id
+        );
+        URL_SAFE_NO_PAD.encode(raw)
+    }
+
+    pub fn decode(value: &str) -> Result<Self, actix_web::Error> {
+        let invalid = || actix_web::error::ErrorBadRequest("Invalid cursor");
+
+        let raw = URL_SAFE_NO_PAD.decode(value).map_err(|_| invalid())?;
+        let raw = String::from_utf8(raw).map_err(|_| invalid())?;
+        let (created_at, id) = raw.split_once('|').ok_or_else(invalid)?;
+
+        Ok(Self {
+            created_at: DateTime::parse_from_rfc3339(created_at)
+                .map_err(|_| invalid())?
+                .with_timezone(&Utc),
+            id: Uuid::parse_str(id).map_err(|_| invalid())?,
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PageInfo {
+    pub next_cursor: Option<String>,
+    pub has_more: bool,
+    /// Keyset pages only: a cursor for paging back the opposite way.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prev_cursor: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Page<T> {
+    pub data: Vec<T>,
+    pub page_info: PageInfo,
+}
+
+impl<T> Page<T> {
+    /// Builds a page from rows fetched with `limit + 1`, using the extra row to detect more.
+    pub fn from_overfetch(mut rows: Vec<T>, limit: i64, offset: i64) -> Self {
+        let has_more = rows.len() as i64 > limit;
+        rows.truncate(limit as usize);
+
+        Self {
+            data: rows,
+            page_info: PageInfo {
+                next_cursor: has_more.then(|| (offset + limit).to_string()),
+                has_more,
+                prev_cursor: None,
+            },
+        }
+    }
+
+    pub fn keyset(
+        rows: Vec<T>,
+        next_cursor: Option<KeysetCursor>,
+        prev_cursor: Option<KeysetCursor>,
+    ) -> Self {
+        Self {
+            data: rows,
+            page_info: PageInfo {
+                has_more: next_cursor.is_some(),
+                next_cursor: next_cursor.map(|cursor| cursor.encode()),
+                prev_cursor: prev_cursor.map(|cursor| cursor.encode()),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::web;
+
+    fn list_query(query: &str) -> Result<ListQuery, actix_web::error::QueryPayloadError> {
+        web::Query::<ListQuery>::from_query(query).map(web::Query::into_inner)
+    }
+
+    #[test]
+    fn limit_is_clamped_into_range() {
+        assert_eq!(clamp_limit(None, 20, 50), 20);
+        assert_eq!(clamp_limit(Some(0), 20, 50), 1);
+        assert_eq!(clamp_limit(Some(10), 20, 50), 10);
+        assert_eq!(clamp_limit(Some(500), 20, 50), 50);
+
+        assert_eq!(list_query("").unwrap().page_limit(), DEFAULT_PAGE_LIMIT);
+        assert_eq!(
+            list_query("limit=1000").unwrap().page_limit(),
+            MAX_PAGE_LIMIT
+        );
+    }
+
+    #[test]
+    fn negative_or_non_numeric_limit_is_rejected() {
+        assert!(list_query("limit=-1").is_err());
+        assert!(list_query("limit=ten").is_err());
+    }
+
+    #[test]
+    fn offset_cursor_must_be_a_non_negative_number() {
+        assert_eq!(list_query("").unwrap().offset().unwrap(), 0);
+        assert_eq!(list_query("cursor=40").unwrap().offset().unwrap(), 40);
+        assert!(list_query("cursor=-5").unwrap().offset().is_err());
+        assert!(list_query("cursor=abc").unwrap().offset().is_err());
+    }
+
+    #[test]
+    fn keyset_cursor_round_trips() {
+        let created_at = DateTime::parse_from_rfc3339("2024-01-02T03:04:05.123456Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let cursor = KeysetCursor::new(created_at, Uuid::new_v4());
+
+        let query = list_query(&format!("cursor={}", cursor.encode())).unwrap();
+        assert_eq!(query.keyset_cursor().unwrap(), Some(cursor));
+        assert_eq!(list_query("").unwrap().keyset_cursor().unwrap(), None);
+    }
+
+    #[test]
+    fn malformed_keyset_cursor_is_rejected() {
+        assert!(KeysetCursor::decode("not base64!").is_err());
+        assert!(KeysetCursor::decode(&URL_SAFE_NO_PAD.encode("no separator")).is_err());
+        assert!(KeysetCursor::decode(&URL_SAFE_NO_PAD.encode("yesterday|not-a-uuid")).is_err());
+    }
+
+    #[test]
+    fn overfetch_has_more_only_past_the_limit() {
+        let page = Page::from_overfetch(vec![1, 2], 2, 0);
+        assert_eq!(page.data, [1, 2]);
+        assert!(!page.page_info.has_more);
+        assert_eq!(page.page_info.next_cursor, None);
+
+        let page = Page::from_overfetch(vec![1, 2, 3], 2, 4);
+        assert_eq!(page.data, [1, 2]);
+        assert!(page.page_info.has_more);
+        assert_eq!(page.page_info.next_cursor.as_deref(), Some("6"));
+    }
+}
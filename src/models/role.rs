@@ -0,0 +1,136 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{
+    encode::IsNull,
+    error::BoxDynError,
+    postgres::{PgArgumentBuffer, PgTypeInfo, PgValueRef},
+    Decode, Encode, Postgres, Type,
+};
+use std::{fmt, str::FromStr};
+
+/// A member's role in a channel, stored as text in `channel_members.role`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Admin,
+    Member,
+    /// Can read a channel but not post to it.
+    Viewer,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Admin => "admin",
+            Role::Member => "member",
+            Role::Viewer => "viewer",
+        }
+    }
+
+    pub fn is_admin(&self) -> bool {
+        *self == Role::Admin
+    }
+
+    pub fn can_post(&self) -> bool {
+        *self != Role::Viewer
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownRole(pub String);
+
+impl fmt::Display for UnknownRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown role: {}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownRole {}
+
+impl FromStr for Role {
+    type Err = UnknownRole;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "admin" => Ok(Role::Admin),
+            "member" => Ok(Role::Member),
+            "viewer" => Ok(Role::Viewer),
+            other => Err(UnknownRole(other.to_string())),
+        }
+    }
+}
+
+impl Type<Postgres> for Role {
+    fn type_info() -> PgTypeInfo {
+        <String as Type<Postgres>>::type_info()
+    }
+
+    fn compatible(ty: &PgTypeInfo) -> bool {
+        <String as Type<Postgres>>::compatible(ty)
+    }
+}
+
+// an unexpected value in the column degrades to the least privileged role instead of failing
+// the whole query
+impl<'r> Decode<'r, Postgres> for Role {
+    fn decode(value: PgValueRef<'r>) -> Result<Self, BoxDynError> {
+        let raw = <&str as Decode<Postgres>>::decode(value)?;
+        Ok(raw.parse().unwrap_or_else(|e| {
+            log::warn!("{}, treating it as viewer", e);
+            Role::Viewer
+        }))
+    }
+}
+
+impl Encode<'_, Postgres> for Role {
+    fn encode_by_ref(&self, buf: &mut PgArgumentBuffer) -> Result<IsNull, BoxDynError> {
+        <&str as Encode<Postgres>>::encode_by_ref(&self.as_str(), buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::PgPool;
+
+    const ALL: [Role; 3] = [Role::Admin, Role::Member, Role::Viewer];
+
+    #[test]
+    fn text_form_round_trips() {
+        for role in ALL {
+            assert_eq!(role.to_string().parse::<Role>(), Ok(role));
+            assert_eq!(serde_json::to_value(role).unwrap(), role.as_str());
+        }
+        assert_eq!(
+            "Admin".parse::<Role>(),
+            Err(UnknownRole("Admin".to_string()))
+        );
+    }
+
+    #[sqlx::test]
+    async fn round_trips_through_postgres(pool: PgPool) {
+        for role in ALL {
+            let (decoded, raw): (Role, String) = sqlx::query_as("SELECT $1, $1::text")
+                .bind(role)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+            assert_eq!(decoded, role);
+            assert_eq!(raw, role.as_str());
+        }
+    }
+
+    #[sqlx::test]
+    async fn unknown_stored_value_decodes_as_viewer(pool: PgPool) {
+        let role: Role = sqlx::query_scalar("SELECT 'owner'::varchar")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(role, Role::Viewer);
+    }
+}
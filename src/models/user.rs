@@ -1,16 +1,22 @@
+use crate::models::pagination::non_negative_limit;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::prelude::FromRow;
+use std::fmt;
 use uuid::Uuid;
 
-#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+/// Database row for a user. Never return it from a handler, convert to `UserResponse`.
+#[derive(Clone, Serialize, Deserialize, FromRow)]
 pub struct User {
     pub id: Uuid,
     pub username: String,
     pub email: String,
     #[serde(skip_serializing)]
     pub password_hash: String,
+    pub avatar_url: Option<String>,
     pub created_at: DateTime<Utc>,
+    #[serde(skip_serializing)]
+    pub token_version: i32,
 }
 
 #[derive(Debug, Serialize)]
@@ -18,6 +24,7 @@ pub struct UserResponse {
     pub id: Uuid,
     pub username: String,
     pub email: String,
+    pub avatar_url: Option<String>,
     pub created_at: DateTime<Utc>,
 }
 
@@ -38,6 +45,87 @@ pub struct RegisterRequest {
 pub struct LoginRequest {
     pub email: String,
     pub password: String,
+    pub totp_code: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TotpSetupResponse {
+    pub secret: String,
+    pub otpauth_url: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TotpVerifyRequest {
+    pub code: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SendVerificationResponse {
+    pub expires_at: DateTime<Utc>,
+    /// Only returned in development, where no email is sent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyEmailQuery {
+    pub token: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PresenceResponse {
+    pub user_id: Uuid,
+    pub is_online: bool,
+    pub last_seen_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LeaveAllResponse {
+    pub left: u64,
+    /// Channels removed because the user was their last member.
+    pub deleted_channels: Vec<Uuid>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateUsernameRequest {
+    pub username: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateProfileRequest {
+    /// Omitted leaves the avatar unchanged, `""` removes it.
+    pub avatar_url: Option<String>,
+    /// Whether the user shows up in user search; omitted leaves it unchanged.
+    pub discoverable: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UserSearchQuery {
+    pub q: String,
+    #[serde(default, deserialize_with = "non_negative_limit")]
+    pub limit: Option<i64>,
+}
+
+/// Public fields of a user found through search.
+#[derive(Debug, Serialize, FromRow)]
+pub struct UserSearchResult {
+    pub id: Uuid,
+    pub username: String,
+    pub avatar_url: Option<String>,
+}
+
+// hand-written so the hash can't end up in logs via `{:?}`
+impl fmt::Debug for User {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("User")
+            .field("id", &self.id)
+            .field("username", &self.username)
+            .field("email", &self.email)
+            .field("password_hash", &"<redacted>")
+            .field("avatar_url", &self.avatar_url)
+            .field("created_at", &self.created_at)
+            .finish()
+    }
 }
 
 impl From<User> for UserResponse {
@@ -46,7 +134,45 @@ impl From<User> for UserResponse {
             id: user.id,
             username: user.username,
             email: user.email,
+            avatar_url: user.avatar_url,
             created_at: user.created_at,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "$2b$12$abcdefghijklmnopqrstuu5C0tVdD1dfy0LK8C0mTE0xrTwMLyqSW";
+
+    fn user() -> User {
+        User {
+            id: Uuid::new_v4(),
+            username: "alice".to_string(),
+            email: "alice@example.com".to_string(),
+            password_hash: HASH.to_string(),
+            avatar_url: None,
+            created_at: Utc::now(),
+            token_version: 3,
+        }
+    }
+
+    #[test]
+    fn serializing_a_user_leaves_out_the_password_hash() {
+        let json = serde_json::to_value(user()).unwrap();
+
+        assert!(json.get("password_hash").is_none());
+        assert!(json.get("token_version").is_none());
+        assert_eq!(json["username"], "alice");
+        assert!(!json.to_string().contains(HASH));
+    }
+
+    #[test]
+    fn debug_output_redacts_the_password_hash() {
+        let debug = format!("{:?}", user());
+
+        assert!(!debug.contains(HASH));
+        assert!(debug.contains("<redacted>"));
+    }
+}
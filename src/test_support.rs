@@ -0,0 +1,340 @@
+//! Fixtures for tests that hit the database. Each `#[sqlx::test]` gets its own freshly
+//! migrated database from `DATABASE_URL`, see `.env.example`.
+
+use crate::{
+    config, db,
+    handlers::websocket::{ChatServer, ChatServerHandle},
+    models::role::Role,
+    utils::jwt::create_jwt,
+};
+use actix_web::{
+    dev::{ServiceFactory, ServiceRequest, ServiceResponse},
+    http::header::AUTHORIZATION,
+    web, App, HttpServer,
+};
+use sqlx::PgPool;
+use std::{env, net::SocketAddr, sync::Once, time::Duration};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    sync::mpsc,
+};
+use uuid::Uuid;
+
+/// Password of every user made by `create_user`.
+pub const PASSWORD: &str = "correct horse battery";
+
+/// Settings the tests rely on, applied once before anything reads them.
+///
+/// Feature flags that default to off are switched on here so their paths can be tested.
+pub fn init() {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| {
+        env::set_var("APP_ENV", "test");
+        env::set_var("BCRYPT_COST", "4");
+        env::set_var(
+            "TOTP_ENCRYPTION_KEY",
+            "MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=",
+        );
+        env::set_var("MESSAGE_SANITIZE", "escape");
+        env::set_var("PERSIST_SYSTEM_MESSAGES", "true");
+        env::set_var("UNIQUE_CHANNEL_NAMES", "true");
+    });
+}
+
+pub fn start_server(pool: &PgPool) -> ChatServerHandle {
+    init();
+    let (server, handle) = ChatServer::new(pool.clone());
+    tokio::spawn(server.run());
+    handle
+}
+
+/// The API as `main` serves it, guest routes included, without logging and CORS.
+pub fn app(
+    pool: &PgPool,
+    server: &ChatServerHandle,
+) -> App<
+    impl ServiceFactory<
+        ServiceRequest,
+        Config = (),
+        Response = ServiceResponse,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    init();
+    App::new()
+        .app_data(web::Data::new(pool.clone()))
+        .app_data(web::Data::new(server.clone()))
+        .app_data(web::Data::new(config::proxy::TrustedProxies::default()))
+        .app_data(config::json::json_config(config::json::json_body_limit()))
+        .configure(|cfg| crate::routes(cfg, true))
+}
+
+/// Serves `app` on a loopback port, for tests that need a real socket such as WebSocket ones.
+pub fn serve(pool: &PgPool, server: &ChatServerHandle) -> SocketAddr {
+    init();
+    let (pool, server) = (pool.clone(), server.clone());
+    let http = HttpServer::new(move || app(&pool, &server))
+        .workers(1)
+        .disable_signals()
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+    let addr = http.addrs()[0];
+    tokio::spawn(http.run());
+    addr
+}
+
+/// A bare WebSocket client, just enough to drive the handshake and frames by hand.
+pub struct WsClient {
+    stream: TcpStream,
+    buf: Vec<u8>,
+}
+
+/// A frame as the server sent it; the payload of a close frame starts with its code.
+pub struct WsFrame {
+    pub opcode: u8,
+    pub payload: Vec<u8>,
+}
+
+impl WsClient {
+    pub const TEXT: u8 = 0x1;
+    pub const CLOSE: u8 = 0x8;
+
+    /// Sends the upgrade request for `path` and returns the response head, lowercased.
+    pub async fn connect(addr: SocketAddr, path: &str, headers: &[(&str, &str)]) -> (String, Self) {
+        let mut request = format!(
+            "GET {} HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n",
+            path, addr
+        );
+        for (name, value) in headers {
+            request.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        request.push_str("\r\n");
+
+        let mut client = Self {
+            stream: TcpStream::connect(addr).await.unwrap(),
+            buf: Vec::new(),
+        };
+        client.stream.write_all(request.as_bytes()).await.unwrap();
+
+        let end = loop {
+            if let Some(end) = client.buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                break end + 4;
+            }
+            assert!(
+                client.fill().await,
+                "connection closed during the handshake"
+            );
+        };
+        let head = String::from_utf8_lossy(&client.buf[..end]).to_lowercase();
+        client.buf.drain(..end);
+        (head, client)
+    }
+
+    /// Sends one masked frame, as clients must.
+    pub async fn send(&mut self, opcode: u8, payload: &[u8]) {
+        let mut frame = vec![0x80 | opcode];
+        match payload.len() {
+            len if len < 126 => frame.push(0x80 | len as u8),
+            len if len <= u16::MAX as usize => {
+                frame.push(0x80 | 126);
+                frame.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            len => {
+                frame.push(0x80 | 127);
+                frame.extend_from_slice(&(len as u64).to_be_bytes());
+            }
+        }
+        let mask = [0x12, 0x34, 0x56, 0x78];
+        frame.extend_from_slice(&mask);
+        frame.extend(
+            payload
+                .iter()
+                .enumerate()
+                .map(|(i, byte)| byte ^ mask[i % 4]),
+        );
+        self.stream.write_all(&frame).await.unwrap();
+    }
+
+    pub async fn send_json(&mut self, value: serde_json::Value) {
+        self.send(Self::TEXT, value.to_string().as_bytes()).await;
+    }
+
+    /// The next frame, or `None` once the server has closed the connection.
+    pub async fn recv(&mut self) -> Option<WsFrame> {
+        loop {
+            if let Some((frame, len)) = self.parse() {
+                self.buf.drain(..len);
+                return Some(frame);
+            }
+            if !tokio::time::timeout(Duration::from_secs(10), self.fill())
+                .await
+                .expect("timed out waiting for a frame")
+            {
+                return None;
+            }
+        }
+    }
+
+    /// Skips frames until a text frame of type `kind` arrives.
+    pub async fn next_json(&mut self, kind: &str) -> serde_json::Value {
+        loop {
+            let frame = self.recv().await.expect("connection closed");
+            if frame.opcode != Self::TEXT {
+                continue;
+            }
+            let value: serde_json::Value = serde_json::from_slice(&frame.payload).unwrap();
+            if value["type"] == kind {
+                return value;
+            }
+        }
+    }
+
+    /// Skips frames until the close frame and returns its code.
+    pub async fn close_code(&mut self) -> u16 {
+        loop {
+            let frame = self
+                .recv()
+                .await
+                .expect("connection closed without a close frame");
+            if frame.opcode == Self::CLOSE {
+                return u16::from_be_bytes([frame.payload[0], frame.payload[1]]);
+            }
+        }
+    }
+
+    async fn fill(&mut self) -> bool {
+        let mut chunk = [0; 4096];
+        let read = self.stream.read(&mut chunk).await.unwrap_or(0);
+        self.buf.extend_from_slice(&chunk[..read]);
+        read > 0
+    }
+
+    /// Server frames are never masked.
+    fn parse(&self) -> Option<(WsFrame, usize)> {
+        let buf = &self.buf;
+        if buf.len() < 2 {
+            return None;
+        }
+        let (len, start) = match buf[1] & 0x7f {
+            126 if buf.len() >= 4 => (u16::from_be_bytes([buf[2], buf[3]]) as usize, 4),
+            127 if buf.len() >= 10 => (
+                u64::from_be_bytes(buf[2..10].try_into().unwrap()) as usize,
+                10,
+            ),
+            126 | 127 => return None,
+            len => (len as usize, 2),
+        };
+        if buf.len() < start + len {
+            return None;
+        }
+        let frame = WsFrame {
+            opcode: buf[0] & 0x0f,
+            payload: buf[start..start + len].to_vec(),
+        };
+        Some((frame, start + len))
+    }
+}
+
+/// Skips frames until one of type `kind` arrives.
+pub async fn next_frame(rx: &mut mpsc::Receiver<String>, kind: &str) -> serde_json::Value {
+    loop {
+        let text = tokio::time::timeout(Duration::from_secs(1), rx.recv())
+            .await
+            .expect("timed out waiting for a frame")
+            .expect("session closed");
+        let frame: serde_json::Value = serde_json::from_str(&text).unwrap();
+        if frame["type"] == kind {
+            return frame;
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TestUser {
+    pub id: Uuid,
+    pub token: String,
+}
+
+impl TestUser {
+    pub fn bearer(&self) -> (actix_web::http::header::HeaderName, String) {
+        (AUTHORIZATION, format!("Bearer {}", self.token))
+    }
+}
+
+/// Inserts a user with `PASSWORD`, `<username>@example.com` and a valid token.
+pub async fn create_user(pool: &PgPool, username: &str) -> TestUser {
+    init();
+    let email = format!("{}@example.com", username);
+    let password_hash = bcrypt::hash(PASSWORD, 4).unwrap();
+
+    let id = sqlx::query_scalar::<_, Uuid>(
+        r#"
+        INSERT INTO users (username, email, password_hash)
+        VALUES ($1, $2, $3)
+        RETURNING id
+        "#,
+    )
+    .bind(username)
+    .bind(&email)
+    .bind(&password_hash)
+    .fetch_one(pool)
+    .await
+    .unwrap();
+
+    TestUser {
+        id,
+        token: create_jwt(id, username, 0).unwrap(),
+    }
+}
+
+/// Inserts a private channel with `owner` as its admin.
+pub async fn create_channel(pool: &PgPool, owner: &TestUser, name: &str) -> Uuid {
+    let channel_id = sqlx::query_scalar::<_, Uuid>(
+        r#"
+        INSERT INTO channels (name, created_by)
+        VALUES ($1, $2)
+        RETURNING id
+        "#,
+    )
+    .bind(name)
+    .bind(owner.id)
+    .fetch_one(pool)
+    .await
+    .unwrap();
+
+    add_member(pool, channel_id, owner, Role::Admin).await;
+    channel_id
+}
+
+pub async fn add_member(pool: &PgPool, channel_id: Uuid, user: &TestUser, role: Role) {
+    let mut tx = pool.begin().await.unwrap();
+    db::membership::add_member(&mut tx, channel_id, user.id, role)
+        .await
+        .unwrap();
+    tx.commit().await.unwrap();
+}
+
+/// Inserts a plain user message, bypassing the send checks.
+pub async fn insert_message(
+    pool: &PgPool,
+    channel_id: Uuid,
+    user: &TestUser,
+    content: &str,
+) -> Uuid {
+    sqlx::query_scalar::<_, Uuid>(
+        r#"
+        INSERT INTO messages (channel_id, user_id, content)
+        VALUES ($1, $2, $3)
+        RETURNING id
+        "#,
+    )
+    .bind(channel_id)
+    .bind(user.id)
+    .bind(content)
+    .fetch_one(pool)
+    .await
+    .unwrap()
+}
@@ -0,0 +1,229 @@
+use std::fmt;
+
+use actix_web::{dev::Payload, web, FromRequest, HttpRequest};
+use futures_util::future::LocalBoxFuture;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{middleware::auth::AuthUser, models::role::Role};
+
+/// The authenticated user's membership in the channel named by the `{id}` path segment.
+///
+/// Extracting it rejects non-members with 403, so handlers only need to check the role.
+#[derive(Debug, Clone)]
+pub struct ChannelMember {
+    pub user_id: Uuid,
+    pub channel_id: Uuid,
+    pub role: Role,
+}
+
+impl ChannelMember {
+    pub fn is_admin(&self) -> bool {
+        self.role.is_admin()
+    }
+}
+
+/// Returns the user's role in the channel, or `None` if they aren't a member.
+pub async fn member_role(
+    pool: &PgPool,
+    channel_id: Uuid,
+    user_id: Uuid,
+) -> Result<Option<Role>, sqlx::Error> {
+    sqlx::query_scalar::<_, Role>(
+        r#"
+        SELECT role FROM channel_members
+        WHERE channel_id = $1 AND user_id = $2
+        "#,
+    )
+    .bind(channel_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await
+}
+
+impl FromRequest for ChannelMember {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let req = req.clone();
+
+        Box::pin(async move {
+            let user = AuthUser::extract(&req).await?;
+            let user_id = user.id;
+
+            let channel_id = req
+                .match_info()
+                .get("id")
+                .and_then(|id| Uuid::parse_str(id).ok())
+                .ok_or_else(|| actix_web::error::ErrorNotFound("Channel not found"))?;
+
+            let pool = req
+                .app_data::<web::Data<PgPool>>()
+                .ok_or_else(|| actix_web::error::ErrorInternalServerError("Database error"))?;
+
+            let role = member_role(pool.get_ref(), channel_id, user_id)
+                .await
+                .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?
+                .ok_or_else(|| actix_web::error::ErrorForbidden("Not a member of this channel"))?;
+
+            Ok(Self {
+                user_id,
+                channel_id,
+                role,
+            })
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum MessageAccessError {
+    NotMember,
+    MessageNotFound,
+    Database(sqlx::Error),
+}
+
+impl fmt::Display for MessageAccessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotMember => write!(f, "Not a member of this channel"),
+            Self::MessageNotFound => write!(f, "Message not found"),
+            Self::Database(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl From<MessageAccessError> for actix_web::Error {
+    fn from(err: MessageAccessError) -> Self {
+        match err {
+            MessageAccessError::NotMember => actix_web::error::ErrorForbidden(err.to_string()),
+            MessageAccessError::MessageNotFound => actix_web::error::ErrorNotFound(err.to_string()),
+            MessageAccessError::Database(_) => {
+                actix_web::error::ErrorInternalServerError("Database error")
+            }
+        }
+    }
+}
+
+/// Checks that the user belongs to the channel and that the message lives in it.
+///
+/// A message from another channel is reported as not found so ids can't be probed.
+pub async fn authorize_message_access(
+    pool: &PgPool,
+    user_id: Uuid,
+    channel_id: Uuid,
+    message_id: Uuid,
+) -> Result<(), MessageAccessError> {
+    let (is_member, in_channel) = sqlx::query_as::<_, (bool, bool)>(
+        r#"
+        SELECT
+            EXISTS(
+                SELECT 1 FROM channel_members
+                WHERE channel_id = $1 AND user_id = $2
+            ),
+            EXISTS(
+                SELECT 1 FROM messages
+                WHERE id = $3 AND channel_id = $1 AND deleted_at IS NULL
+            )
+        "#,
+    )
+    .bind(channel_id)
+    .bind(user_id)
+    .bind(message_id)
+    .fetch_one(pool)
+    .await
+    .map_err(MessageAccessError::Database)?;
+
+    if !is_member {
+        return Err(MessageAccessError::NotMember);
+    }
+
+    if !in_channel {
+        return Err(MessageAccessError::MessageNotFound);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_support::{add_member, create_channel, create_user, insert_message, TestUser},
+        utils::jwt::decode_jwt,
+    };
+    use actix_web::{http::StatusCode, test, HttpMessage};
+
+    /// Extracts `ChannelMember` as a route behind the auth middleware would.
+    async fn extract_member(
+        pool: &PgPool,
+        user: &TestUser,
+        channel_id: Uuid,
+    ) -> Result<ChannelMember, actix_web::Error> {
+        let req = test::TestRequest::default()
+            .param("id", channel_id.to_string())
+            .app_data(web::Data::new(pool.clone()))
+            .to_http_request();
+        req.extensions_mut()
+            .insert(decode_jwt(&user.token).unwrap());
+        ChannelMember::extract(&req).await
+    }
+
+    #[sqlx::test]
+    async fn member_extractor_surfaces_the_role(pool: PgPool) {
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+        add_member(&pool, channel_id, &bob, Role::Viewer).await;
+
+        let member = extract_member(&pool, &alice, channel_id).await.unwrap();
+        assert_eq!((member.user_id, member.channel_id), (alice.id, channel_id));
+        assert!(member.is_admin());
+
+        let member = extract_member(&pool, &bob, channel_id).await.unwrap();
+        assert_eq!(member.role, Role::Viewer);
+        assert!(!member.is_admin());
+    }
+
+    #[sqlx::test]
+    async fn member_extractor_rejects_non_members(pool: PgPool) {
+        let alice = create_user(&pool, "alice").await;
+        let mallory = create_user(&pool, "mallory").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+
+        for channel_id in [channel_id, Uuid::new_v4()] {
+            let err = extract_member(&pool, &mallory, channel_id)
+                .await
+                .unwrap_err();
+            assert_eq!(err.as_response_error().status_code(), StatusCode::FORBIDDEN);
+        }
+    }
+
+    #[sqlx::test]
+    async fn message_access_needs_membership_and_the_right_channel(pool: PgPool) {
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+        let general = create_channel(&pool, &alice, "general").await;
+        let random = create_channel(&pool, &alice, "random").await;
+        let message_id = insert_message(&pool, general, &alice, "hi").await;
+
+        assert!(
+            authorize_message_access(&pool, alice.id, general, message_id)
+                .await
+                .is_ok()
+        );
+        assert!(matches!(
+            authorize_message_access(&pool, bob.id, general, message_id).await,
+            Err(MessageAccessError::NotMember)
+        ));
+        // a member of the other channel can't reach the message through it
+        assert!(matches!(
+            authorize_message_access(&pool, alice.id, random, message_id).await,
+            Err(MessageAccessError::MessageNotFound)
+        ));
+        assert!(matches!(
+            authorize_message_access(&pool, alice.id, general, Uuid::new_v4()).await,
+            Err(MessageAccessError::MessageNotFound)
+        ));
+    }
+}
@@ -1,20 +1,190 @@
 use chrono::{Duration, Utc};
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use jsonwebtoken::{
+    decode, encode, errors::ErrorKind, Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
 use serde::{Deserialize, Serialize};
+use std::{env, fmt, fs, sync::OnceLock};
 use uuid::Uuid;
 
+use crate::config::is_development;
+
+const DEFAULT_SECRET: &str = "secret";
+
+static JWT_KEYS: OnceLock<JwtKeys> = OnceLock::new();
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String, // user id
     pub username: String,
     pub exp: usize,
+    /// The user's `token_version` when issued; tokens from before this claim count as 0.
+    #[serde(default)]
+    pub ver: i32,
+}
+
+#[derive(Debug)]
+pub enum JwtConfigError {
+    UnsupportedAlgorithm(String),
+    MissingKey(&'static str),
+    UnreadableKey(String, std::io::Error),
+    InvalidKey(jsonwebtoken::errors::Error),
+    InsecureSecret,
+}
+
+impl fmt::Display for JwtConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedAlgorithm(alg) => {
+                write!(f, "Unsupported JWT_ALG '{}', expected HS256 or RS256", alg)
+            }
+            Self::MissingKey(var) => write!(f, "{} or {}_FILE must be set for RS256", var, var),
+            Self::UnreadableKey(path, e) => write!(f, "Failed to read key file {}: {}", path, e),
+            Self::InvalidKey(e) => write!(f, "Invalid JWT key: {}", e),
+            Self::InsecureSecret => write!(
+                f,
+                "JWT_SECRET must be set to a non-default value unless APP_ENV is dev or test"
+            ),
+        }
+    }
+}
+
+/// Why a token couldn't be issued or accepted; the `Display` text is safe to send to clients.
+#[derive(Debug)]
+pub enum JwtError {
+    Expired,
+    InvalidSignature,
+    /// Not a well-formed JWT, or claims that don't match what we issue.
+    Malformed,
+    /// Any other validation failure, e.g. a token signed with a different algorithm.
+    Invalid(jsonwebtoken::errors::Error),
+    Encode(jsonwebtoken::errors::Error),
+}
+
+impl fmt::Display for JwtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Expired => write!(f, "Token expired"),
+            Self::InvalidSignature => write!(f, "Invalid token signature"),
+            Self::Malformed => write!(f, "Malformed token"),
+            Self::Invalid(_) => write!(f, "Invalid token"),
+            Self::Encode(_) => write!(f, "Failed to create token"),
+        }
+    }
+}
+
+impl std::error::Error for JwtError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Invalid(e) | Self::Encode(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl JwtError {
+    fn from_decode(e: jsonwebtoken::errors::Error) -> Self {
+        match e.kind() {
+            ErrorKind::ExpiredSignature => Self::Expired,
+            ErrorKind::InvalidSignature => Self::InvalidSignature,
+            ErrorKind::InvalidToken
+            | ErrorKind::Base64(_)
+            | ErrorKind::Json(_)
+            | ErrorKind::Utf8(_) => Self::Malformed,
+            _ => Self::Invalid(e),
+        }
+    }
+}
+
+pub struct JwtKeys {
+    algorithm: Algorithm,
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+}
+
+impl JwtKeys {
+    pub fn hs256(secret: &[u8]) -> Self {
+        Self {
+            algorithm: Algorithm::HS256,
+            encoding: EncodingKey::from_secret(secret),
+            decoding: DecodingKey::from_secret(secret),
+        }
+    }
+
+    pub fn rs256(private_pem: &[u8], public_pem: &[u8]) -> Result<Self, JwtConfigError> {
+        Ok(Self {
+            algorithm: Algorithm::RS256,
+            encoding: EncodingKey::from_rsa_pem(private_pem).map_err(JwtConfigError::InvalidKey)?,
+            decoding: DecodingKey::from_rsa_pem(public_pem).map_err(JwtConfigError::InvalidKey)?,
+        })
+    }
+
+    fn encode(&self, claims: &Claims) -> Result<String, JwtError> {
+        encode(&Header::new(self.algorithm), claims, &self.encoding).map_err(JwtError::Encode)
+    }
+
+    fn decode(&self, token: &str) -> Result<Claims, JwtError> {
+        decode::<Claims>(token, &self.decoding, &Validation::new(self.algorithm))
+            .map(|token_data| token_data.claims)
+            .map_err(JwtError::from_decode)
+    }
+
+    /// Reads `JWT_ALG` (`HS256` default or `RS256`) and the matching key material.
+    ///
+    /// HS256 uses `JWT_SECRET`; RS256 takes PEM keys from `JWT_PRIVATE_KEY`/`JWT_PUBLIC_KEY`
+    /// or the files named by `JWT_PRIVATE_KEY_FILE`/`JWT_PUBLIC_KEY_FILE`.
+    pub fn from_env() -> Result<Self, JwtConfigError> {
+        let alg = env::var("JWT_ALG").unwrap_or_else(|_| "HS256".to_string());
+
+        match alg.to_ascii_uppercase().as_str() {
+            "HS256" => {
+                let secret = jwt_secret()?;
+                Ok(Self::hs256(secret.as_bytes()))
+            }
+            "RS256" => {
+                let private_pem = read_pem("JWT_PRIVATE_KEY")?;
+                let public_pem = read_pem("JWT_PUBLIC_KEY")?;
+                Self::rs256(&private_pem, &public_pem)
+            }
+            _ => Err(JwtConfigError::UnsupportedAlgorithm(alg)),
+        }
+    }
+}
+
+/// The HS256 secret. Outside development an explicit, non-default `JWT_SECRET` is required.
+fn jwt_secret() -> Result<String, JwtConfigError> {
+    resolve_secret(env::var("JWT_SECRET").ok(), is_development())
 }
 
-pub fn create_jwt(
-    user_id: Uuid,
-    username: &str,
-    secret: &str,
-) -> Result<String, jsonwebtoken::errors::Error> {
+fn resolve_secret(configured: Option<String>, development: bool) -> Result<String, JwtConfigError> {
+    match configured {
+        Some(secret) if !secret.is_empty() && secret != DEFAULT_SECRET => Ok(secret),
+        _ if development => Ok(DEFAULT_SECRET.to_string()),
+        _ => Err(JwtConfigError::InsecureSecret),
+    }
+}
+
+fn read_pem(var: &'static str) -> Result<Vec<u8>, JwtConfigError> {
+    if let Ok(pem) = env::var(var) {
+        return Ok(pem.into_bytes());
+    }
+
+    let path = env::var(format!("{}_FILE", var)).map_err(|_| JwtConfigError::MissingKey(var))?;
+    fs::read(&path).map_err(|e| JwtConfigError::UnreadableKey(path, e))
+}
+
+/// Loads the signing keys once at startup so a bad configuration fails fast.
+pub fn init_jwt_keys() -> Result<(), JwtConfigError> {
+    if JWT_KEYS.get().is_none() {
+        let _ = JWT_KEYS.set(JwtKeys::from_env()?);
+    }
+    Ok(())
+}
+
+fn jwt_keys() -> &'static JwtKeys {
+    JWT_KEYS.get_or_init(|| JwtKeys::from_env().expect("Invalid JWT configuration!"))
+}
+
+pub fn create_jwt(user_id: Uuid, username: &str, token_version: i32) -> Result<String, JwtError> {
     let expiration = Utc::now()
         .checked_add_signed(Duration::hours(24))
         .expect("valid timestamp")
@@ -24,21 +194,119 @@ pub fn create_jwt(
         sub: user_id.to_string(),
         username: username.to_string(),
         exp: expiration as usize,
+        ver: token_version,
     };
 
-    encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(secret.as_ref()),
-    )
+    jwt_keys().encode(&claims)
+}
+
+pub fn decode_jwt(token: &str) -> Result<Claims, JwtError> {
+    jwt_keys().decode(token)
 }
 
-pub fn decode_jwt(token: &str, secret: String) -> Result<Claims, jsonwebtoken::errors::Error> {
-    let token_data = decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(secret.as_ref()),
-        &Validation::default(),
-    )?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRIVATE_PEM: &[u8] = include_bytes!("../../tests/fixtures/jwt_rs256_private.pem");
+    const PUBLIC_PEM: &[u8] = include_bytes!("../../tests/fixtures/jwt_rs256_public.pem");
+
+    fn claims(hours: i64) -> Claims {
+        Claims {
+            sub: Uuid::new_v4().to_string(),
+            username: "alice".to_string(),
+            exp: (Utc::now() + Duration::hours(hours)).timestamp() as usize,
+            ver: 3,
+        }
+    }
+
+    #[test]
+    fn hs256_signs_and_verifies() {
+        let keys = JwtKeys::hs256(b"a test secret");
+        let claims = claims(1);
+        let decoded = keys.decode(&keys.encode(&claims).unwrap()).unwrap();
+        assert_eq!(
+            (decoded.sub, decoded.username, decoded.ver),
+            (claims.sub, claims.username, 3)
+        );
+    }
+
+    #[test]
+    fn rs256_signs_and_verifies() {
+        let keys = JwtKeys::rs256(PRIVATE_PEM, PUBLIC_PEM).unwrap();
+        let claims = claims(1);
+        let token = keys.encode(&claims).unwrap();
+        assert_eq!(keys.decode(&token).unwrap().sub, claims.sub);
+
+        // a token from one algorithm is never accepted under the other
+        let hs256 = JwtKeys::hs256(b"a test secret");
+        assert!(hs256.decode(&token).is_err());
+        assert!(keys.decode(&hs256.encode(&claims).unwrap()).is_err());
+    }
+
+    #[test]
+    fn decode_failures_are_told_apart() {
+        let keys = JwtKeys::hs256(b"a test secret");
+
+        let expired = keys.encode(&claims(-2)).unwrap();
+        assert!(matches!(keys.decode(&expired), Err(JwtError::Expired)));
+
+        let forged = JwtKeys::hs256(b"another secret")
+            .encode(&claims(1))
+            .unwrap();
+        assert!(matches!(
+            keys.decode(&forged),
+            Err(JwtError::InvalidSignature)
+        ));
+
+        for garbage in ["", "not-a-jwt", "a.b.c"] {
+            assert!(matches!(keys.decode(garbage), Err(JwtError::Malformed)));
+        }
+
+        let rs256 = JwtKeys::rs256(PRIVATE_PEM, PUBLIC_PEM).unwrap();
+        let other_alg = rs256.encode(&claims(1)).unwrap();
+        assert!(matches!(keys.decode(&other_alg), Err(JwtError::Invalid(_))));
+
+        assert_eq!(JwtError::Expired.to_string(), "Token expired");
+        assert_eq!(JwtError::Malformed.to_string(), "Malformed token");
+    }
+
+    #[test]
+    fn signing_with_an_unusable_key_is_an_encode_error() {
+        let keys = JwtKeys {
+            algorithm: Algorithm::RS256,
+            encoding: EncodingKey::from_secret(b"not an rsa key"),
+            decoding: DecodingKey::from_secret(b"not an rsa key"),
+        };
+        assert!(matches!(keys.encode(&claims(1)), Err(JwtError::Encode(_))));
+    }
+
+    #[test]
+    fn rs256_rejects_a_key_that_is_not_pem() {
+        assert!(matches!(
+            JwtKeys::rs256(b"not a key", PUBLIC_PEM),
+            Err(JwtConfigError::InvalidKey(_))
+        ));
+    }
+
+    #[test]
+    fn production_refuses_a_missing_or_default_secret() {
+        for configured in [None, Some(""), Some(DEFAULT_SECRET)] {
+            assert!(matches!(
+                resolve_secret(configured.map(String::from), false),
+                Err(JwtConfigError::InsecureSecret)
+            ));
+            assert_eq!(
+                resolve_secret(configured.map(String::from), true).unwrap(),
+                DEFAULT_SECRET
+            );
+        }
 
-    Ok(token_data.claims)
+        for development in [false, true] {
+            assert_eq!(
+                resolve_secret(Some("s3cret".to_string()), development).unwrap(),
+                "s3cret"
+            );
+        }
+    }
 }
@@ -1 +1,8 @@
+pub mod access;
 pub mod jwt;
+pub mod password;
+pub mod sanitize;
+pub mod task_tracker;
+pub mod totp;
+pub mod validation;
+pub mod word_filter;
@@ -0,0 +1,230 @@
+use argon2::{
+    password_hash::{
+        self, rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString,
+    },
+    Argon2, Params,
+};
+use bcrypt::{hash, verify, BcryptError, DEFAULT_COST};
+use std::{env, fmt, sync::OnceLock};
+
+const MIN_COST: u32 = 4;
+const MAX_COST: u32 = 31;
+
+/// The algorithm new password hashes are created with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PasswordScheme {
+    #[default]
+    Bcrypt,
+    /// Argon2id with the crate's default parameters.
+    Argon2,
+}
+
+impl PasswordScheme {
+    /// Tells the scheme apart from the stored hash's PHC prefix, e.g. `$argon2id$` or `$2b$`.
+    pub fn of_hash(password_hash: &str) -> Self {
+        if password_hash.starts_with("$argon2") {
+            PasswordScheme::Argon2
+        } else {
+            PasswordScheme::Bcrypt
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PasswordError {
+    Bcrypt(BcryptError),
+    Argon2(password_hash::Error),
+}
+
+impl fmt::Display for PasswordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bcrypt(e) => write!(f, "bcrypt: {}", e),
+            Self::Argon2(e) => write!(f, "argon2: {}", e),
+        }
+    }
+}
+
+impl From<BcryptError> for PasswordError {
+    fn from(e: BcryptError) -> Self {
+        Self::Bcrypt(e)
+    }
+}
+
+impl From<password_hash::Error> for PasswordError {
+    fn from(e: password_hash::Error) -> Self {
+        Self::Argon2(e)
+    }
+}
+
+/// `bcrypt` (default) or `argon2`; anything else is an error.
+pub fn parse_password_scheme(value: Option<&str>) -> Result<PasswordScheme, String> {
+    match value.map(str::trim).filter(|value| !value.is_empty()) {
+        None | Some("bcrypt") => Ok(PasswordScheme::Bcrypt),
+        Some("argon2") => Ok(PasswordScheme::Argon2),
+        Some(other) => Err(format!(
+            "Unknown PASSWORD_HASH '{}', expected bcrypt or argon2",
+            other
+        )),
+    }
+}
+
+/// Reads `PASSWORD_HASH` once, falling back to bcrypt when it's invalid.
+pub fn password_scheme() -> PasswordScheme {
+    static SCHEME: OnceLock<PasswordScheme> = OnceLock::new();
+    *SCHEME.get_or_init(|| {
+        parse_password_scheme(env::var("PASSWORD_HASH").ok().as_deref()).unwrap_or_else(|e| {
+            log::warn!("{}, using bcrypt", e);
+            PasswordScheme::Bcrypt
+        })
+    })
+}
+
+/// Reads `BCRYPT_COST`, falling back to bcrypt's default when unset or out of range.
+pub fn bcrypt_cost() -> u32 {
+    parse_cost(env::var("BCRYPT_COST").ok().as_deref())
+}
+
+fn parse_cost(value: Option<&str>) -> u32 {
+    match value.map(str::trim).map(str::parse::<u32>) {
+        Some(Ok(cost)) if (MIN_COST..=MAX_COST).contains(&cost) => cost,
+        Some(_) => {
+            log::warn!("Invalid BCRYPT_COST, using default of {}", DEFAULT_COST);
+            DEFAULT_COST
+        }
+        None => DEFAULT_COST,
+    }
+}
+
+/// Hashes with the configured `PASSWORD_HASH` scheme.
+pub fn hash_password(password: &str) -> Result<String, PasswordError> {
+    hash_with(password, password_scheme(), bcrypt_cost())
+}
+
+fn hash_with(password: &str, scheme: PasswordScheme, cost: u32) -> Result<String, PasswordError> {
+    match scheme {
+        PasswordScheme::Bcrypt => Ok(hash(password, cost)?),
+        PasswordScheme::Argon2 => {
+            let salt = SaltString::generate(&mut OsRng);
+            let hashed = Argon2::default().hash_password(password.as_bytes(), &salt)?;
+            Ok(hashed.to_string())
+        }
+    }
+}
+
+/// Verifies against whichever scheme produced `password_hash`, so existing bcrypt hashes
+/// keep working after switching to argon2 and vice versa.
+pub fn verify_password(password: &str, password_hash: &str) -> Result<bool, PasswordError> {
+    match PasswordScheme::of_hash(password_hash) {
+        PasswordScheme::Bcrypt => Ok(verify(password, password_hash)?),
+        PasswordScheme::Argon2 => {
+            let parsed = PasswordHash::new(password_hash)?;
+            match Argon2::default().verify_password(password.as_bytes(), &parsed) {
+                Ok(()) => Ok(true),
+                Err(password_hash::Error::Password) => Ok(false),
+                Err(e) => Err(e.into()),
+            }
+        }
+    }
+}
+
+/// Compares only the cost parameters; a parsed hash also carries its output length,
+/// which the defaults leave unset, so comparing whole `Params` would never match.
+fn is_default_cost(params: &Params) -> bool {
+    let default = Params::default();
+    params.m_cost() == default.m_cost()
+        && params.t_cost() == default.t_cost()
+        && params.p_cost() == default.p_cost()
+}
+
+/// Extracts the cost from a `$2b$12$...` style hash.
+fn hash_cost(password_hash: &str) -> Option<u32> {
+    password_hash.split('$').nth(2)?.parse().ok()
+}
+
+/// Whether a stored hash was produced with another scheme than the configured one, or with
+/// other parameters: a different bcrypt cost or non-default argon2 settings.
+pub fn needs_rehash(password_hash: &str) -> bool {
+    rehash_needed(password_hash, password_scheme(), bcrypt_cost())
+}
+
+fn rehash_needed(password_hash: &str, scheme: PasswordScheme, cost: u32) -> bool {
+    if PasswordScheme::of_hash(password_hash) != scheme {
+        return true;
+    }
+
+    match scheme {
+        PasswordScheme::Bcrypt => hash_cost(password_hash) != Some(cost),
+        PasswordScheme::Argon2 => PasswordHash::new(password_hash)
+            .ok()
+            .and_then(|parsed| Params::try_from(&parsed).ok())
+            .is_none_or(|params| !is_default_cost(&params)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the lowest cost bcrypt allows, to keep the tests fast
+    const TEST_COST: u32 = MIN_COST;
+
+    #[test]
+    fn password_scheme_parsing() {
+        assert_eq!(parse_password_scheme(None), Ok(PasswordScheme::Bcrypt));
+        assert_eq!(parse_password_scheme(Some("")), Ok(PasswordScheme::Bcrypt));
+        assert_eq!(
+            parse_password_scheme(Some(" argon2 ")),
+            Ok(PasswordScheme::Argon2)
+        );
+        assert!(parse_password_scheme(Some("md5")).is_err());
+    }
+
+    #[test]
+    fn bcrypt_cost_parsing() {
+        assert_eq!(parse_cost(None), DEFAULT_COST);
+        assert_eq!(parse_cost(Some("10")), 10);
+        assert_eq!(parse_cost(Some("3")), DEFAULT_COST);
+        assert_eq!(parse_cost(Some("32")), DEFAULT_COST);
+        assert_eq!(parse_cost(Some("high")), DEFAULT_COST);
+    }
+
+    #[test]
+    fn both_schemes_verify() {
+        for scheme in [PasswordScheme::Bcrypt, PasswordScheme::Argon2] {
+            let hashed = hash_with("correct horse", scheme, TEST_COST).unwrap();
+
+            assert_eq!(PasswordScheme::of_hash(&hashed), scheme);
+            assert!(verify_password("correct horse", &hashed).unwrap());
+            assert!(!verify_password("wrong horse", &hashed).unwrap());
+        }
+    }
+
+    #[test]
+    fn rehash_when_scheme_or_cost_changes() {
+        let bcrypt = hash_with("pw", PasswordScheme::Bcrypt, TEST_COST).unwrap();
+        let argon2 = hash_with("pw", PasswordScheme::Argon2, TEST_COST).unwrap();
+
+        assert!(!rehash_needed(&bcrypt, PasswordScheme::Bcrypt, TEST_COST));
+        assert!(rehash_needed(
+            &bcrypt,
+            PasswordScheme::Bcrypt,
+            TEST_COST + 1
+        ));
+        assert!(rehash_needed(&bcrypt, PasswordScheme::Argon2, TEST_COST));
+
+        assert!(!rehash_needed(&argon2, PasswordScheme::Argon2, TEST_COST));
+        assert!(rehash_needed(&argon2, PasswordScheme::Bcrypt, TEST_COST));
+    }
+
+    #[test]
+    fn rehash_argon2_with_non_default_params() {
+        let params = Params::new(Params::MIN_M_COST * 2, 1, 1, None).unwrap();
+        let weak = Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params)
+            .hash_password(b"pw", &SaltString::generate(&mut OsRng))
+            .unwrap()
+            .to_string();
+
+        assert!(rehash_needed(&weak, PasswordScheme::Argon2, TEST_COST));
+    }
+}
@@ -0,0 +1,146 @@
+use std::{env, sync::OnceLock};
+
+/// How message content is made safe for clients that render it as HTML.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SanitizeMode {
+    /// Stored as sent, for clients that only ever render plain text.
+    #[default]
+    Raw,
+    /// `<b>hi</b>` becomes `&lt;b&gt;hi&lt;/b&gt;`.
+    Escape,
+    /// `<b>hi</b>` becomes `hi`; anything left that could open a tag is escaped.
+    Strip,
+}
+
+impl SanitizeMode {
+    pub fn apply(&self, content: &str) -> String {
+        match self {
+            SanitizeMode::Raw => content.to_string(),
+            SanitizeMode::Escape => escape_html(content),
+            // stripping alone isn't enough, `<<b>script>` would leave `<script>` behind
+            SanitizeMode::Strip => escape_html(&strip_tags(content)),
+        }
+    }
+}
+
+/// `raw` (default), `escape` or `strip`; anything else is an error.
+pub fn parse_sanitize_mode(value: Option<&str>) -> Result<SanitizeMode, String> {
+    match value.map(str::trim).filter(|value| !value.is_empty()) {
+        None | Some("raw") => Ok(SanitizeMode::Raw),
+        Some("escape") => Ok(SanitizeMode::Escape),
+        Some("strip") => Ok(SanitizeMode::Strip),
+        Some(other) => Err(format!(
+            "Unknown MESSAGE_SANITIZE '{}', expected raw, escape or strip",
+            other
+        )),
+    }
+}
+
+/// Reads `MESSAGE_SANITIZE` once; an invalid value falls back to `escape` rather than `raw`.
+pub fn sanitize_mode() -> SanitizeMode {
+    static MODE: OnceLock<SanitizeMode> = OnceLock::new();
+    *MODE.get_or_init(|| {
+        parse_sanitize_mode(env::var("MESSAGE_SANITIZE").ok().as_deref()).unwrap_or_else(|e| {
+            log::warn!("{}, escaping HTML", e);
+            SanitizeMode::Escape
+        })
+    })
+}
+
+/// Applies the configured mode to message content before it is stored.
+pub fn sanitize_content(content: &str) -> String {
+    sanitize_mode().apply(content)
+}
+
+fn escape_html(content: &str) -> String {
+    let mut output = String::with_capacity(content.len());
+    for c in content.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '"' => output.push_str("&quot;"),
+            '\'' => output.push_str("&#x27;"),
+            c => output.push(c),
+        }
+    }
+    output
+}
+
+/// Drops anything that looks like a tag, comment or doctype: `<` followed by a letter,
+/// `/`, `!` or `?`, up to the next `>`. A lone `<`, as in `a < b`, is kept.
+fn strip_tags(content: &str) -> String {
+    let mut output = String::with_capacity(content.len());
+    let mut chars = content.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let opens_tag = c == '<'
+            && chars
+                .peek()
+                .is_some_and(|next| next.is_ascii_alphabetic() || matches!(next, '/' | '!' | '?'));
+
+        if opens_tag {
+            // an unterminated tag swallows the rest, like a browser would
+            for c in chars.by_ref() {
+                if c == '>' {
+                    break;
+                }
+            }
+        } else {
+            output.push(c);
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_neutralises_script_tags() {
+        assert_eq!(
+            SanitizeMode::Escape.apply("<script>alert('x')</script>"),
+            "&lt;script&gt;alert(&#x27;x&#x27;)&lt;/script&gt;"
+        );
+        assert_eq!(escape_html("a & \"b\""), "a &amp; &quot;b&quot;");
+    }
+
+    #[test]
+    fn strip_removes_tags_and_keeps_text() {
+        assert_eq!(strip_tags("<b>hi</b> there"), "hi there");
+        assert_eq!(strip_tags("<!-- note -->text"), "text");
+        assert_eq!(strip_tags("a < b and c > d"), "a < b and c > d");
+        assert_eq!(strip_tags("before <img src=x"), "before ");
+    }
+
+    #[test]
+    fn strip_mode_never_leaves_a_script_tag_behind() {
+        assert_eq!(
+            SanitizeMode::Strip.apply("<script>alert(1)</script>"),
+            "alert(1)"
+        );
+        let nested = SanitizeMode::Strip.apply("<<b>script>alert(1)<</b>/script>");
+        assert!(!nested.contains("<script"));
+        assert!(!nested.contains("</script"));
+    }
+
+    #[test]
+    fn raw_mode_stores_content_as_sent() {
+        assert_eq!(SanitizeMode::Raw.apply("<b>hi</b>"), "<b>hi</b>");
+    }
+
+    #[test]
+    fn sanitize_mode_parsing() {
+        assert_eq!(parse_sanitize_mode(None), Ok(SanitizeMode::Raw));
+        assert_eq!(
+            parse_sanitize_mode(Some(" strip ")),
+            Ok(SanitizeMode::Strip)
+        );
+        assert_eq!(
+            parse_sanitize_mode(Some("escape")),
+            Ok(SanitizeMode::Escape)
+        );
+        assert!(parse_sanitize_mode(Some("html")).is_err());
+    }
+}
@@ -0,0 +1,148 @@
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::{runtime::Handle, sync::Notify};
+
+#[derive(Debug, Default)]
+struct State {
+    running: AtomicUsize,
+    idle: Notify,
+}
+
+/// Counts in-flight background tasks so shutdown can wait for them.
+///
+/// Tasks run on the runtime the tracker was created on rather than the caller's. Spawned
+/// from an HTTP worker they would otherwise die with it, since actix stops its workers
+/// before `HttpServer::run` returns.
+#[derive(Debug, Clone)]
+pub struct TaskTracker {
+    runtime: Handle,
+    state: Arc<State>,
+}
+
+/// Decrements the count when the task finishes, panics or is cancelled.
+struct Running(Arc<State>);
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        if self.0.running.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.0.idle.notify_waiters();
+        }
+    }
+}
+
+impl TaskTracker {
+    /// Must be called inside a Tokio runtime, which then runs every tracked task.
+    pub fn on_current_runtime() -> Self {
+        Self {
+            runtime: Handle::current(),
+            state: Arc::default(),
+        }
+    }
+
+    pub fn spawn<F>(&self, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.state.running.fetch_add(1, Ordering::AcqRel);
+        let running = Running(self.state.clone());
+        self.runtime.spawn(async move {
+            let _running = running;
+            task.await;
+        });
+    }
+
+    pub fn running(&self) -> usize {
+        self.state.running.load(Ordering::Acquire)
+    }
+
+    /// Waits up to `timeout` for every tracked task to finish, returning how many are
+    /// still running.
+    pub async fn drain(&self, timeout: Duration) -> usize {
+        let all_done = async {
+            loop {
+                // created before the check so a task finishing in between still wakes us
+                let idle = self.state.idle.notified();
+                if self.running() == 0 {
+                    return;
+                }
+                idle.await;
+            }
+        };
+
+        let _ = tokio::time::timeout(timeout, all_done).await;
+        self.running()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        handlers::websocket::insert_message_with_retry,
+        models::message::MessageFormat,
+        test_support::{create_channel, create_user},
+    };
+    use sqlx::PgPool;
+    use std::sync::atomic::AtomicBool;
+
+    #[tokio::test]
+    async fn drain_waits_for_running_tasks() {
+        let tracker = TaskTracker::on_current_runtime();
+        let done = Arc::new(AtomicBool::new(false));
+        let flag = done.clone();
+        tracker.spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            flag.store(true, Ordering::Release);
+        });
+        // a panicking task still counts as finished
+        tracker.spawn(async { panic!("task failed") });
+
+        assert_eq!(tracker.drain(Duration::from_secs(5)).await, 0);
+        assert!(done.load(Ordering::Acquire));
+    }
+
+    #[tokio::test]
+    async fn drain_gives_up_after_the_timeout() {
+        let tracker = TaskTracker::on_current_runtime();
+        tracker.spawn(tokio::time::sleep(Duration::from_secs(60)));
+
+        assert_eq!(tracker.drain(Duration::from_millis(20)).await, 1);
+    }
+
+    #[sqlx::test]
+    async fn a_pending_insert_is_stored_during_the_drain(pool: PgPool) {
+        let alice = create_user(&pool, "alice").await;
+        let channel_id = create_channel(&pool, &alice, "general").await;
+        let tracker = TaskTracker::on_current_runtime();
+
+        let task_pool = pool.clone();
+        tracker.spawn(async move {
+            // still in flight when shutdown starts
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            let _ = insert_message_with_retry(
+                &task_pool,
+                channel_id,
+                alice.id,
+                "sent just before shutdown",
+                &[],
+                None,
+                MessageFormat::Plain,
+            )
+            .await;
+        });
+        assert_eq!(tracker.running(), 1);
+
+        assert_eq!(tracker.drain(Duration::from_secs(5)).await, 0);
+        let stored: String = sqlx::query_scalar("SELECT content FROM messages")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(stored, "sent just before shutdown");
+    }
+}
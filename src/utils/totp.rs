@@ -0,0 +1,117 @@
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Nonce,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use std::{env, fmt};
+use totp_rs::{Algorithm, Secret, TOTP};
+
+const ISSUER: &str = "live-chat";
+const NONCE_LENGTH: usize = 12;
+
+#[derive(Debug)]
+pub enum TotpError {
+    NotConfigured,
+    InvalidSecret,
+    Crypto,
+}
+
+impl fmt::Display for TotpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotConfigured => write!(f, "TOTP_ENCRYPTION_KEY is missing or invalid"),
+            Self::InvalidSecret => write!(f, "Invalid TOTP secret"),
+            Self::Crypto => write!(f, "Failed to encrypt or decrypt TOTP secret"),
+        }
+    }
+}
+
+/// Reads the base64-encoded 32-byte `TOTP_ENCRYPTION_KEY`.
+fn encryption_key() -> Result<Aes256Gcm, TotpError> {
+    let key = env::var("TOTP_ENCRYPTION_KEY").map_err(|_| TotpError::NotConfigured)?;
+    let bytes = STANDARD
+        .decode(key.trim())
+        .map_err(|_| TotpError::NotConfigured)?;
+
+    Aes256Gcm::new_from_slice(&bytes).map_err(|_| TotpError::NotConfigured)
+}
+
+pub fn generate_secret() -> Result<Vec<u8>, TotpError> {
+    Secret::generate_secret()
+        .to_bytes()
+        .map_err(|_| TotpError::InvalidSecret)
+}
+
+/// Encrypts a raw secret for storage as base64 of `nonce || ciphertext`.
+pub fn encrypt_secret(secret: &[u8]) -> Result<String, TotpError> {
+    let cipher = encryption_key()?;
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, secret)
+        .map_err(|_| TotpError::Crypto)?;
+
+    let mut stored = nonce.to_vec();
+    stored.extend(ciphertext);
+    Ok(STANDARD.encode(stored))
+}
+
+pub fn decrypt_secret(stored: &str) -> Result<Vec<u8>, TotpError> {
+    let cipher = encryption_key()?;
+    let bytes = STANDARD.decode(stored).map_err(|_| TotpError::Crypto)?;
+
+    if bytes.len() <= NONCE_LENGTH {
+        return Err(TotpError::Crypto);
+    }
+
+    let (nonce, ciphertext) = bytes.split_at(NONCE_LENGTH);
+    let nonce: [u8; NONCE_LENGTH] = nonce.try_into().map_err(|_| TotpError::Crypto)?;
+    cipher
+        .decrypt(&Nonce::from(nonce), ciphertext)
+        .map_err(|_| TotpError::Crypto)
+}
+
+pub fn build_totp(secret: Vec<u8>, account_name: &str) -> Result<TOTP, TotpError> {
+    TOTP::new(
+        Algorithm::SHA1,
+        6,
+        1,
+        30,
+        secret,
+        Some(ISSUER.to_string()),
+        account_name.to_string(),
+    )
+    .map_err(|_| TotpError::InvalidSecret)
+}
+
+pub fn verify_code(totp: &TOTP, code: &str) -> bool {
+    totp.check_current(code.trim()).unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::init;
+
+    #[test]
+    fn stored_secret_round_trips_and_resists_tampering() {
+        init();
+        let secret = generate_secret().unwrap();
+        let stored = encrypt_secret(&secret).unwrap();
+        assert_ne!(stored, encrypt_secret(&secret).unwrap(), "nonce must vary");
+        assert_eq!(decrypt_secret(&stored).unwrap(), secret);
+
+        let mut bytes = STANDARD.decode(&stored).unwrap();
+        *bytes.last_mut().unwrap() ^= 1;
+        assert!(decrypt_secret(&STANDARD.encode(bytes)).is_err());
+        assert!(decrypt_secret("c2hvcnQ=").is_err());
+    }
+
+    #[test]
+    fn only_the_current_code_verifies() {
+        let totp = build_totp(generate_secret().unwrap(), "alice@example.com").unwrap();
+        let code = totp.generate_current().unwrap();
+        assert!(verify_code(&totp, &format!(" {} ", code)));
+        assert!(!verify_code(&totp, "not a code"));
+        assert!(totp.get_url().starts_with("otpauth://totp/live-chat:"));
+    }
+}
@@ -0,0 +1,297 @@
+use crate::models::Attachment;
+use std::fmt;
+
+pub const MAX_URL_LENGTH: usize = 2048;
+pub const MAX_ATTACHMENTS: usize = 10;
+pub const MAX_ATTACHMENT_SIZE: i64 = 25 * 1024 * 1024;
+pub const MIN_USERNAME_LENGTH: usize = 3;
+/// Matches the `users.username` column, `VARCHAR(50)`.
+pub const MAX_USERNAME_LENGTH: usize = 50;
+/// Matches the `users.email` column, `VARCHAR(255)`.
+pub const MAX_EMAIL_LENGTH: usize = 255;
+/// In bytes; bcrypt ignores everything past the 72nd byte, so longer passwords would
+/// silently match on their prefix alone.
+pub const MAX_PASSWORD_LENGTH: usize = 72;
+/// Prefix given to anonymized accounts, so nobody can pick it.
+const RESERVED_USERNAME_PREFIX: &str = "deleted-";
+/// Hard cap for channel names, matching the `channels.name` column.
+pub const MAX_CHANNEL_NAME_LENGTH: usize = 100;
+/// In characters, checked before sanitizing so escaping can't push a message over.
+pub const MAX_MESSAGE_LENGTH: usize = 4000;
+
+const ALLOWED_MIME_TYPES: &[&str] = &[
+    "image/png",
+    "image/jpeg",
+    "image/gif",
+    "image/webp",
+    "application/pdf",
+    "text/plain",
+    "video/mp4",
+    "audio/mpeg",
+];
+
+pub fn validate_http_url(url: &str) -> Result<(), &'static str> {
+    if url.len() > MAX_URL_LENGTH {
+        return Err("URL is too long");
+    }
+
+    let host = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))
+        .ok_or("URL must be http(s)")?;
+
+    if host.is_empty() || host.starts_with('/') || url.chars().any(char::is_whitespace) {
+        return Err("Invalid URL");
+    }
+
+    Ok(())
+}
+
+/// Usernames are 3-50 ASCII letters, digits, `_`, `-` or `.`.
+pub fn validate_username(username: &str) -> Result<(), String> {
+    if username.len() < MIN_USERNAME_LENGTH || username.len() > MAX_USERNAME_LENGTH {
+        return Err(format!(
+            "Username must be between {} and {} characters",
+            MIN_USERNAME_LENGTH, MAX_USERNAME_LENGTH
+        ));
+    }
+
+    if !username
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+    {
+        return Err("Username may only contain letters, digits, '_', '-' and '.'".to_string());
+    }
+
+    if username.starts_with(RESERVED_USERNAME_PREFIX) {
+        return Err("Username is reserved".to_string());
+    }
+
+    Ok(())
+}
+
+/// Only checks the length and that there is something on both sides of an `@`; whether
+/// the address works is up to email verification.
+pub fn validate_email(email: &str) -> Result<(), String> {
+    if email.chars().count() > MAX_EMAIL_LENGTH {
+        return Err(format!(
+            "Email must be at most {} characters",
+            MAX_EMAIL_LENGTH
+        ));
+    }
+
+    match email.rsplit_once('@') {
+        Some((local, domain)) if !local.is_empty() && !domain.is_empty() => Ok(()),
+        _ => Err("Invalid email address".to_string()),
+    }
+}
+
+pub fn validate_password(password: &str) -> Result<(), String> {
+    if password.is_empty() {
+        return Err("Password is required".to_string());
+    }
+
+    if password.len() > MAX_PASSWORD_LENGTH {
+        return Err(format!(
+            "Password must be at most {} bytes",
+            MAX_PASSWORD_LENGTH
+        ));
+    }
+
+    Ok(())
+}
+
+/// Escapes `\`, `%` and `_` so user input matches literally inside a `LIKE` pattern.
+pub fn escape_like(term: &str) -> String {
+    term.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// Strips control characters and surrounding whitespace, then checks the name isn't empty
+/// or longer than `max_length` characters.
+pub fn sanitize_channel_name(name: &str, max_length: usize) -> Result<String, String> {
+    let name: String = name.chars().filter(|c| !c.is_control()).collect();
+    let name = name.trim();
+
+    if name.is_empty() {
+        return Err("Channel name is required".to_string());
+    }
+
+    if name.chars().count() > max_length {
+        return Err(format!(
+            "Channel name must be at most {} characters",
+            max_length
+        ));
+    }
+
+    Ok(name.to_string())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentError {
+    /// Only whitespace, and no attachments to carry the message instead.
+    Empty,
+    TooLong,
+}
+
+impl fmt::Display for ContentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "Message content is required"),
+            Self::TooLong => write!(
+                f,
+                "Message must be at most {} characters",
+                MAX_MESSAGE_LENGTH
+            ),
+        }
+    }
+}
+
+/// Shared by every path that posts a message; a message may be attachments alone.
+pub fn validate_message_content(content: &str, has_attachments: bool) -> Result<(), ContentError> {
+    if content.trim().is_empty() && !has_attachments {
+        return Err(ContentError::Empty);
+    }
+
+    if content.chars().count() > MAX_MESSAGE_LENGTH {
+        return Err(ContentError::TooLong);
+    }
+
+    Ok(())
+}
+
+pub fn validate_attachments(attachments: &[Attachment]) -> Result<(), &'static str> {
+    if attachments.len() > MAX_ATTACHMENTS {
+        return Err("Too many attachments");
+    }
+
+    for attachment in attachments {
+        validate_http_url(&attachment.url)?;
+
+        if !ALLOWED_MIME_TYPES.contains(&attachment.mime_type.as_str()) {
+            return Err("Attachment type not allowed");
+        }
+
+        if attachment.size <= 0 || attachment.size > MAX_ATTACHMENT_SIZE {
+            return Err("Attachment size out of range");
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channel_name_must_have_visible_characters() {
+        for name in ["", "   ", "\u{0}\u{7}", " \t\n "] {
+            assert_eq!(
+                sanitize_channel_name(name, 10),
+                Err("Channel name is required".to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn channel_name_length_boundaries() {
+        assert_eq!(sanitize_channel_name("abcde", 5), Ok("abcde".to_string()));
+        assert!(sanitize_channel_name("abcdef", 5).is_err());
+        // counted in characters, and after trimming
+        assert_eq!(sanitize_channel_name(" ééééé ", 5), Ok("ééééé".to_string()));
+    }
+
+    #[test]
+    fn control_characters_are_stripped_from_channel_names() {
+        assert_eq!(
+            sanitize_channel_name("gen\u{0}er\u{1b}al\n", 10),
+            Ok("general".to_string())
+        );
+    }
+
+    #[test]
+    fn email_length_boundaries() {
+        let domain = "@example.com";
+        let longest = format!("{}{}", "a".repeat(MAX_EMAIL_LENGTH - domain.len()), domain);
+        assert_eq!(validate_email(&longest), Ok(()));
+
+        let too_long = format!("a{}", longest);
+        assert!(validate_email(&too_long).is_err());
+    }
+
+    #[test]
+    fn email_needs_both_sides_of_the_at() {
+        assert_eq!(validate_email("alice@example.com"), Ok(()));
+        assert!(validate_email("alice").is_err());
+        assert!(validate_email("@example.com").is_err());
+        assert!(validate_email("alice@").is_err());
+    }
+
+    #[test]
+    fn password_length_is_counted_in_bytes() {
+        assert!(validate_password("").is_err());
+        assert_eq!(validate_password(&"a".repeat(MAX_PASSWORD_LENGTH)), Ok(()));
+        assert!(validate_password(&"a".repeat(MAX_PASSWORD_LENGTH + 1)).is_err());
+
+        // 37 two-byte characters are 74 bytes, past what bcrypt reads
+        assert!(validate_password(&"é".repeat(37)).is_err());
+    }
+
+    #[test]
+    fn username_length_boundaries() {
+        assert!(validate_username(&"a".repeat(MIN_USERNAME_LENGTH - 1)).is_err());
+        assert_eq!(validate_username(&"a".repeat(MIN_USERNAME_LENGTH)), Ok(()));
+        assert_eq!(validate_username(&"a".repeat(MAX_USERNAME_LENGTH)), Ok(()));
+        assert!(validate_username(&"a".repeat(MAX_USERNAME_LENGTH + 1)).is_err());
+        assert!(validate_username("deleted-alice").is_err());
+        assert!(validate_username("alice bob").is_err());
+    }
+
+    #[test]
+    fn message_content_length_boundaries() {
+        let longest = "a".repeat(MAX_MESSAGE_LENGTH);
+        assert_eq!(validate_message_content(&longest, false), Ok(()));
+
+        let too_long = "a".repeat(MAX_MESSAGE_LENGTH + 1);
+        assert_eq!(
+            validate_message_content(&too_long, false),
+            Err(ContentError::TooLong)
+        );
+
+        // counted in characters, not bytes
+        let wide = "é".repeat(MAX_MESSAGE_LENGTH);
+        assert_eq!(validate_message_content(&wide, false), Ok(()));
+    }
+
+    #[test]
+    fn empty_message_needs_an_attachment() {
+        assert_eq!(
+            validate_message_content("   ", false),
+            Err(ContentError::Empty)
+        );
+        assert_eq!(validate_message_content("", true), Ok(()));
+    }
+
+    #[test]
+    fn attachments_are_checked_against_the_allowlist_and_caps() {
+        let attachment = |mime_type: &str, size: i64| Attachment {
+            url: "https://cdn.example.com/file".to_string(),
+            mime_type: mime_type.to_string(),
+            size,
+        };
+
+        assert_eq!(validate_attachments(&[]), Ok(()));
+        assert_eq!(
+            validate_attachments(&[attachment("image/png", 1024)]),
+            Ok(())
+        );
+        assert!(validate_attachments(&[attachment("application/x-msdownload", 1024)]).is_err());
+        assert!(validate_attachments(&[attachment("image/png", 0)]).is_err());
+        assert!(validate_attachments(&[attachment("image/png", MAX_ATTACHMENT_SIZE + 1)]).is_err());
+
+        let too_many = vec![attachment("image/png", 1024); MAX_ATTACHMENTS + 1];
+        assert!(validate_attachments(&too_many).is_err());
+    }
+}
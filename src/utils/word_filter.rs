@@ -0,0 +1,133 @@
+use std::{env, sync::OnceLock};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterMode {
+    Reject,
+    Mask,
+}
+
+#[derive(Debug)]
+pub struct WordFilter {
+    words: Vec<Vec<char>>,
+    mode: FilterMode,
+}
+
+#[derive(Debug)]
+pub struct BlockedContent;
+
+impl WordFilter {
+    pub fn new(words: &[&str], mode: FilterMode) -> Self {
+        let words = words
+            .iter()
+            .map(|word| word.trim())
+            .filter(|word| !word.is_empty())
+            .map(|word| word.chars().flat_map(char::to_lowercase).collect())
+            .collect();
+
+        Self { words, mode }
+    }
+
+    /// Reads `BLOCKED_WORDS` (comma-separated) and `WORD_FILTER_MODE` (`mask` or `reject`).
+    pub fn from_env() -> Self {
+        let words = env::var("BLOCKED_WORDS").unwrap_or_default();
+        let mode = match env::var("WORD_FILTER_MODE").as_deref() {
+            Ok("reject") => FilterMode::Reject,
+            _ => FilterMode::Mask,
+        };
+
+        Self::new(&words.split(',').collect::<Vec<_>>(), mode)
+    }
+
+    /// Returns the content to persist, masked if needed, or `BlockedContent` in reject mode.
+    pub fn apply(&self, content: &str) -> Result<String, BlockedContent> {
+        if self.words.is_empty() {
+            return Ok(content.to_string());
+        }
+
+        let chars: Vec<char> = content.chars().collect();
+        let mut output = String::with_capacity(content.len());
+        let mut i = 0;
+
+        while i < chars.len() {
+            let matched = self
+                .words
+                .iter()
+                .filter_map(|word| match_len(&chars[i..], word))
+                .max();
+
+            match matched {
+                Some(_) if self.mode == FilterMode::Reject => return Err(BlockedContent),
+                Some(len) => {
+                    output.extend(std::iter::repeat_n('*', len));
+                    i += len;
+                }
+                None => {
+                    output.push(chars[i]);
+                    i += 1;
+                }
+            }
+        }
+
+        Ok(output)
+    }
+}
+
+/// Number of original chars matching `word` case-insensitively at the start of `chars`.
+fn match_len(chars: &[char], word: &[char]) -> Option<usize> {
+    let mut lowered = Vec::with_capacity(word.len());
+
+    for (consumed, &c) in chars.iter().enumerate() {
+        lowered.extend(c.to_lowercase());
+        if lowered.len() >= word.len() {
+            return (lowered == word).then_some(consumed + 1);
+        }
+        if !word.starts_with(&lowered) {
+            return None;
+        }
+    }
+
+    None
+}
+
+pub fn word_filter() -> &'static WordFilter {
+    static FILTER: OnceLock<WordFilter> = OnceLock::new();
+    FILTER.get_or_init(WordFilter::from_env)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mask_mode_stars_out_matches_whatever_their_case() {
+        let filter = WordFilter::new(&["darn", " heck "], FilterMode::Mask);
+        assert_eq!(
+            filter.apply("Oh DARN it, heck").unwrap(),
+            "Oh **** it, ****"
+        );
+        assert_eq!(filter.apply("all fine").unwrap(), "all fine");
+    }
+
+    #[test]
+    fn reject_mode_refuses_any_match() {
+        let filter = WordFilter::new(&["darn"], FilterMode::Reject);
+        assert!(filter.apply("oh Darn").is_err());
+        assert_eq!(filter.apply("oh dear").unwrap(), "oh dear");
+    }
+
+    #[test]
+    fn matching_is_unicode_aware() {
+        let filter = WordFilter::new(&["ÉCOLE"], FilterMode::Mask);
+        assert_eq!(filter.apply("à l'école!").unwrap(), "à l'*****!");
+
+        // one char that lowercases to two must not be split
+        let filter = WordFilter::new(&["i̇x"], FilterMode::Mask);
+        assert_eq!(filter.apply("İx and İ").unwrap(), "** and İ");
+    }
+
+    #[test]
+    fn empty_list_lets_everything_through() {
+        let filter = WordFilter::new(&["", " "], FilterMode::Reject);
+        assert_eq!(filter.apply("anything").unwrap(), "anything");
+    }
+}